@@ -16,6 +16,9 @@ BLOCKS = {
     'leaves': 9,
     'wood_top': 10,
     'wood': 11,
+    'water': 12,
+    'ladder': 13,
+    'vines': 14,
 }
 N = len(BLOCKS)
 
@@ -1,9 +1,17 @@
-use std::{env, fs::File, path::Path};
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
 
-use bevy::prelude::{debug, error, KeyCode, MouseButton};
-use kurinji::{EventPhase, Kurinji};
+use bevy::prelude::{debug, error, GamepadButtonType, KeyCode, MouseButton};
+use kurinji::{EventPhase, GamepadAxis, Kurinji};
 use serde::{Deserialize, Serialize};
 
+use crate::save::{self, SaveError};
+
 #[derive(Debug, Default, Deserialize, Serialize, Clone)]
 #[serde(default)]
 pub struct CobbleConfig {
@@ -11,6 +19,10 @@ pub struct CobbleConfig {
     pub debug: DebugConfig,
     pub game: GameConfig,
     pub input: InputConfig,
+    /// The config file could not be parsed and the defaults are used instead, storing them would
+    /// overwrite the user's file
+    #[serde(skip)]
+    pub load_failed: bool,
 }
 
 impl CobbleConfig {
@@ -42,15 +54,76 @@ pub struct VideoConfig {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(default)]
 pub struct GameConfig {
+    /// Step onto one block high ledges without jumping
+    pub auto_step: bool,
     pub creative: bool,
     pub breakable_bedrock: bool,
+    pub generator: GeneratorConfig,
+    /// Recipe file read instead of the bundled recipes
+    pub recipes: Option<String>,
+    /// Directory worlds are saved in, nothing is saved if unset
+    pub saves: Option<String>,
+    /// Height of the world in blocks, rounded up to whole chunk sections
+    pub world_height: usize,
+}
+
+impl GameConfig {
+    /// World height rounded up to whole chunk sections, at least one section
+    pub fn world_height(&self) -> usize {
+        let section = crate::world::defaults::SECTION_SIZE;
+        ((self.world_height + section - 1) / section).max(1) * section
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum GeneratorConfig {
+    Basic,
+    /// Noise graph read from a YAML file, which is reloaded on modification if `watch` is set
+    Graph {
+        path: String,
+        #[serde(default)]
+        watch: bool,
+    },
+    /// Terrain height sampled from a grayscale image
+    Heightmap(HeightmapConfig),
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct HeightmapConfig {
+    /// Grayscale image of the terrain height
+    pub path: String,
+    /// Optional image of the surface blocks, see `world::BLOCK_COLORS`
+    pub blocks: Option<String>,
+    /// World units (blocks) per pixel
+    pub scale: f64,
+    /// Map the full value range of the image to the world height instead of one block per value
+    pub scale_to_height: bool,
+    pub out_of_bounds: OutOfBounds,
+    pub trees: bool,
+    /// Air below this height is filled with water
+    pub water_level: Option<usize>,
+}
+
+/// Terrain height outside of the heightmap
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+pub enum OutOfBounds {
+    /// Fill with a constant height
+    Height(f64),
+    /// Extend the edge pixels of the image
+    Edge,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(default)]
 pub struct InputConfig {
     pub bindings: kurinji::Bindings,
+    /// Actions begun by scrolling the mouse wheel up and down, which kurinji cannot bind
+    pub wheel_up: Option<String>,
+    pub wheel_down: Option<String>,
     pub sensitivity: f32,
+    /// Sensitivity of looking around with the gamepad's stick
+    pub gamepad_sensitivity: f32,
     pub initial_cursor_grab: bool,
 }
 
@@ -98,15 +171,35 @@ impl Default for DebugConfig {
 impl Default for GameConfig {
     fn default() -> Self {
         Self {
+            auto_step: false,
             creative: true,
             breakable_bedrock: false,
+            generator: GeneratorConfig::Basic,
+            recipes: None,
+            saves: Some("saves".to_owned()),
+            world_height: crate::world::defaults::WORLD_HEIGHT,
+        }
+    }
+}
+
+impl Default for HeightmapConfig {
+    fn default() -> Self {
+        Self {
+            path: "heightmap.png".to_owned(),
+            blocks: None,
+            scale: 1.0,
+            scale_to_height: true,
+            out_of_bounds: OutOfBounds::Height(1.0),
+            trees: true,
+            water_level: None,
         }
     }
 }
 
 impl Default for InputConfig {
     fn default() -> Self {
-        let bindings = Kurinji::default()
+        let mut kurinji = Kurinji::default();
+        kurinji
             .bind_keyboard_pressed(KeyCode::Key1, "SLOT_1")
             .bind_keyboard_pressed(KeyCode::Key2, "SLOT_2")
             .bind_keyboard_pressed(KeyCode::Key3, "SLOT_3")
@@ -116,8 +209,14 @@ impl Default for InputConfig {
             .bind_keyboard_pressed(KeyCode::Key7, "SLOT_7")
             .bind_keyboard_pressed(KeyCode::Key8, "SLOT_8")
             .bind_keyboard_pressed(KeyCode::Key9, "SLOT_9")
+            .bind_gamepad_button_pressed(GamepadButtonType::LeftTrigger, "SLOT_PREVIOUS")
+            .bind_gamepad_button_pressed(GamepadButtonType::RightTrigger, "SLOT_NEXT")
             .bind_keyboard_pressed(KeyCode::Escape, "PAUSE")
             .set_event_phase("PAUSE", EventPhase::OnBegin)
+            .bind_keyboard_pressed(KeyCode::E, "INVENTORY")
+            .set_event_phase("INVENTORY", EventPhase::OnBegin)
+            .bind_keyboard_pressed(KeyCode::F1, "CONTROLS")
+            .set_event_phase("CONTROLS", EventPhase::OnBegin)
             .bind_keyboard_pressed(KeyCode::Tab, "FLY_TOGGLE")
             .bind_keyboard_pressed(KeyCode::W, "MOVE_FORWARD")
             .bind_keyboard_pressed(KeyCode::S, "MOVE_BACKWARD")
@@ -131,31 +230,259 @@ impl Default for InputConfig {
             .set_event_phase("BREAK", EventPhase::OnBegin)
             .bind_mouse_button_pressed(MouseButton::Right, "PLACE")
             .set_event_phase("PLACE", EventPhase::OnBegin)
-            .get_bindings();
+            .bind_gamepad_axis(GamepadAxis::LeftStickYPositive, "MOVE_FORWARD")
+            .bind_gamepad_axis(GamepadAxis::LeftStickYNegative, "MOVE_BACKWARD")
+            .bind_gamepad_axis(GamepadAxis::LeftStickXNegative, "MOVE_LEFT")
+            .bind_gamepad_axis(GamepadAxis::LeftStickXPositive, "MOVE_RIGHT")
+            .bind_gamepad_axis(GamepadAxis::RightStickXNegative, "LOOK_LEFT")
+            .bind_gamepad_axis(GamepadAxis::RightStickXPositive, "LOOK_RIGHT")
+            .bind_gamepad_axis(GamepadAxis::RightStickYPositive, "LOOK_UP")
+            .bind_gamepad_axis(GamepadAxis::RightStickYNegative, "LOOK_DOWN")
+            .bind_gamepad_button_pressed(GamepadButtonType::South, "MOVE_JUMP")
+            .bind_gamepad_button_pressed(GamepadButtonType::East, "MOVE_MOD_SLOW_DESC")
+            .bind_gamepad_button_pressed(GamepadButtonType::LeftThumb, "MOVE_MOD_FAST")
+            .bind_gamepad_button_pressed(GamepadButtonType::West, "PICK_BLOCK")
+            .bind_gamepad_button_pressed(GamepadButtonType::North, "INVENTORY")
+            .bind_gamepad_button_pressed(GamepadButtonType::Select, "FLY_TOGGLE")
+            .bind_gamepad_button_pressed(GamepadButtonType::Start, "PAUSE")
+            .bind_gamepad_button_pressed(GamepadButtonType::RightTrigger2, "BREAK")
+            .bind_gamepad_button_pressed(GamepadButtonType::LeftTrigger2, "PLACE");
+        // Sticks hardly ever rest exactly in their center
+        for action in [
+            "MOVE_FORWARD",
+            "MOVE_BACKWARD",
+            "MOVE_LEFT",
+            "MOVE_RIGHT",
+            "LOOK_LEFT",
+            "LOOK_RIGHT",
+            "LOOK_UP",
+            "LOOK_DOWN",
+        ]
+        .iter()
+        {
+            kurinji.set_dead_zone(action, 0.15);
+        }
+        let bindings = kurinji.get_bindings();
         Self {
             bindings,
+            wheel_up: Some("SLOT_PREVIOUS".to_owned()),
+            wheel_down: Some("SLOT_NEXT".to_owned()),
             sensitivity: 1.0,
+            gamepad_sensitivity: 1.0,
             initial_cursor_grab: cfg!(not(target_arch = "wasm")),
         }
     }
 }
 
-/// Try loading the config by trying the local file first and then the global in
-/// XDG_CONFIG_HOME
-fn open_config() -> Option<File> {
-    let local_path = Path::new("./cobble.yaml");
-    if let Ok(f) = File::open(local_path) {
-        debug!("Local config file found");
-        return Some(f);
+/// Actions the game reacts to and the names they are listed with on the controls screen
+pub const ACTIONS: [(&str, &str); 29] = [
+    ("MOVE_FORWARD", "Move forward"),
+    ("MOVE_BACKWARD", "Move backward"),
+    ("MOVE_LEFT", "Move left"),
+    ("MOVE_RIGHT", "Move right"),
+    ("MOVE_JUMP", "Jump/Ascend"),
+    ("MOVE_MOD_SLOW_DESC", "Sneak/Descend"),
+    ("MOVE_MOD_FAST", "Sprint"),
+    ("LOOK_LEFT", "Look left"),
+    ("LOOK_RIGHT", "Look right"),
+    ("LOOK_UP", "Look up"),
+    ("LOOK_DOWN", "Look down"),
+    ("BREAK", "Break block"),
+    ("PLACE", "Place block"),
+    ("PICK_BLOCK", "Pick block"),
+    ("FLY_TOGGLE", "Toggle fly"),
+    ("INVENTORY", "Inventory"),
+    ("CONTROLS", "Controls"),
+    ("PAUSE", "Pause"),
+    ("SLOT_NEXT", "Next slot"),
+    ("SLOT_PREVIOUS", "Previous slot"),
+    ("SLOT_1", "Slot 1"),
+    ("SLOT_2", "Slot 2"),
+    ("SLOT_3", "Slot 3"),
+    ("SLOT_4", "Slot 4"),
+    ("SLOT_5", "Slot 5"),
+    ("SLOT_6", "Slot 6"),
+    ("SLOT_7", "Slot 7"),
+    ("SLOT_8", "Slot 8"),
+    ("SLOT_9", "Slot 9"),
+];
+
+/// Name of `action` as it is shown to the player
+pub fn action_name(action: &str) -> &str {
+    ACTIONS
+        .iter()
+        .find(|(id, _)| *id == action)
+        .map_or(action, |(_, name)| name)
+}
+
+/// Key or button that is bound to an action by pressing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Button of the first gamepad
+    Gamepad(GamepadButtonType),
+}
+
+impl Button {
+    pub fn name(&self) -> String {
+        match self {
+            Button::Key(key) => format!("{:?}", key).trim_start_matches("Key").to_owned(),
+            Button::Mouse(MouseButton::Other(button)) => format!("Mouse {}", button),
+            Button::Mouse(button) => format!("{:?} Mouse", button),
+            Button::Gamepad(button) => format!("Pad {:?}", button),
+        }
+    }
+
+    /// Whether the button belongs to the gamepad rather than to the keyboard and mouse
+    fn is_gamepad(&self) -> bool {
+        matches!(self, Button::Gamepad(_))
+    }
+}
+
+/// Bindings in the form they are written to the config file. Unlike `kurinji::Bindings`, which
+/// keeps them to itself, they can be looked up and changed
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct BindingMap {
+    #[serde(default, rename = "KeyboardKeys")]
+    keys: HashMap<KeyCode, String>,
+    #[serde(default, rename = "MouseButtons")]
+    mouse: HashMap<MouseButton, String>,
+    #[serde(default, rename = "GamepadButtons")]
+    gamepad: HashMap<usize, HashMap<GamepadButtonType, String>>,
+    #[serde(default, rename = "GamepadAxis")]
+    axes: HashMap<usize, HashMap<GamepadAxis, String>>,
+    /// Mouse motion, dead zones and event phases, which are kept as they are
+    #[serde(flatten)]
+    other: HashMap<String, serde_yaml::Value>,
+}
+
+impl BindingMap {
+    pub fn from_bindings(bindings: &kurinji::Bindings) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_value(serde_yaml::to_value(bindings)?)
+    }
+
+    pub fn to_bindings(&self) -> Result<kurinji::Bindings, serde_yaml::Error> {
+        serde_yaml::from_value(serde_yaml::to_value(self)?)
+    }
+
+    /// Action `button` is bound to
+    pub fn action(&self, button: Button) -> Option<&str> {
+        match button {
+            Button::Key(key) => self.keys.get(&key),
+            Button::Mouse(button) => self.mouse.get(&button),
+            Button::Gamepad(button) => self.gamepad.get(&0).and_then(|pad| pad.get(&button)),
+        }
+        .map(String::as_str)
+    }
+
+    /// Buttons bound to `action`, keys and mouse buttons first
+    pub fn buttons(&self, action: &str) -> Vec<Button> {
+        let keys = self.keys.iter().map(|(key, a)| (Button::Key(*key), a));
+        let mouse = self
+            .mouse
+            .iter()
+            .map(|(button, a)| (Button::Mouse(*button), a));
+        let gamepad = self
+            .gamepad
+            .get(&0)
+            .into_iter()
+            .flatten()
+            .map(|(button, a)| (Button::Gamepad(*button), a));
+        let mut buttons: Vec<Button> = keys
+            .chain(mouse)
+            .chain(gamepad)
+            .filter(|(_, a)| *a == action)
+            .map(|(button, _)| button)
+            .collect();
+        // Hash maps are unordered, the names keep the list stable
+        buttons.sort_by_key(|button| (button.is_gamepad(), button.name()));
+        buttons
+    }
+
+    /// Names of the gamepad axes bound to `action`
+    pub fn axes(&self, action: &str) -> Vec<String> {
+        let mut axes: Vec<String> = self
+            .axes
+            .get(&0)
+            .into_iter()
+            .flatten()
+            .filter(|(_, a)| *a == action)
+            .map(|(axis, _)| format!("Pad {:?}", axis))
+            .collect();
+        axes.sort();
+        axes
+    }
+
+    /// Bind `button` to `action` in place of the action's other buttons of the same device, the
+    /// keyboard and mouse counting as one. Returns the action the button was bound to before
+    pub fn rebind(&mut self, action: &str, button: Button) -> Option<String> {
+        let previous = self
+            .action(button)
+            .filter(|previous| *previous != action)
+            .map(str::to_owned);
+        for old in self.buttons(action) {
+            if old.is_gamepad() == button.is_gamepad() {
+                self.unbind(old);
+            }
+        }
+        let action = action.to_owned();
+        match button {
+            Button::Key(key) => self.keys.insert(key, action),
+            Button::Mouse(button) => self.mouse.insert(button, action),
+            Button::Gamepad(button) => self.gamepad.entry(0).or_default().insert(button, action),
+        };
+        previous
+    }
+
+    fn unbind(&mut self, button: Button) {
+        match button {
+            Button::Key(key) => self.keys.remove(&key),
+            Button::Mouse(button) => self.mouse.remove(&button),
+            Button::Gamepad(button) => self.gamepad.get_mut(&0).and_then(|pad| pad.remove(&button)),
+        };
+    }
+
+    /// Actions that are bound but that the game does not know about
+    pub fn unknown_actions(&self) -> Vec<&str> {
+        let gamepad = self.gamepad.values().flat_map(|pad| pad.values());
+        let axes = self.axes.values().flat_map(|pad| pad.values());
+        let mut unknown: Vec<&str> = self
+            .keys
+            .values()
+            .chain(self.mouse.values())
+            .chain(gamepad)
+            .chain(axes)
+            .map(String::as_str)
+            .filter(|action| ACTIONS.iter().all(|(id, _)| id != action))
+            .collect();
+        unknown.sort_unstable();
+        unknown.dedup();
+        unknown
     }
-    let config_path = Path::new(
+}
+
+const LOCAL_CONFIG: &str = "./cobble.yaml";
+
+/// Path of the global config in XDG_CONFIG_HOME
+fn global_config_path() -> PathBuf {
+    Path::new(
         &(match env::var("XDG_CONFIG_HOME") {
             Ok(f) => f,
             Err(_) => "~/.config/".to_owned(),
         }),
     )
-    .with_file_name("cobble.yaml");
-    File::open(config_path).ok()
+    .with_file_name("cobble.yaml")
+}
+
+/// Try loading the config by trying the local file first and then the global in
+/// XDG_CONFIG_HOME
+fn open_config() -> Option<File> {
+    if let Ok(f) = File::open(LOCAL_CONFIG) {
+        debug!("Local config file found");
+        return Some(f);
+    }
+    File::open(global_config_path()).ok()
 }
 
 pub fn load() -> CobbleConfig {
@@ -165,8 +492,101 @@ pub fn load() -> CobbleConfig {
             Ok(data) => data,
             Err(e) => {
                 error!("Failed to parse config file: {}", e);
-                CobbleConfig::default()
+                CobbleConfig {
+                    load_failed: true,
+                    ..Default::default()
+                }
             }
         },
     )
 }
+
+/// Write `config` to the file it is loaded from, which is the local file unless there only is a
+/// global one. A file that failed to load is kept as it is
+pub fn store(config: &CobbleConfig) -> Result<(), SaveError> {
+    if config.load_failed {
+        return Err(SaveError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the config file could not be parsed and is not overwritten",
+        )));
+    }
+    let global = global_config_path();
+    let path = if !Path::new(LOCAL_CONFIG).exists() && global.exists() {
+        global
+    } else {
+        PathBuf::from(LOCAL_CONFIG)
+    };
+    save::write_yaml(&path, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebind_actions() {
+        let bindings = InputConfig::default().bindings;
+        let mut map = BindingMap::from_bindings(&bindings).unwrap();
+        assert!(map.unknown_actions().is_empty());
+        assert_eq!(map.action(Button::Key(KeyCode::W)), Some("MOVE_FORWARD"));
+        assert_eq!(
+            map.buttons("BREAK"),
+            vec![
+                Button::Mouse(MouseButton::Left),
+                Button::Gamepad(GamepadButtonType::RightTrigger2)
+            ]
+        );
+        assert_eq!(map.axes("MOVE_FORWARD"), vec!["Pad LeftStickYPositive"]);
+
+        // Rebinding replaces the buttons of the same device and takes the button from its action
+        assert_eq!(map.rebind("BREAK", Button::Key(KeyCode::Q)), None);
+        assert_eq!(
+            map.buttons("BREAK"),
+            vec![
+                Button::Key(KeyCode::Q),
+                Button::Gamepad(GamepadButtonType::RightTrigger2)
+            ]
+        );
+        assert_eq!(map.action(Button::Mouse(MouseButton::Left)), None);
+        assert_eq!(
+            map.rebind("BREAK", Button::Key(KeyCode::E)),
+            Some("INVENTORY".to_owned())
+        );
+        assert!(map.buttons("INVENTORY").iter().all(Button::is_gamepad));
+        assert_eq!(map.rebind("BREAK", Button::Key(KeyCode::E)), None);
+        assert_eq!(
+            map.rebind("PLACE", Button::Gamepad(GamepadButtonType::South)),
+            Some("MOVE_JUMP".to_owned())
+        );
+        assert_eq!(
+            map.buttons("PLACE"),
+            vec![
+                Button::Mouse(MouseButton::Right),
+                Button::Gamepad(GamepadButtonType::South)
+            ]
+        );
+
+        // Everything else survives the round trip through kurinji's bindings
+        let rebound = BindingMap::from_bindings(&map.to_bindings().unwrap()).unwrap();
+        assert_eq!(rebound, map);
+        assert_eq!(
+            rebound.other,
+            BindingMap::from_bindings(&bindings).unwrap().other
+        );
+
+        map.rebind("DANCE", Button::Key(KeyCode::X));
+        assert_eq!(map.unknown_actions(), vec!["DANCE"]);
+        assert_eq!(action_name("SLOT_NEXT"), "Next slot");
+        assert_eq!(action_name("DANCE"), "DANCE");
+        assert_eq!(Button::Key(KeyCode::Key4).name(), "4");
+    }
+
+    #[test]
+    fn broken_config_is_not_overwritten() {
+        let config = CobbleConfig {
+            load_failed: true,
+            ..Default::default()
+        };
+        assert!(matches!(store(&config), Err(SaveError::Io(_))));
+    }
+}
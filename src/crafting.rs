@@ -0,0 +1,439 @@
+//! Recipes that turn the items in the crafting grid into other items
+use std::{collections::HashMap, fmt, fs::File, path::Path};
+
+use bevy::prelude::error;
+use serde::{Deserialize, Serialize};
+
+use crate::item::Item;
+
+/// Width and height of the crafting grid
+pub const GRID_SIZE: usize = 3;
+pub const GRID_SLOTS: usize = GRID_SIZE * GRID_SIZE;
+
+/// Recipes shipped with the game, used unless the config names another recipe file
+const BUNDLED_RECIPES: &str = include_str!("../assets/crafting/recipes.yaml");
+
+/// A recipe as it is written in a recipe file, which holds a list of them
+///
+/// # Example
+/// ```yaml
+/// - type: Shaped
+///   pattern:
+///     - "P P"
+///     - "PPP"
+///   key: { P: Planks }
+///   output: { item: LadderNorth, count: 3 }
+/// - type: Shapeless
+///   ingredients: [Wood]
+///   output: { item: Planks, count: 4 }
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum RecipeDef {
+    /// Items in a fixed arrangement, which may be placed anywhere in the grid and mirrored. Each
+    /// character of the pattern stands for the item in `key`, a space for an empty cell
+    Shaped {
+        pattern: Vec<String>,
+        key: HashMap<char, Item>,
+        output: RecipeOutput,
+    },
+    /// Items in any arrangement
+    Shapeless {
+        ingredients: Vec<Item>,
+        output: RecipeOutput,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct RecipeOutput {
+    pub item: Item,
+    #[serde(default = "one")]
+    pub count: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug)]
+pub enum RecipeError {
+    Io(std::io::Error),
+    Parse(serde_yaml::Error),
+    /// The pattern of a recipe (index) uses a character (second) that is not in its key
+    UnknownKey(usize, char),
+    /// A recipe (index) has no ingredients, too many, or a pattern larger than the grid
+    InvalidShape(usize),
+    /// A recipe (index) crafts nothing, its output count is zero
+    EmptyOutput(usize),
+}
+
+impl fmt::Display for RecipeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecipeError::Io(e) => write!(f, "{}", e),
+            RecipeError::Parse(e) => write!(f, "{}", e),
+            RecipeError::UnknownKey(recipe, key) => write!(
+                f,
+                "recipe {} uses `{}` in its pattern, which is not in its key",
+                recipe, key
+            ),
+            RecipeError::InvalidShape(recipe) => write!(
+                f,
+                "recipe {} is empty or does not fit into the {}x{} grid",
+                recipe, GRID_SIZE, GRID_SIZE
+            ),
+            RecipeError::EmptyOutput(recipe) => {
+                write!(f, "recipe {} has an output count of zero", recipe)
+            }
+        }
+    }
+}
+
+/// Rectangular arrangement of items, row by row
+#[derive(Debug, Clone, PartialEq)]
+struct Shape {
+    width: usize,
+    height: usize,
+    cells: Vec<Option<Item>>,
+}
+
+impl Shape {
+    /// Smallest rectangle containing all items of a grid `width` cells wide
+    fn cropped(cells: &[Option<Item>], width: usize) -> Self {
+        let height = cells.len().checked_div(width).unwrap_or(0);
+        let occupied = |x: usize, y: usize| cells[y * width + x].is_some();
+        let columns: Vec<usize> = (0..width)
+            .filter(|x| (0..height).any(|y| occupied(*x, y)))
+            .collect();
+        let rows: Vec<usize> = (0..height)
+            .filter(|y| (0..width).any(|x| occupied(x, *y)))
+            .collect();
+        match (columns.first(), columns.last(), rows.first(), rows.last()) {
+            (Some(&left), Some(&right), Some(&top), Some(&bottom)) => Self {
+                width: right - left + 1,
+                height: bottom - top + 1,
+                cells: (top..=bottom)
+                    .flat_map(|y| (left..=right).map(move |x| cells[y * width + x]))
+                    .collect(),
+            },
+            _ => Self {
+                width: 0,
+                height: 0,
+                cells: Vec::new(),
+            },
+        }
+    }
+
+    /// The shape flipped from left to right
+    fn mirrored(&self) -> Self {
+        Self {
+            width: self.width,
+            height: self.height,
+            cells: self
+                .cells
+                .chunks(self.width.max(1))
+                .flat_map(|row| row.iter().rev().copied())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Ingredients {
+    Shaped(Shape),
+    /// Sorted by id
+    Shapeless(Vec<Item>),
+}
+
+/// Items sorted by their id, to compare them regardless of their arrangement
+fn sorted(items: impl Iterator<Item = Item>) -> Vec<Item> {
+    let mut items: Vec<Item> = items.collect();
+    items.sort_by_key(Item::id);
+    items
+}
+
+#[derive(Debug, Clone)]
+pub struct Recipe {
+    ingredients: Ingredients,
+    pub output: RecipeOutput,
+}
+
+impl Recipe {
+    fn compile(index: usize, def: &RecipeDef) -> Result<Self, RecipeError> {
+        let (ingredients, output) = match def {
+            RecipeDef::Shaped {
+                pattern,
+                key,
+                output,
+            } => {
+                // Shorter rows are padded with empty cells
+                let width = pattern
+                    .iter()
+                    .map(|row| row.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                let mut cells = Vec::with_capacity(width * pattern.len());
+                for row in pattern {
+                    for c in row.chars().chain(std::iter::repeat(' ')).take(width) {
+                        cells.push(match c {
+                            ' ' => None,
+                            c => Some(key.get(&c).ok_or(RecipeError::UnknownKey(index, c))?.held()),
+                        });
+                    }
+                }
+                let shape = Shape::cropped(&cells, width);
+                if shape.cells.is_empty() || shape.width > GRID_SIZE || shape.height > GRID_SIZE {
+                    return Err(RecipeError::InvalidShape(index));
+                }
+                (Ingredients::Shaped(shape), output)
+            }
+            RecipeDef::Shapeless {
+                ingredients,
+                output,
+            } => {
+                if ingredients.is_empty() || ingredients.len() > GRID_SLOTS {
+                    return Err(RecipeError::InvalidShape(index));
+                }
+                let ingredients = sorted(ingredients.iter().map(|item| item.held()));
+                (Ingredients::Shapeless(ingredients), output)
+            }
+        };
+        // The ingredients would be used up for nothing
+        if output.count == 0 {
+            return Err(RecipeError::EmptyOutput(index));
+        }
+        // Blocks are written with any facing but held and crafted as the same item
+        Ok(Self {
+            ingredients,
+            output: RecipeOutput {
+                item: output.item.held(),
+                ..*output
+            },
+        })
+    }
+
+    /// Whether the items of a grid `width` cells wide are exactly the ingredients of the recipe
+    pub fn matches(&self, cells: &[Option<Item>], width: usize) -> bool {
+        match &self.ingredients {
+            Ingredients::Shaped(shape) => {
+                let grid = Shape::cropped(cells, width);
+                grid == *shape || grid == shape.mirrored()
+            }
+            Ingredients::Shapeless(ingredients) => {
+                sorted(cells.iter().flatten().copied()) == *ingredients
+            }
+        }
+    }
+}
+
+/// All known recipes, the first matching one is crafted
+pub struct RecipeBook {
+    recipes: Vec<Recipe>,
+}
+
+impl RecipeBook {
+    pub fn compile(defs: &[RecipeDef]) -> Result<Self, RecipeError> {
+        let recipes = defs
+            .iter()
+            .enumerate()
+            .map(|(index, def)| Recipe::compile(index, def))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { recipes })
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, RecipeError> {
+        let defs: Vec<RecipeDef> = serde_yaml::from_str(yaml).map_err(RecipeError::Parse)?;
+        Self::compile(&defs)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, RecipeError> {
+        let reader = File::open(path).map_err(RecipeError::Io)?;
+        let defs: Vec<RecipeDef> = serde_yaml::from_reader(reader).map_err(RecipeError::Parse)?;
+        Self::compile(&defs)
+    }
+
+    pub fn bundled() -> Self {
+        Self::from_yaml(BUNDLED_RECIPES).expect("Bundled recipes are invalid")
+    }
+
+    /// Recipes read from `path`, falling back to the bundled ones if there is none or it fails to
+    /// load
+    pub fn from_config(path: &Option<String>) -> Self {
+        match path {
+            None => Self::bundled(),
+            Some(path) => Self::from_file(Path::new(path)).unwrap_or_else(|e| {
+                error!(
+                    "Failed to load recipes `{}`, falling back to the bundled recipes: {}",
+                    path, e
+                );
+                Self::bundled()
+            }),
+        }
+    }
+
+    /// First recipe matching the items of a grid `width` cells wide
+    pub fn find(&self, cells: &[Option<Item>], width: usize) -> Option<&Recipe> {
+        self.recipes
+            .iter()
+            .find(|recipe| recipe.matches(cells, width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::BlockType::*;
+
+    fn grid(rows: [[Option<Item>; GRID_SIZE]; GRID_SIZE]) -> Vec<Option<Item>> {
+        rows.iter().flatten().copied().collect()
+    }
+
+    #[test]
+    fn shaped_and_shapeless_matching() {
+        let book = RecipeBook::from_yaml(
+            r#"
+- type: Shaped
+  pattern:
+    - "CC "
+    - " C"
+  key: { C: Cobble }
+  output: { item: Bricks, count: 2 }
+- type: Shapeless
+  ingredients: [Sand, Gravel, Sand]
+  output: { item: Dirt }
+"#,
+        )
+        .unwrap();
+        let find = |cells: &[Option<Item>]| book.find(cells, GRID_SIZE).map(|r| r.output);
+        let bricks = Some(RecipeOutput {
+            item: Item::Block(Bricks),
+            count: 2,
+        });
+        let c = Some(Item::Block(Cobble));
+
+        // Anywhere in the grid and mirrored
+        assert_eq!(
+            find(&grid([[c, c, None], [None, c, None], [None; 3]])),
+            bricks
+        );
+        assert_eq!(
+            find(&grid([[None; 3], [None, c, c], [None, None, c]])),
+            bricks
+        );
+        assert_eq!(
+            find(&grid([[None; 3], [c, c, None], [c, None, None]])),
+            bricks
+        );
+        // But neither rotated nor with additional blocks
+        assert_eq!(
+            find(&grid([[c, None, None], [c, c, None], [None; 3]])),
+            None
+        );
+        assert_eq!(find(&grid([[c, c, c], [None, c, None], [None; 3]])), None);
+        assert_eq!(find(&grid([[None; 3]; 3])), None);
+
+        let dirt = Some(RecipeOutput {
+            item: Item::Block(Dirt),
+            count: 1,
+        });
+        let (s, g) = (Some(Item::Block(Sand)), Some(Item::Block(Gravel)));
+        assert_eq!(
+            find(&grid([[g, None, None], [None; 3], [s, None, s]])),
+            dirt
+        );
+        assert_eq!(find(&grid([[s, g, s], [None; 3], [None; 3]])), dirt);
+        assert_eq!(find(&grid([[s, g, None], [None; 3], [None; 3]])), None);
+        assert_eq!(find(&grid([[s, g, s], [s, None, None], [None; 3]])), None);
+
+        // Independent of the grid's size
+        assert_eq!(book.find(&[c, c, None, c], 2).map(|r| r.output), bricks);
+    }
+
+    #[test]
+    fn invalid_recipes() {
+        assert!(matches!(
+            RecipeBook::from_yaml(
+                "[{ type: Shaped, pattern: [AB], key: { A: Dirt }, output: { item: Sand } }]"
+            ),
+            Err(RecipeError::UnknownKey(0, 'B'))
+        ));
+        assert!(matches!(
+            RecipeBook::from_yaml(
+                "[{ type: Shapeless, ingredients: [Dirt], output: { item: Sand } },
+                  { type: Shaped, pattern: [AAAA], key: { A: Dirt }, output: { item: Sand } }]"
+            ),
+            Err(RecipeError::InvalidShape(1))
+        ));
+        assert!(matches!(
+            RecipeBook::from_yaml("[{ type: Shapeless, ingredients: [], output: { item: Sand } }]"),
+            Err(RecipeError::InvalidShape(0))
+        ));
+        assert!(matches!(
+            RecipeBook::from_yaml(
+                "[{ type: Shapeless, ingredients: [Dirt], output: { item: Sand, count: 0 } }]"
+            ),
+            Err(RecipeError::EmptyOutput(0))
+        ));
+        assert!(matches!(
+            RecipeBook::from_yaml("[{ type: Shapeless, ingredients: [Bedrock], output: Sand }]"),
+            Err(RecipeError::Parse(_))
+        ));
+        // Surrounding empty cells are cropped
+        assert!(RecipeBook::from_yaml(
+            "[{ type: Shaped, pattern: ['    ', ' AA'], key: { A: Dirt }, output: { item: Sand } }]"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn facings_are_the_same_item() {
+        let book = RecipeBook::from_yaml(
+            r#"
+- type: Shaped
+  pattern: [V, V]
+  key: { V: VinesWest }
+  output: { item: LadderEast, count: 2 }
+- type: Shapeless
+  ingredients: [LadderSouth, Dirt]
+  output: { item: VinesEast }
+"#,
+        )
+        .unwrap();
+        let find = |cells: &[Option<Item>]| book.find(cells, 1).map(|r| r.output);
+        let vines = Some(Item::from(VinesWest));
+        assert_eq!(vines, Some(Item::Block(VinesNorth)));
+        assert_eq!(
+            find(&[vines, vines]),
+            Some(RecipeOutput {
+                item: Item::Block(LadderNorth),
+                count: 2
+            })
+        );
+        let ladder = Some(Item::from(LadderEast));
+        assert_eq!(
+            find(&[ladder, Some(Item::Block(Dirt))]).map(|output| output.item),
+            Some(Item::Block(VinesNorth))
+        );
+    }
+
+    #[test]
+    fn bundled_recipes() {
+        let book = RecipeBook::bundled();
+        let mut cells = [None; GRID_SLOTS];
+        cells[4] = Some(Item::Block(Wood));
+        let planks = book.find(&cells, GRID_SIZE).unwrap().output;
+        assert_eq!(planks.item, Item::Block(Planks));
+        assert_eq!(planks.count, 4);
+
+        // Tools are crafted from planks or cobble on a handle of wood
+        let (m, w) = (Some(Item::Block(Cobble)), Some(Item::Block(Wood)));
+        let axe = book.find(
+            &grid([[None, m, m], [None, w, m], [None, w, None]]),
+            GRID_SIZE,
+        );
+        assert_eq!(
+            axe.map(|recipe| recipe.output.item),
+            Some(Item::Tool(crate::item::Tool::StoneAxe))
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use bevy::prelude::*;
+use bevy_rapier3d::{
+    na::{Isometry3, Translation3, Vector3},
+    physics::RigidBodyHandleComponent,
+    rapier::dynamics::RigidBodySet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::CobbleConfig,
+    interface::controller::BodyTag,
+    world::{properties, NineSurroundChunk, PlayerPosition},
+    AppState,
+};
+
+pub const MAX_HEALTH: u32 = 20;
+/// Height in blocks that can be fallen without taking damage
+const SAFE_FALL_HEIGHT: f32 = 3.0;
+/// Seconds between two damage ticks while the player's head is inside a block
+const SUFFOCATION_INTERVAL: f32 = 0.5;
+
+pub const MAX_STAMINA: f32 = 20.0;
+/// Stamina below which the player can no longer sprint
+const SPRINT_THRESHOLD: f32 = 6.0;
+/// Stamina drained per second of sprinting
+pub const SPRINT_COST: f32 = 1.0;
+/// Stamina drained per jump
+pub const JUMP_COST: f32 = 0.5;
+/// Stamina regained per second once the player has rested for `RECOVERY_DELAY` seconds
+const STAMINA_RECOVERY: f32 = 0.1;
+const RECOVERY_DELAY: f32 = 2.0;
+/// Stamina per second that can no longer be regained by resting, the player starves within an
+/// hour
+const HUNGER_RATE: f32 = MAX_STAMINA / 3600.0;
+/// Seconds between two damage ticks while the stamina is depleted
+const STARVATION_INTERVAL: f32 = 4.0;
+
+/// System labels for ECS
+#[derive(Clone, PartialEq, Eq, Hash, Debug, SystemLabel)]
+pub enum HealthLabels {
+    ApplyDamage,
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Health::default())
+            .insert_resource(Stamina::default())
+            .add_event::<EventDamage>()
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(suffocation.system().before(HealthLabels::ApplyDamage))
+                    .with_system(starvation.system().before(HealthLabels::ApplyDamage))
+                    .with_system(apply_damage.system().label(HealthLabels::ApplyDamage))
+                    .with_system(respawn.system().after(HealthLabels::ApplyDamage)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageCause {
+    Fall,
+    Suffocation,
+    Starvation,
+}
+
+/// Damage dealt to the player, ignored in creative mode
+pub struct EventDamage {
+    pub amount: u32,
+    pub cause: DamageCause,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Health {
+    current: u32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            current: MAX_HEALTH,
+        }
+    }
+}
+
+impl Health {
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+
+    pub fn damage(&mut self, amount: u32) {
+        self.current = self.current.saturating_sub(amount);
+    }
+
+    pub fn heal(&mut self, amount: u32) {
+        self.current = (self.current + amount).min(MAX_HEALTH);
+    }
+}
+
+/// Hunger and stamina of the player, drained by sprinting and jumping and slowly regained while
+/// resting, but never beyond what hunger leaves
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Stamina {
+    current: f32,
+    /// Most stamina that can be regained, only ever lowered by hunger
+    #[serde(default = "max_stamina")]
+    satiety: f32,
+    /// Seconds since the player last exerted themselves
+    #[serde(skip)]
+    rested: f32,
+    /// Seconds since the last starvation damage while the stamina is depleted
+    #[serde(skip)]
+    starving: f32,
+}
+
+fn max_stamina() -> f32 {
+    MAX_STAMINA
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Self {
+            current: MAX_STAMINA,
+            satiety: MAX_STAMINA,
+            rested: 0.0,
+            starving: 0.0,
+        }
+    }
+}
+
+impl Stamina {
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    pub fn satiety(&self) -> f32 {
+        self.satiety
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    pub fn can_sprint(&self) -> bool {
+        self.current >= SPRINT_THRESHOLD
+    }
+
+    pub fn exert(&mut self, amount: f32) {
+        self.current = (self.current - amount).max(0.0);
+        self.rested = 0.0;
+    }
+
+    /// Advance the time since the last exertion by `delta` seconds, hunger for that time and
+    /// regain stamina once rested
+    pub fn recover(&mut self, delta: f32) {
+        self.satiety = (self.satiety - HUNGER_RATE * delta).max(0.0);
+        self.rested += delta;
+        if self.rested >= RECOVERY_DELAY {
+            self.current += STAMINA_RECOVERY * delta;
+        }
+        self.current = self.current.min(self.satiety);
+    }
+
+    /// Advance the time the stamina has been depleted by `delta` seconds, returns the starvation
+    /// damage dealt in that time
+    pub fn starve(&mut self, delta: f32) -> u32 {
+        if !self.is_empty() {
+            self.starving = 0.0;
+            return 0;
+        }
+        self.starving += delta;
+        let ticks = (self.starving / STARVATION_INTERVAL).floor();
+        self.starving -= ticks * STARVATION_INTERVAL;
+        ticks as u32
+    }
+}
+
+/// Damage of a landing with a vertical speed of `impact_speed`, one point per full block fallen
+/// beyond `SAFE_FALL_HEIGHT`. `gravity` is the magnitude of the gravitational acceleration
+pub fn fall_damage(impact_speed: f32, gravity: f32) -> u32 {
+    if impact_speed <= 0.0 || gravity <= 0.0 {
+        return 0;
+    }
+    let height = impact_speed * impact_speed / (2.0 * gravity);
+    (height - SAFE_FALL_HEIGHT).floor().max(0.0) as u32
+}
+
+/// Damage the player periodically while their head is inside a solid block
+fn suffocation(
+    time: Res<Time>,
+    position: Res<PlayerPosition>,
+    chunks: Res<NineSurroundChunk>,
+    mut damage: EventWriter<EventDamage>,
+    mut since_last: Local<f32>,
+) {
+    let inside = position.absolut.y >= 0.0
+        && chunks
+            .get(&position.absolut)
+            .map_or(false, |block| properties(&block).solid);
+    if !inside {
+        *since_last = 0.0;
+        return;
+    }
+    *since_last += time.delta_seconds();
+    if *since_last >= SUFFOCATION_INTERVAL {
+        *since_last -= SUFFOCATION_INTERVAL;
+        damage.send(EventDamage {
+            amount: 1,
+            cause: DamageCause::Suffocation,
+        });
+    }
+}
+
+/// Regain stamina while resting and damage the player periodically while it is depleted
+fn starvation(
+    time: Res<Time>,
+    config: Res<CobbleConfig>,
+    mut stamina: ResMut<Stamina>,
+    mut damage: EventWriter<EventDamage>,
+) {
+    if config.game.creative {
+        return;
+    }
+    stamina.recover(time.delta_seconds());
+    let amount = stamina.starve(time.delta_seconds());
+    if amount > 0 {
+        damage.send(EventDamage {
+            amount,
+            cause: DamageCause::Starvation,
+        });
+    }
+}
+
+fn apply_damage(
+    config: Res<CobbleConfig>,
+    mut health: ResMut<Health>,
+    mut events: EventReader<EventDamage>,
+) {
+    for event in events.iter() {
+        if config.game.creative || health.is_dead() {
+            continue;
+        }
+        health.damage(event.amount);
+        if health.is_dead() {
+            info!("Player died: {:?}", event.cause);
+        }
+    }
+}
+
+/// Move a dead player back to the world spawn, on top of the highest solid block of the spawn
+/// column if it is loaded
+fn respawn(
+    mut health: ResMut<Health>,
+    mut stamina: ResMut<Stamina>,
+    chunks: Res<NineSurroundChunk>,
+    mut bodies: ResMut<RigidBodySet>,
+    query: Query<&RigidBodyHandleComponent, With<BodyTag>>,
+) {
+    if !health.is_dead() {
+        return;
+    }
+    let spawn = Vec3::from(crate::SPAWN_POSITION);
+    let height = chunks
+        .data
+        .values()
+        .next()
+        .map_or(0, |chunk| chunk.height());
+    let surface = (0..height).rev().find(|y| {
+        chunks
+            .get(&Vec3::new(spawn.x, *y as f32 + 0.5, spawn.z))
+            .map_or(false, |block| properties(&block).solid)
+    });
+    // The body's center lies one block above its feet
+    let y = surface.map_or(spawn.y, |y| spawn.y.max(y as f32 + 2.0));
+
+    if let Ok(body_handle) = query.single() {
+        if let Some(body) = bodies.get_mut(body_handle.handle()) {
+            let rotation = body.position().rotation;
+            body.set_position(
+                Isometry3::from_parts(Translation3::new(spawn.x, y, spawn.z), rotation),
+                true,
+            );
+            body.set_linvel(Vector3::zeros(), true);
+        }
+    }
+    health.heal(MAX_HEALTH);
+    *stamina = Stamina::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fall_damage_by_height() {
+        let gravity = 9.81;
+        let speed = |height: f32| (2.0 * gravity * height).sqrt();
+        assert_eq!(fall_damage(0.0, gravity), 0);
+        assert_eq!(fall_damage(-20.0, gravity), 0);
+        assert_eq!(fall_damage(speed(1.0), gravity), 0);
+        assert_eq!(fall_damage(speed(3.9), gravity), 0);
+        assert_eq!(fall_damage(speed(5.5), gravity), 2);
+        assert_eq!(fall_damage(speed(23.5), gravity), 20);
+    }
+
+    #[test]
+    fn health_bounds() {
+        let mut health = Health::default();
+        health.damage(5);
+        assert_eq!(health.current(), MAX_HEALTH - 5);
+        health.heal(100);
+        assert_eq!(health.current(), MAX_HEALTH);
+        health.damage(100);
+        assert!(health.is_dead());
+        assert_eq!(health.current(), 0);
+    }
+
+    #[test]
+    fn stamina_drain_and_recovery() {
+        let mut stamina = Stamina::default();
+        assert!(stamina.can_sprint());
+        stamina.exert(MAX_STAMINA - SPRINT_THRESHOLD + 1.0);
+        assert!(!stamina.can_sprint());
+        stamina.exert(100.0);
+        assert!(stamina.is_empty());
+
+        // Nothing is regained before the player has rested
+        stamina.recover(RECOVERY_DELAY / 2.0);
+        assert!(stamina.is_empty());
+        stamina.recover(10.0);
+        assert!((stamina.current() - 10.0 * STAMINA_RECOVERY).abs() < 1e-5);
+        // Hunger limits how much is regained
+        stamina.recover(1000.0);
+        assert!(stamina.satiety() < MAX_STAMINA);
+        assert_eq!(stamina.current(), stamina.satiety());
+    }
+
+    #[test]
+    fn starvation_deals_damage() {
+        let mut stamina = Stamina::default();
+        let mut health = Health::default();
+        let mut starved_after = None;
+        // A resting player, simulated in steps of a second
+        for second in 1..=7200i32 {
+            stamina.recover(1.0);
+            health.damage(stamina.starve(1.0));
+            if health.is_dead() {
+                starved_after = Some(second);
+                break;
+            }
+        }
+        // Once the stamina is used up by hunger, every damage tick takes one point of health
+        let hungry = (MAX_STAMINA / HUNGER_RATE) as u32;
+        let starving = MAX_HEALTH * STARVATION_INTERVAL as u32;
+        let expected = (hungry + starving) as i32;
+        let starved_after = starved_after.unwrap();
+        assert!(
+            (starved_after - expected).abs() <= 1,
+            "starved after {} seconds instead of {}",
+            starved_after,
+            expected
+        );
+
+        // Being exhausted is enough to starve, but the time to the next tick starts over on
+        // regaining any stamina
+        let mut stamina = Stamina::default();
+        stamina.exert(MAX_STAMINA);
+        assert_eq!(stamina.starve(STARVATION_INTERVAL - 1.0), 0);
+        assert_eq!(stamina.starve(STARVATION_INTERVAL + 2.0), 2);
+        stamina.recover(RECOVERY_DELAY + 1.0);
+        assert_eq!(stamina.starve(1.0), 0);
+        stamina.exert(MAX_STAMINA);
+        assert_eq!(stamina.starve(STARVATION_INTERVAL - 1.0), 0);
+    }
+}
@@ -1,29 +1,43 @@
 // Partially based on https://github.com/sburris0/bevy_flycam/blob/3350f6626382694217b50a197befcce66f2bf050/src/lib.rs
 // Original code is licensed under MIT, see LICENSES/bevy_flycam
-use std::{collections::HashSet, ops::Div, time::Duration};
+use std::collections::HashSet;
 
 use bevy::app::{Events, ManualEventReader};
 use bevy::ecs::system::SystemParam;
-use bevy::input::mouse::MouseMotion;
+use bevy::input::mouse::{MouseMotion, MouseWheel};
 use bevy::prelude::*;
+use bevy::render::camera::{Camera, CameraProjection, PerspectiveProjection};
 use bevy_rapier3d::{
     na::UnitQuaternion,
-    physics::{EventQueue, RigidBodyHandleComponent},
-    rapier::{dynamics::RigidBodySet, geometry::ColliderSet, math::Vector},
+    physics::{PhysicsInterpolationComponent, RigidBodyHandleComponent},
+    rapier::{
+        dynamics::{RigidBody, RigidBodySet},
+        math::Vector,
+    },
 };
-use kurinji::{Kurinji, OnActionBegin, OnActionProgress};
+use kurinji::{Kurinji, OnActionBegin};
 
 use crate::world::{
-    absolut_to_index_i32, compute_is_airborn, defaults, index_to_absolut,
-    raycast::RaycastSelection, BlockType, EventChunkAction,
+    absolut_to_index_i32, clamp_to_edges, compute_is_airborn, defaults, index_to_absolut,
+    place_sensor, properties, raycast::RaycastSelection, step_up_height, BlockType,
+    EventChunkAction, Facing, NineSurroundChunk, PhysicsClock, PhysicsWorld, PlayerPosition,
+    PlayerSensor, Surroundings, PLAYER_EYE_HEIGHT, PLAYER_HALF_HEIGHT, PLAYER_RADIUS,
+};
+use crate::{
+    config::{BindingMap, CobbleConfig},
+    health::{fall_damage, DamageCause, EventDamage, Stamina, JUMP_COST, SPRINT_COST},
+    interface::{controls::ControlsScreen, inventory_screen::InventoryScreen, Screens},
+    inventory::Inventory,
+    item::Item,
+    AppState,
 };
-use crate::{config::CobbleConfig, inventory::Inventory};
 
 /// System labels for ECS
 #[derive(Clone, PartialEq, Eq, Hash, Debug, SystemLabel)]
 pub enum ControllerLabels {
     PlayerMove,
     ProcessInput,
+    CursorGrab,
 }
 
 pub struct NoCameraPlayerPlugin;
@@ -31,28 +45,72 @@ impl Plugin for NoCameraPlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<InputState>()
             .init_resource::<MovementSettings>()
+            .init_resource::<PlayerInput>()
+            .init_resource::<InventoryScreen>()
+            .init_resource::<ControlsScreen>()
+            .init_resource::<BreakProgress>()
             .add_startup_system(init.system())
             .add_startup_system(mapping.system())
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(enter_world.system()))
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(leave_world.system()))
+            .add_system(read_input.system().before(ControllerLabels::PlayerMove))
             .add_system(player_move.system().label(ControllerLabels::PlayerMove))
             .add_system(player_look.system())
-            .add_system(cursor_grab.system())
-            .add_system(process_input.system().label(ControllerLabels::ProcessInput));
+            .add_system(cursor_grab.system().label(ControllerLabels::CursorGrab))
+            .add_system(underwater_camera.system())
+            .add_system(sneak_camera.system())
+            .add_system(
+                wheel_actions
+                    .system()
+                    .before(ControllerLabels::ProcessInput),
+            )
+            .add_system(process_input.system().label(ControllerLabels::ProcessInput))
+            .add_system(break_blocks.system());
     }
 }
 
 const SENSITIVITY_COEFF: f32 = 0.1;
+/// Degrees per second the view turns with a fully tilted stick at a sensitivity of one
+const GAMEPAD_LOOK_SPEED: f32 = 150.0;
+/// Gravity scale while swimming with the head above the surface
+const SWIM_GRAVITY_SCALE: f32 = 0.3;
+/// Gravity scale while submerged, the negative value lets the player float up to the surface
+const SUBMERGED_GRAVITY_SCALE: f32 = -0.1;
+/// Factor applied to the movement speed while swimming
+const SWIM_SPEED_FACTOR: f32 = 0.5;
+/// Maximum vertical speed inside fluids, also slows down the player when falling into a fluid
+const SWIM_MAX_VERTICAL_SPEED: f32 = 3.0;
+/// Vertical speed when climbing up and when sliding down a climbable block
+const CLIMB_SPEED: f32 = 2.5;
+const CLIMB_SLIDE_SPEED: f32 = 1.5;
+/// Height of the camera above the center of the player's body and how far it is lowered while
+/// sneaking
+pub const CAMERA_HEIGHT: f32 = 0.3;
+const SNEAK_CAMERA_DROP: f32 = 0.25;
+/// Speed in blocks per second at which the camera is lowered and raised
+const SNEAK_CAMERA_SPEED: f32 = 2.0;
+/// Minimum time in seconds the movement is looked ahead to stop a sneaking player at edges
+const SNEAK_LOOKAHEAD: f32 = 1.0 / 30.0;
+/// Space left between the player's body and the ledge it steps onto
+const STEP_CLEARANCE: f32 = 0.05;
+/// Background color and view distance while the player's eyes are inside a fluid
+pub const UNDERWATER_COLOR: Color = Color::rgb(0.12, 0.25, 0.55);
+const UNDERWATER_VIEW_DISTANCE: f32 = 16.0;
 
 /// Keeps track of mouse motion events, pitch, and yaw
 #[derive(Default)]
-struct InputState {
+pub struct InputState {
     reader_motion: ManualEventReader<MouseMotion>,
-    pitch: f32,
-    yaw: f32,
+    /// Rotation of the camera around the horizontal axis in radians
+    pub pitch: f32,
+    /// Rotation of the body around the vertical axis in degrees
+    pub yaw: f32,
 }
 
 /// Mouse sensitivity and movement speed
 pub struct MovementSettings {
     pub sensitivity: f32,
+    pub gamepad_sensitivity: f32,
     pub speed: f32,
     pub fly: bool,
 }
@@ -61,12 +119,21 @@ impl Default for MovementSettings {
     fn default() -> Self {
         Self {
             sensitivity: 1.0,
+            gamepad_sensitivity: 1.0,
             speed: 6.0,
             fly: false,
         }
     }
 }
 
+/// Block the player is breaking in survival mode and for how long, scaled by the break speed of
+/// the held tool
+#[derive(Default)]
+struct BreakProgress {
+    target: Option<(IVec2, UVec3)>,
+    seconds: f32,
+}
+
 pub struct CameraTag;
 pub struct BodyTag;
 pub struct YawTag;
@@ -74,9 +141,13 @@ pub struct YawTag;
 pub struct MovementState {
     pub airborn: bool,
     pub intersections: HashSet<(usize, u64)>,
-    last_jump: Duration,
-    last_grounded: Duration,
-    last_airborn: Duration,
+    /// Whether the player was airborn before the last physics step
+    was_airborn: bool,
+    /// Physics steps since the last jump and since the player left the ground
+    steps_since_jump: u32,
+    airborn_steps: u32,
+    /// Highest downward speed since the player left the ground
+    fall_speed: f32,
 }
 
 impl Default for MovementState {
@@ -84,133 +155,347 @@ impl Default for MovementState {
         Self {
             airborn: true,
             intersections: HashSet::with_capacity(1),
-            last_jump: Duration::new(0, 0),
-            last_grounded: Duration::from_secs(u64::MAX),
-            last_airborn: Duration::from_secs(0),
+            was_airborn: true,
+            steps_since_jump: u32::MAX,
+            airborn_steps: 0,
+            fall_speed: 0.0,
         }
     }
 }
 
-fn toggle_grab_cursor(window: &mut Window) {
-    window.set_cursor_lock_mode(!window.cursor_locked());
-    window.set_cursor_visibility(!window.cursor_visible());
+/// Movement input of the player, sampled once per frame and applied to every physics step of
+/// that frame
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct PlayerInput {
+    /// Strength of the movement in each direction from zero to one, in between for a partly
+    /// tilted stick
+    pub forward: f32,
+    pub backward: f32,
+    pub left: f32,
+    pub right: f32,
+    pub jump: bool,
+    pub sneak: bool,
+    pub sprint: bool,
 }
 
-fn set_grab_cursor(window: &mut Window, value: bool) {
+pub fn set_grab_cursor(window: &mut Window, value: bool) {
     window.set_cursor_lock_mode(value);
     window.set_cursor_visibility(!value);
 }
 
-fn init(
-    config: Res<CobbleConfig>,
-    mut settings: ResMut<MovementSettings>,
-    mut windows: ResMut<Windows>,
-) {
+fn init(config: Res<CobbleConfig>, mut settings: ResMut<MovementSettings>) {
+    settings.sensitivity = config.input.sensitivity;
+    settings.gamepad_sensitivity = config.input.gamepad_sensitivity;
+}
+
+fn enter_world(config: Res<CobbleConfig>, mut windows: ResMut<Windows>) {
     let window = windows.get_primary_mut().unwrap();
     set_grab_cursor(window, config.input.initial_cursor_grab);
-    settings.sensitivity = config.input.sensitivity;
+}
+
+/// Close the screens and release the cursor for the main menu
+fn leave_world(
+    mut windows: ResMut<Windows>,
+    mut screen: ResMut<InventoryScreen>,
+    mut controls: ResMut<ControlsScreen>,
+    mut progress: ResMut<BreakProgress>,
+) {
+    set_grab_cursor(windows.get_primary_mut().unwrap(), false);
+    screen.set_open(false);
+    controls.set_open(false);
+    *progress = BreakProgress::default();
 }
 
 #[derive(SystemParam)]
 pub struct PlayerMoveParams<'a> {
-    input: Res<'a, Kurinji>,
-    windows: Res<'a, Windows>,
+    input: Res<'a, PlayerInput>,
     settings: Res<'a, MovementSettings>,
-    collider_set: Res<'a, ColliderSet>,
-    events: Res<'a, EventQueue>,
     time: Res<'a, Time>,
+    config: Res<'a, CobbleConfig>,
+    chunks: Res<'a, NineSurroundChunk>,
 }
 
+/// Everything a physics step of the player reads besides the player's body and movement state
+struct StepInputs<'a> {
+    input: &'a PlayerInput,
+    settings: &'a MovementSettings,
+    config: &'a CobbleConfig,
+    chunks: &'a NineSurroundChunk,
+}
+
+/// Whether the player sneaks, i.e., walks slowly, does not walk off edges, and ducks
+fn is_sneaking(sneak: bool, settings: &MovementSettings, surroundings: &Surroundings) -> bool {
+    !settings.fly && !surroundings.feet_in_fluid && !surroundings.climbing && sneak
+}
+
+fn read_input(input: Res<Kurinji>, windows: Res<Windows>, mut player_input: ResMut<PlayerInput>) {
+    let window = windows.get_primary().unwrap();
+    *player_input = if window.cursor_locked() {
+        PlayerInput {
+            forward: input.get_action_strength("MOVE_FORWARD"),
+            backward: input.get_action_strength("MOVE_BACKWARD"),
+            left: input.get_action_strength("MOVE_LEFT"),
+            right: input.get_action_strength("MOVE_RIGHT"),
+            jump: input.is_action_active("MOVE_JUMP"),
+            sneak: input.is_action_active("MOVE_MOD_SLOW_DESC"),
+            sprint: input.is_action_active("MOVE_MOD_FAST"),
+        }
+    } else {
+        PlayerInput::default()
+    };
+}
+
+/// Advance the player's movement and the physics world in fixed steps, so that the movement does
+/// not depend on the frame rate
 fn player_move(
     params: PlayerMoveParams,
-    mut bodies: ResMut<RigidBodySet>,
-    mut input_events: EventReader<OnActionProgress>,
-    query: Query<&RigidBodyHandleComponent, With<BodyTag>>,
+    mut world: PhysicsWorld,
+    mut clock: ResMut<PhysicsClock>,
+    player: Query<&RigidBodyHandleComponent, With<BodyTag>>,
+    sensor: Query<&RigidBodyHandleComponent, With<PlayerSensor>>,
+    mut interpolated: Query<(
+        &RigidBodyHandleComponent,
+        &mut PhysicsInterpolationComponent,
+    )>,
+    mut damage: EventWriter<EventDamage>,
+    mut stamina: ResMut<Stamina>,
     mut state: Local<MovementState>,
 ) {
-    // Figure out whether the player is airborn based on a collider sensor parented to the player
-    // model
-    compute_is_airborn(&params.events, &params.collider_set, &mut state);
-    if state.airborn {
-        state.last_airborn = params.time.time_since_startup();
+    let player_handle = player.single().ok().map(|body| body.handle());
+    let sensor_handle = sensor.single().ok().map(|sensor| sensor.handle());
+    let (dt, gravity) = (world.dt(), world.gravity());
+    let inputs = StepInputs {
+        input: &params.input,
+        settings: &params.settings,
+        config: &params.config,
+        chunks: &params.chunks,
+    };
+    for _ in 0..clock.advance(params.time.delta_seconds(), dt) {
+        // Rendered bodies move from where they are before the step, including any step up
+        for (body_handle, mut previous) in interpolated.iter_mut() {
+            if let Some(body) = world.bodies.get(body_handle.handle()) {
+                previous.0 = Some(*body.position());
+            }
+        }
+        if let Some(body) = player_handle.and_then(|handle| world.bodies.get_mut(handle)) {
+            if let Some(fall) = player_step(&inputs, body, dt, gravity, &mut state, &mut stamina) {
+                damage.send(fall);
+            }
+            let center = body.position().translation.vector;
+            let feet = Vec3::new(center.x, center.y - PLAYER_HALF_HEIGHT, center.z);
+            if let Some(sensor) = sensor_handle {
+                place_sensor(&mut world.bodies, sensor, feet);
+            }
+        }
+        world.step();
+
+        // Figure out whether the player is airborn based on the sensor below the player's feet
+        state.was_airborn = state.airborn;
+        compute_is_airborn(&world.events, &world.colliders, &mut state);
+        world.events.clear();
+    }
+}
+
+/// Move the player for a single physics step of `dt` seconds. Only depends on the input and the
+/// state after the previous step, which makes the movement reproducible. Returns the damage of
+/// landing after a fall, if any
+fn player_step(
+    inputs: &StepInputs,
+    body: &mut RigidBody,
+    dt: f32,
+    gravity: f32,
+    state: &mut MovementState,
+    stamina: &mut Stamina,
+) -> Option<EventDamage> {
+    /// Seconds after a jump before the player can jump again
+    const JUMP_COOLDOWN: f32 = 1.0;
+    /// Vertical speed at the start of a jump
+    const JUMP_SPEED: f32 = 5.3;
+
+    let input = inputs.input;
+    state.steps_since_jump = state.steps_since_jump.saturating_add(1);
+    state.airborn_steps = if state.airborn {
+        state.airborn_steps.saturating_add(1)
+    } else {
+        0
+    };
+
+    let center = body.position().translation.vector;
+    let feet = Vec3::new(center.x, center.y - PLAYER_HALF_HEIGHT, center.z);
+    let surroundings = inputs
+        .chunks
+        .surroundings(feet + Vec3::Y * PLAYER_EYE_HEIGHT);
+    let climbing = !inputs.settings.fly && surroundings.climbing;
+    let swimming = !inputs.settings.fly && !climbing && surroundings.feet_in_fluid;
+    let gravity_scale = if inputs.settings.fly || climbing {
+        0.0
+    } else if swimming && surroundings.head_in_fluid {
+        SUBMERGED_GRAVITY_SCALE
+    } else if swimming {
+        SWIM_GRAVITY_SCALE
+    } else {
+        1.0
+    };
+    body.set_gravity_scale(gravity_scale, true);
+    let sneaking = is_sneaking(input.sneak, inputs.settings, &surroundings);
+    let solid = |point: Vec3| {
+        point.y >= 0.0
+            && inputs
+                .chunks
+                .get(&point)
+                .map_or(false, |block| properties(&block).solid)
+    };
+
+    // The vertical velocity is already reduced by the contact on landing, hence the highest
+    // speed while airborn is used. Fluids and climbable blocks break any fall
+    let mut fall = None;
+    if inputs.settings.fly || swimming || climbing {
+        state.fall_speed = 0.0;
+    } else if state.airborn {
+        state.fall_speed = state.fall_speed.max(-body.linvel().y);
+    } else if state.was_airborn {
+        let amount = fall_damage(state.fall_speed, gravity);
+        if amount > 0 {
+            fall = Some(EventDamage {
+                amount,
+                cause: DamageCause::Fall,
+            });
+        }
+        state.fall_speed = 0.0;
+    }
+
+    // Stamina is only drained and required in survival mode
+    let survival = !inputs.config.game.creative;
+    let sprinting = !inputs.settings.fly
+        && !swimming
+        && !climbing
+        && input.sprint
+        && (!survival || stamina.can_sprint());
+    let sprint_factor = if sprinting {
+        1.5
+    } else if sneaking {
+        0.6
     } else {
-        state.last_grounded = params.time.time_since_startup();
+        1.0
+    };
+    let forward = Vector::new(0.0, 0.0, -sprint_factor);
+    let right = Vector::new(0.6, 0.0, 0.0);
+    #[inline(always)]
+    fn as_bevy(a: Vector<f32>) -> Vec3 {
+        Vec3::new(a.x, a.y, a.z)
     }
+    let pos = body.position();
+    let forward = as_bevy(pos.rotation.transform_vector(&forward));
+    let right = as_bevy(pos.rotation.transform_vector(&right));
 
-    let window = params.windows.get_primary().unwrap();
-    if let Ok(body_handle) = query.single() {
-        let body = bodies.get_mut(body_handle.handle()).unwrap();
-        body.set_gravity_scale(if params.settings.fly { 0.0 } else { 1.0 }, true);
-
-        let mut velocity = Vec3::ZERO;
-        let sprint_factor =
-            if !params.settings.fly && params.input.is_action_active("MOVE_MOD_FAST") {
-                1.5
-            } else if !params.settings.fly && params.input.is_action_active("MOVE_MOD_SLOW_DESC") {
-                0.6
-            } else {
-                1.0
-            };
-        let forward = Vector::new(0.0, 0.0, -sprint_factor);
-        let right = Vector::new(0.6, 0.0, 0.0);
-        #[inline(always)]
-        fn as_bevy(a: Vector<f32>) -> Vec3 {
-            Vec3::new(a.x, a.y, a.z)
+    let mut velocity =
+        forward * (input.forward - input.backward) + right * (input.right - input.left);
+    if input.jump {
+        velocity += Vec3::Y;
+    }
+    if input.sneak && (inputs.settings.fly || swimming) {
+        velocity -= Vec3::Y;
+    }
+
+    #[inline(always)]
+    fn airborn_speed_coefficient(x: f32) -> f32 {
+        1.005_937_3 * (1.527_939_2 * x).exp()
+    }
+    velocity *= inputs.settings.speed;
+    if swimming {
+        velocity *= SWIM_SPEED_FACTOR;
+    } else if !inputs.settings.fly && !climbing {
+        velocity /= airborn_speed_coefficient(state.airborn_steps as f32 * dt);
+    }
+
+    if !velocity.is_nan() && velocity.abs().max_element() > 1.0e-3 {
+        if survival && sprinting && (velocity.x != 0.0 || velocity.z != 0.0) {
+            stamina.exert(SPRINT_COST * dt);
         }
-        let pos = body.position();
-        let forward = as_bevy(pos.rotation.transform_vector(&forward));
-        let right = as_bevy(pos.rotation.transform_vector(&right));
-        let up = Vec3::new(0.0, 1.0, 0.0);
-
-        if window.cursor_locked() {
-            for event in input_events.iter() {
-                match event.action.as_str() {
-                    "MOVE_FORWARD" => velocity += forward,
-                    "MOVE_BACKWARD" => velocity -= forward,
-                    "MOVE_LEFT" => velocity -= right,
-                    "MOVE_RIGHT" => velocity += right,
-                    "MOVE_JUMP" => velocity += up,
-                    "MOVE_MOD_SLOW_DESC" if params.settings.fly => velocity -= up,
-                    _ => (),
+        if swimming {
+            // Rise and dive on input, otherwise drift with the buoyancy
+            if velocity.y.abs() < f32::EPSILON {
+                velocity.y = body.linvel().y;
+            }
+        } else if climbing {
+            // The vertical speed is set below, regardless of the horizontal input
+            velocity.y = body.linvel().y;
+        } else if !inputs.settings.fly {
+            if inputs.config.game.auto_step && !state.airborn {
+                if let Some(lift) = step_up_height(
+                    feet,
+                    velocity,
+                    PLAYER_RADIUS,
+                    2.0 * PLAYER_HALF_HEIGHT,
+                    solid,
+                ) {
+                    let mut isometry = *body.position();
+                    isometry.translation.vector.y += lift + STEP_CLEARANCE;
+                    body.set_position(isometry, true);
+                }
+            }
+            if velocity.y.abs() >= f32::EPSILON
+                && state.steps_since_jump as f32 * dt > JUMP_COOLDOWN
+                && !state.airborn
+            {
+                state.steps_since_jump = 0;
+                if survival {
+                    stamina.exert(JUMP_COST);
                 }
+                velocity.y = JUMP_SPEED;
+            } else {
+                velocity.y = body.linvel().y;
             }
         }
-
-        #[inline(always)]
-        fn airborn_speed_coefficient(x: f32) -> f32 {
-            1.005_937_3 * (1.527_939_2 * x).exp()
+        body.set_linvel(velocity.into(), true);
+    }
+    if climbing {
+        // Climb up when moving forward, hold on to the block when sneaking, otherwise slide down
+        let mut linvel = *body.linvel();
+        linvel.y = if input.forward > 0.0 {
+            CLIMB_SPEED
+        } else if input.sneak {
+            0.0
+        } else {
+            -CLIMB_SLIDE_SPEED
+        };
+        body.set_linvel(linvel, true);
+    } else if sneaking && !state.airborn {
+        // Also stop any remaining movement, which would otherwise carry the player off edges
+        let mut linvel = *body.linvel();
+        let lookahead = dt.max(SNEAK_LOOKAHEAD);
+        let allowed = clamp_to_edges(
+            feet,
+            Vec3::new(linvel.x, 0.0, linvel.z) * lookahead,
+            PLAYER_RADIUS,
+            solid,
+        );
+        if allowed.x == 0.0 {
+            linvel.x = 0.0;
         }
-        velocity *= params.settings.speed;
-        if !params.settings.fly {
-            velocity /= airborn_speed_coefficient(
-                (state.last_airborn.as_millis() as f32 - state.last_grounded.as_millis() as f32)
-                    .div(1000.0)
-                    .clamp(0.0, 1000.0),
-            );
+        if allowed.z == 0.0 {
+            linvel.z = 0.0;
         }
-
-        if !velocity.is_nan() && velocity.abs().max_element() > 1.0e-3 {
-            if !params.settings.fly {
-                if velocity.y.abs() >= f32::EPSILON
-                    && params.time.time_since_startup() - state.last_jump
-                        > Duration::from_millis(1000)
-                    && !state.airborn
-                {
-                    state.last_jump = params.time.time_since_startup();
-                    velocity.y = 0.0;
-                    body.apply_force(Vector::new(0.0, 30000.0 * 0.8, 0.0), true);
-                } else {
-                    velocity.y = body.linvel().y;
-                }
-            }
-            body.set_linvel(velocity.into(), true);
+        body.set_linvel(linvel, true);
+    } else if swimming {
+        let mut linvel = *body.linvel();
+        if linvel.y.abs() > SWIM_MAX_VERTICAL_SPEED {
+            linvel.y = linvel
+                .y
+                .clamp(-SWIM_MAX_VERTICAL_SPEED, SWIM_MAX_VERTICAL_SPEED);
+            body.set_linvel(linvel, true);
         }
     }
+    fall
 }
 
+/// Turn the view with the mouse and the `LOOK_*` actions, which are usually bound to a stick
+#[allow(clippy::too_many_arguments)]
 fn player_look(
     settings: Res<MovementSettings>,
     windows: Res<Windows>,
+    input: Res<Kurinji>,
+    time: Res<Time>,
     mut state: ResMut<InputState>,
     motion: Res<Events<MouseMotion>>,
     mut bodies: ResMut<RigidBodySet>,
@@ -219,68 +504,267 @@ fn player_look(
         Query<&RigidBodyHandleComponent, With<BodyTag>>,
     )>,
 ) {
-    let window = windows.get_primary().unwrap();
-    for ev in state.reader_motion.iter(&motion) {
-        if let Ok(mut transform) = query.q0_mut().single_mut() {
-            if window.cursor_locked() {
-                state.pitch -= (settings.sensitivity * SENSITIVITY_COEFF * ev.delta.y).to_radians();
+    let mouse = state
+        .reader_motion
+        .iter(&motion)
+        .fold(Vec2::ZERO, |sum, ev| sum + ev.delta);
+    if !windows.get_primary().unwrap().cursor_locked() {
+        return;
+    }
+    let stick = Vec2::new(
+        input.get_action_strength("LOOK_RIGHT") - input.get_action_strength("LOOK_LEFT"),
+        input.get_action_strength("LOOK_DOWN") - input.get_action_strength("LOOK_UP"),
+    );
+    // Rotation in degrees to the right and down
+    let delta = mouse * settings.sensitivity * SENSITIVITY_COEFF
+        + stick * settings.gamepad_sensitivity * GAMEPAD_LOOK_SPEED * time.delta_seconds();
+    if delta == Vec2::ZERO {
+        return;
+    }
+    if let Ok(mut transform) = query.q0_mut().single_mut() {
+        // Clamp pitch to prevent looking straight up or down
+        state.pitch = (state.pitch - delta.y.to_radians()).clamp(
+            -std::f32::consts::PI / 2.0 + 2.0 * std::f32::consts::PI / 180.0,
+            std::f32::consts::PI / 2.0 - 2.0 * std::f32::consts::PI / 180.0,
+        );
+        transform.rotation = Quat::from_axis_angle(Vec3::X, state.pitch);
+    }
+    if let Ok(body_handle) = query.q1().single() {
+        let body = bodies
+            .get_mut(body_handle.handle())
+            .expect("Failed to get player's ridigbody");
+        state.yaw -= delta.x;
+        let rot: UnitQuaternion<f32> = UnitQuaternion::new(Vector::y() * -delta.x.to_radians());
+        let mut next_pos = *body.position();
+        next_pos.append_rotation_wrt_center_mut(&rot);
+        body.set_position(next_pos, true);
+    }
+}
+
+/// Lower the camera while sneaking and raise it again afterwards
+fn sneak_camera(
+    input: Res<PlayerInput>,
+    settings: Res<MovementSettings>,
+    position: Res<PlayerPosition>,
+    time: Res<Time>,
+    mut query: Query<&mut Transform, With<CameraTag>>,
+) {
+    let target = if is_sneaking(input.sneak, &settings, &position.surroundings) {
+        CAMERA_HEIGHT - SNEAK_CAMERA_DROP
+    } else {
+        CAMERA_HEIGHT
+    };
+    let max_change = SNEAK_CAMERA_SPEED * time.delta_seconds();
+    for mut transform in query.iter_mut() {
+        let y = transform.translation.y;
+        transform.translation.y = y + (target - y).clamp(-max_change, max_change);
+    }
+}
+
+/// Fog the view while the player's eyes are inside a fluid by cutting off the view distance and
+/// blending the background into the fluid's color
+fn underwater_camera(
+    position: Res<PlayerPosition>,
+    mut clear_color: ResMut<ClearColor>,
+    mut query: Query<(&mut Camera, &mut PerspectiveProjection), With<CameraTag>>,
+    mut above_water: Local<Option<(Color, f32)>>,
+) {
+    let (mut camera, mut projection) = match query.single_mut() {
+        Ok(camera) => camera,
+        // The camera is despawned with the world, the next one starts above water
+        Err(_) => {
+            if let Some((color, _)) = above_water.take() {
+                clear_color.0 = color;
             }
-            // Clamp pitch to prevent looking straight up or down
-            state.pitch = state.pitch.clamp(
-                -std::f32::consts::PI / 2.0 + 2.0 * std::f32::consts::PI / 180.0,
-                std::f32::consts::PI / 2.0 - 2.0 * std::f32::consts::PI / 180.0,
-            );
-            transform.rotation = Quat::from_axis_angle(Vec3::X, state.pitch);
+            return;
         }
-        if let Ok(body_handle) = query.q1().single() {
-            if window.cursor_locked() {
-                let body = bodies
-                    .get_mut(body_handle.handle())
-                    .expect("Failed to get player's ridigbody");
-                state.yaw -= ev.delta.x * settings.sensitivity * SENSITIVITY_COEFF;
-                let rot: UnitQuaternion<f32> = UnitQuaternion::new(
-                    Vector::y()
-                        * -(ev.delta.x * settings.sensitivity * SENSITIVITY_COEFF).to_radians(),
-                );
-                let mut next_pos = *body.position();
-                next_pos.append_rotation_wrt_center_mut(&rot);
-                body.set_position(next_pos, true);
-            }
+    };
+    match (position.surroundings.head_in_fluid, *above_water) {
+        (true, None) => {
+            *above_water = Some((clear_color.0, projection.far));
+            clear_color.0 = UNDERWATER_COLOR;
+            projection.far = UNDERWATER_VIEW_DISTANCE;
+        }
+        (false, Some((color, far))) => {
+            *above_water = None;
+            clear_color.0 = color;
+            projection.far = far;
         }
+        _ => return,
     }
+    camera.projection_matrix = projection.get_projection_matrix();
 }
 
-fn cursor_grab(input: Res<Kurinji>, mut windows: ResMut<Windows>) {
+/// Open the pause menu on pause and release the cursor while it or the inventory or the controls
+/// screen is open. Keys typed into the palette's search field do not close the screen, pause only
+/// leaves the field. Keys pressed while an action is rebound are left to the controls screen
+fn cursor_grab(
+    input: Res<Kurinji>,
+    mut windows: ResMut<Windows>,
+    mut screen: ResMut<InventoryScreen>,
+    mut controls: ResMut<ControlsScreen>,
+    mut state: ResMut<State<AppState>>,
+) {
     let window = windows.get_primary_mut().unwrap();
-    if input.is_action_active("PAUSE") {
-        toggle_grab_cursor(window);
+    let playing = *state.current() == AppState::InGame;
+    if controls.is_open() {
+        if !controls.is_capturing()
+            && (input.is_action_active("PAUSE") || input.is_action_active("CONTROLS"))
+        {
+            // The controls are also opened from the pause menu, which they return to
+            controls.set_open(false);
+            set_grab_cursor(window, playing);
+        }
+    } else if !playing {
+        // The menus are clicked, pause only resumes the game from the pause menu
+        if *state.current() == AppState::Paused
+            && input.is_action_active("PAUSE")
+            && state.pop().is_ok()
+        {
+            set_grab_cursor(window, true);
+        }
+    } else if screen.is_typing() {
+        if input.is_action_active("PAUSE") {
+            screen.stop_typing();
+        }
+    } else if input.is_action_active("CONTROLS") {
+        screen.set_open(false);
+        controls.set_open(true);
+        set_grab_cursor(window, false);
+    } else if input.is_action_active("INVENTORY") {
+        let open = !screen.is_open();
+        screen.set_open(open);
+        set_grab_cursor(window, !open);
+    } else if input.is_action_active("PAUSE") {
+        if screen.is_open() {
+            screen.set_open(false);
+            set_grab_cursor(window, true);
+        } else if state.push(AppState::Paused).is_ok() {
+            set_grab_cursor(window, false);
+        }
+    }
+}
+
+/// Break the selected block while the break action is held in survival mode. The time it takes
+/// depends on the block's hardness and the held tool, breaking a block wears the tool
+#[allow(clippy::too_many_arguments)]
+fn break_blocks(
+    time: Res<Time>,
+    input: Res<Kurinji>,
+    selection: Res<RaycastSelection>,
+    chunks: Res<NineSurroundChunk>,
+    config: Res<CobbleConfig>,
+    screens: Screens,
+    mut progress: ResMut<BreakProgress>,
+    mut inventory: ResMut<Inventory>,
+    mut mod_event: EventWriter<EventChunkAction>,
+) {
+    // The action's strength is only set while it is held, whatever its event phase
+    let held = input.get_action_strength("BREAK") > 0.0;
+    let target = selection
+        .looking_at
+        .filter(|_| held && !config.game.creative && !screens.any_open());
+    if target != progress.target {
+        progress.target = target;
+        progress.seconds = 0.0;
+    }
+    let (chunk, index) = match target {
+        Some(target) => target,
+        None => return,
+    };
+    let block = match chunks.data.get(&chunk).and_then(|data| data.get(index)) {
+        Some(block) => block,
+        None => return,
+    };
+    let tool = match inventory.current_item() {
+        Some(Item::Tool(tool)) => Some(tool),
+        _ => None,
+    };
+    progress.seconds += time.delta_seconds() * tool.map_or(1.0, |tool| tool.break_speed(block));
+    if progress.seconds >= properties(&block).hardness {
+        mod_event.send(EventChunkAction::ModifyBlock(
+            chunk,
+            index,
+            BlockType::Air,
+            true,
+        ));
+        if tool.is_some() {
+            inventory.wear_current_tool();
+        }
+        // Start over on the next block, even if it is at the same position
+        progress.target = None;
+    }
+}
+
+/// Begin the actions bound to the mouse wheel, once per frame the wheel is scrolled
+fn wheel_actions(
+    config: Res<CobbleConfig>,
+    mut wheel: EventReader<MouseWheel>,
+    mut actions: EventWriter<OnActionBegin>,
+) {
+    let delta: f32 = wheel.iter().map(|event| event.y).sum();
+    let action = if delta > 0.0 {
+        &config.input.wheel_up
+    } else if delta < 0.0 {
+        &config.input.wheel_down
+    } else {
+        return;
+    };
+    if let Some(action) = action {
+        actions.send(OnActionBegin {
+            action: action.clone(),
+            strength: 1.0,
+        });
     }
 }
 
 fn mapping(mut kurinji: ResMut<Kurinji>, config: Res<CobbleConfig>) {
+    // Kurinji reports any action, the game only reacts to the ones it knows about
+    match BindingMap::from_bindings(&config.input.bindings) {
+        Ok(map) => {
+            for action in map.unknown_actions() {
+                warn!("Unknown action `{}` in the input bindings", action);
+            }
+        }
+        Err(e) => error!("Failed to read the input bindings: {}", e),
+    }
     kurinji.set_bindings(config.input.bindings.clone());
 }
 
+/// Horizontal facing of the block at `index` that points the most towards `point`
+fn facing_towards(index: IVec3, point: Vec3) -> Facing {
+    let delta = point - (index.as_f32() + Vec3::splat(0.5));
+    if delta.x.abs() > delta.z.abs() {
+        if delta.x > 0.0 {
+            Facing::East
+        } else {
+            Facing::West
+        }
+    } else if delta.z > 0.0 {
+        Facing::South
+    } else {
+        Facing::North
+    }
+}
+
 fn process_input(
     selection: Res<RaycastSelection>,
+    position: Res<PlayerPosition>,
     mut input: EventReader<OnActionBegin>,
     mut mod_event: EventWriter<EventChunkAction>,
     mut inventory: ResMut<Inventory>,
     mut settings: ResMut<MovementSettings>,
     config: Res<CobbleConfig>,
+    screens: Screens,
 ) {
     for event in input.iter() {
+        // Clicks on the inventory and controls screens neither break nor place blocks
+        if screens.any_open() {
+            continue;
+        }
         match event.action.as_str() {
-            "SLOT_1" => inventory.switch_slot(0),
-            "SLOT_2" => inventory.switch_slot(1),
-            "SLOT_3" => inventory.switch_slot(2),
-            "SLOT_4" => inventory.switch_slot(3),
-            "SLOT_5" => inventory.switch_slot(4),
-            "SLOT_6" => inventory.switch_slot(5),
-            "SLOT_7" => inventory.switch_slot(6),
-            "SLOT_8" => inventory.switch_slot(7),
-            "SLOT_9" => inventory.switch_slot(8),
-            "SLOT_10" => inventory.switch_slot(9),
+            "SLOT_NEXT" => inventory.cycle_slot(1),
+            "SLOT_PREVIOUS" => inventory.cycle_slot(-1),
             "FLY_TOGGLE" if config.game.creative => {
                 settings.fly = !settings.fly;
             }
@@ -289,22 +773,29 @@ fn process_input(
                     mod_event.send(EventChunkAction::PickBlock(chunk, index));
                 }
             }
-            "PLACE" => {
-                if let (Some((chunk, index)), Some(norm)) = (selection.looking_at, selection.normal)
-                {
-                    if let Some(block_type) = inventory.consume_current_slot() {
-                        let (norm_chunk, norm_index) = absolut_to_index_i32::<
-                            { defaults::CHUNK_WIDTH },
-                        >(
-                            &(index_to_absolut::<{ defaults::CHUNK_WIDTH }>(chunk, index) + norm),
-                        );
-                        mod_event.send(EventChunkAction::ModifyBlock(
-                            norm_chunk, norm_index, block_type, true,
-                        ));
-                    }
+            "PLACE" => match (
+                inventory.current_item(),
+                selection.looking_at,
+                selection.normal,
+            ) {
+                (Some(Item::Block(block_type)), Some((chunk, index)), Some(norm)) => {
+                    inventory.consume_current_slot();
+                    let target = index_to_absolut::<{ defaults::CHUNK_WIDTH }>(chunk, index) + norm;
+                    let (norm_chunk, norm_index) =
+                        absolut_to_index_i32::<{ defaults::CHUNK_WIDTH }>(&target);
+                    let block_type = block_type.with_facing(
+                        Facing::from_normal(norm)
+                            .unwrap_or_else(|| facing_towards(target, position.absolut)),
+                    );
+                    mod_event.send(EventChunkAction::ModifyBlock(
+                        norm_chunk, norm_index, block_type, true,
+                    ));
                 }
-            }
-            "BREAK" => {
+                // Tools are not placed
+                _ => (),
+            },
+            // Blocks are broken instantly in creative mode, over time otherwise, see `break_blocks`
+            "BREAK" if config.game.creative => {
                 if let Some((chunk, index)) = selection.looking_at {
                     mod_event.send(EventChunkAction::ModifyBlock(
                         chunk,
@@ -312,9 +803,91 @@ fn process_input(
                         BlockType::Air,
                         true,
                     ));
+                    if let Some(Item::Tool(_)) = inventory.current_item() {
+                        inventory.wear_current_tool();
+                    }
+                }
+            }
+            // `SLOT_1` to `SLOT_9`, numbers beyond the hotbar are ignored
+            action => {
+                if let Some(slot) = action
+                    .strip_prefix("SLOT_")
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .and_then(|n| n.checked_sub(1))
+                {
+                    inventory.switch_slot(slot);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_rapier3d::rapier::dynamics::RigidBodyBuilder;
+
+    use super::*;
+
+    /// Walk forward and keep jumping on flat ground for `steps` physics steps, advanced by the
+    /// physics clock at `fps` frames per second. Returns how high the player jumped and how far
+    /// they walked
+    fn walk_and_jump(fps: u32, steps: u32) -> (f32, f32) {
+        const DT: f32 = 1.0 / 60.0;
+        const GRAVITY: f32 = 9.81;
+        // The player's center when standing on the ground, there are no blocks to collide with
+        const GROUND: f32 = PLAYER_HALF_HEIGHT;
+        let input = PlayerInput {
+            forward: 1.0,
+            jump: true,
+            ..Default::default()
+        };
+        let (settings, config) = (MovementSettings::default(), CobbleConfig::default());
+        let chunks = NineSurroundChunk::empty();
+        let inputs = StepInputs {
+            input: &input,
+            settings: &settings,
+            config: &config,
+            chunks: &chunks,
+        };
+        let mut body = RigidBodyBuilder::new_dynamic()
+            .translation(0.0, GROUND, 0.0)
+            .build();
+        let (mut state, mut stamina) = (MovementState::default(), Stamina::default());
+        let mut clock = PhysicsClock::default();
+        clock.active = true;
+
+        let (mut simulated, mut peak) = (0, 0.0f32);
+        while simulated < steps {
+            let frame_steps = clock.advance(1.0 / fps as f32, DT).min(steps - simulated);
+            for _ in 0..frame_steps {
+                player_step(&inputs, &mut body, DT, GRAVITY, &mut state, &mut stamina);
+                // Integrate the body like the physics world, landing on the ground
+                let mut velocity = *body.linvel();
+                velocity.y -= GRAVITY * DT;
+                let mut position = *body.position();
+                position.translation.vector += velocity * DT;
+                if position.translation.vector.y <= GROUND {
+                    position.translation.vector.y = GROUND;
+                    velocity.y = 0.0;
                 }
+                body.set_position(position, true);
+                body.set_linvel(velocity, true);
+                state.was_airborn = state.airborn;
+                state.airborn = position.translation.vector.y > GROUND;
+                peak = peak.max(position.translation.vector.y - GROUND);
             }
-            _ => (),
+            simulated += frame_steps;
         }
+        (peak, -body.position().translation.vector.z)
+    }
+
+    #[test]
+    fn movement_is_frame_rate_independent() {
+        // Long enough to jump a few times after the cooldown
+        let steps = 240;
+        let (peak, distance) = walk_and_jump(30, steps);
+        assert!(peak > 1.0, "jumped {}", peak);
+        assert!(distance > 5.0, "walked {}", distance);
+        assert_eq!(walk_and_jump(240, steps), (peak, distance));
     }
 }
@@ -0,0 +1,384 @@
+//! Screen listing every action with the keys and buttons bound to it, on which an action is
+//! rebound by clicking it and pressing the new key or button
+use bevy::prelude::*;
+use kurinji::Kurinji;
+
+use crate::{
+    config::{self, action_name, BindingMap, Button, CobbleConfig, ACTIONS},
+    interface::{
+        absolute, contains,
+        controller::ControllerLabels,
+        full_screen,
+        overlay::{Handles, OverlayLabels},
+        text, ACTIVE_COLOR, BUTTON_COLOR, PANEL_COLOR, SCREEN_COLOR,
+    },
+    AppState,
+};
+
+pub struct ControlsScreenPlugin;
+
+impl Plugin for ControlsScreenPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_exit(AppState::Loading)
+                .with_system(setup_controls_screen.system())
+                .after(OverlayLabels::Setup),
+        )
+        // Keys pressed while capturing must not also close the screen, see `cursor_grab`
+        .add_system(handle_controls.system().after(ControllerLabels::CursorGrab))
+        .add_system(update_controls_visibility.system())
+        .add_system(update_controls_text.system());
+    }
+}
+
+/// Rows per column of the action list
+const ROWS: usize = (ACTIONS.len() + 1) / 2;
+const ROW_HEIGHT: f32 = 24.0;
+const COLUMN_WIDTH: f32 = 420.0;
+/// Width of the action's name, its bindings are shown to the right of it
+const LABEL_WIDTH: f32 = 150.0;
+/// Space around the list and between its columns in pixels
+const PADDING: f32 = 12.0;
+/// Height of the title above and of the message below the list
+const HEADER_HEIGHT: f32 = 32.0;
+
+/// Whether the controls screen is shown and the action that is being rebound
+#[derive(Default)]
+pub struct ControlsScreen {
+    open: bool,
+    capture: Option<Capture>,
+    /// Result of the last rebind or the conflict that has to be confirmed
+    message: String,
+}
+
+#[derive(Clone, Copy)]
+struct Capture {
+    action: &'static str,
+    /// Button bound to another action, which is only taken from it when pressed a second time
+    conflict: Option<Button>,
+}
+
+impl ControlsScreen {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+        self.capture = None;
+        self.message.clear();
+    }
+
+    /// Whether the next key or button press is bound to an action
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+}
+
+/// Part of the screen that is only visible while it is open
+struct ControlsPart;
+/// Row of the action at the index in `ACTIONS`
+struct ActionRow(usize);
+/// Text of the keys and buttons bound to the action at the index in `ACTIONS`
+struct ActionBindings(usize);
+struct ControlsMessage;
+
+struct RowMaterials {
+    normal: Handle<ColorMaterial>,
+    capturing: Handle<ColorMaterial>,
+}
+
+fn setup_controls_screen(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    handles: Res<Handles>,
+) {
+    let hidden = Visible {
+        is_visible: false,
+        is_transparent: true,
+    };
+    let rows = RowMaterials {
+        normal: materials.add(BUTTON_COLOR.into()),
+        capturing: materials.add(ACTIVE_COLOR.into()),
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: full_screen(),
+            material: materials.add(SCREEN_COLOR.into()),
+            visible: hidden.clone(),
+            ..Default::default()
+        })
+        .insert(ControlsPart)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(
+                            Val::Px(2.0 * COLUMN_WIDTH + 3.0 * PADDING),
+                            Val::Px(ROWS as f32 * ROW_HEIGHT + 2.0 * HEADER_HEIGHT + 2.0 * PADDING),
+                        ),
+                        ..Default::default()
+                    },
+                    material: materials.add(PANEL_COLOR.into()),
+                    visible: hidden.clone(),
+                    ..Default::default()
+                })
+                .insert(ControlsPart)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: absolute(PADDING, PADDING),
+                            text: text(&handles, "Controls (click an action to rebind it)", 22.0),
+                            visible: hidden.clone(),
+                            ..Default::default()
+                        })
+                        .insert(ControlsPart);
+                    for (index, (_, name)) in ACTIONS.iter().enumerate() {
+                        let left = PADDING + (index / ROWS) as f32 * (COLUMN_WIDTH + PADDING);
+                        let top = PADDING + HEADER_HEIGHT + (index % ROWS) as f32 * ROW_HEIGHT;
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    size: Size::new(
+                                        Val::Px(COLUMN_WIDTH),
+                                        Val::Px(ROW_HEIGHT - 2.0),
+                                    ),
+                                    ..absolute(left, top)
+                                },
+                                material: rows.normal.clone(),
+                                visible: hidden.clone(),
+                                ..Default::default()
+                            })
+                            .insert_bundle((ActionRow(index), ControlsPart))
+                            .with_children(|parent| {
+                                parent
+                                    .spawn_bundle(TextBundle {
+                                        style: absolute(6.0, 2.0),
+                                        text: text(&handles, name, 16.0),
+                                        visible: hidden.clone(),
+                                        ..Default::default()
+                                    })
+                                    .insert(ControlsPart);
+                                parent
+                                    .spawn_bundle(TextBundle {
+                                        style: absolute(LABEL_WIDTH, 2.0),
+                                        text: text(&handles, "", 16.0),
+                                        visible: hidden.clone(),
+                                        ..Default::default()
+                                    })
+                                    .insert_bundle((ActionBindings(index), ControlsPart));
+                            });
+                    }
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: absolute(
+                                PADDING,
+                                PADDING + HEADER_HEIGHT + ROWS as f32 * ROW_HEIGHT + 6.0,
+                            ),
+                            text: text(&handles, "", 16.0),
+                            visible: hidden.clone(),
+                            ..Default::default()
+                        })
+                        .insert_bundle((ControlsMessage, ControlsPart));
+                });
+        });
+    commands.insert_resource(rows);
+}
+
+/// Key, mouse button or button of the first gamepad pressed this frame
+fn pressed_button(
+    keys: &Input<KeyCode>,
+    mouse: &Input<MouseButton>,
+    gamepad: &Input<GamepadButton>,
+) -> Option<Button> {
+    keys.get_just_pressed()
+        .next()
+        .map(|key| Button::Key(*key))
+        .or_else(|| mouse.get_just_pressed().next().map(|b| Button::Mouse(*b)))
+        .or_else(|| {
+            gamepad
+                .get_just_pressed()
+                .find(|button| button.0 == Gamepad(0))
+                .map(|button| Button::Gamepad(button.1))
+        })
+}
+
+/// Start capturing when an action is clicked and bind the next key or button pressed to it. A
+/// button bound to another action has to be pressed twice, Escape cancels. The new bindings are
+/// applied right away and written to the config file
+#[allow(clippy::too_many_arguments)]
+fn handle_controls(
+    mut screen: ResMut<ControlsScreen>,
+    mut config: ResMut<CobbleConfig>,
+    mut kurinji: ResMut<Kurinji>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepad: Res<Input<GamepadButton>>,
+    windows: Res<Windows>,
+    rows: Query<(&Node, &GlobalTransform, &ActionRow)>,
+) {
+    if !screen.open {
+        return;
+    }
+    let mut capture = match screen.capture {
+        Some(capture) => capture,
+        None => {
+            if mouse.just_pressed(MouseButton::Left) {
+                let cursor = windows
+                    .get_primary()
+                    .and_then(|window| window.cursor_position());
+                let clicked = cursor.and_then(|cursor| {
+                    rows.iter()
+                        .find(|(node, transform, _)| contains(node, transform, cursor))
+                });
+                if let Some((_, _, ActionRow(index))) = clicked {
+                    screen.capture = Some(Capture {
+                        action: ACTIONS[*index].0,
+                        conflict: None,
+                    });
+                    screen.message.clear();
+                }
+            }
+            return;
+        }
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        screen.capture = None;
+        screen.message.clear();
+        return;
+    }
+    let button = match pressed_button(&keys, &mouse, &gamepad) {
+        Some(button) => button,
+        None => return,
+    };
+    let mut map = match BindingMap::from_bindings(&config.input.bindings) {
+        Ok(map) => map,
+        Err(e) => {
+            screen.capture = None;
+            screen.message = format!("Failed to read the bindings: {}", e);
+            return;
+        }
+    };
+    if let Some(other) = map.action(button).filter(|other| *other != capture.action) {
+        if capture.conflict != Some(button) {
+            screen.message = format!(
+                "{} is bound to {}, press it again to bind it to {} instead",
+                button.name(),
+                action_name(other),
+                action_name(capture.action)
+            );
+            capture.conflict = Some(button);
+            screen.capture = Some(capture);
+            return;
+        }
+    }
+    let previous = map.rebind(capture.action, button);
+    screen.capture = None;
+    screen.message = match previous {
+        Some(previous) => format!(
+            "{} was taken from {}",
+            button.name(),
+            action_name(&previous)
+        ),
+        None => String::new(),
+    };
+    match map.to_bindings() {
+        Ok(bindings) => {
+            kurinji.set_bindings(bindings.clone());
+            config.input.bindings = bindings;
+        }
+        Err(e) => {
+            screen.message = format!("Failed to apply the bindings: {}", e);
+            return;
+        }
+    }
+    // There is no config file on the web, the bindings only last for the session
+    if !cfg!(target_arch = "wasm32") {
+        if let Err(e) = config::store(&*config) {
+            error!("Failed to save the config: {}", e);
+            screen.message = format!("Failed to save the config: {}", e);
+        }
+    }
+}
+
+fn update_controls_visibility(
+    screen: Res<ControlsScreen>,
+    mut query: Query<&mut Visible, With<ControlsPart>>,
+) {
+    if !screen.is_changed() {
+        return;
+    }
+    for mut visible in query.iter_mut() {
+        visible.is_visible = screen.open;
+    }
+}
+
+/// Names of the keys, buttons, axes and wheel directions bound to `action`
+fn binding_names(map: &BindingMap, config: &CobbleConfig, action: &str) -> String {
+    let wheel = [
+        (&config.input.wheel_up, "Wheel up"),
+        (&config.input.wheel_down, "Wheel down"),
+    ];
+    let names: Vec<String> = map
+        .buttons(action)
+        .iter()
+        .map(Button::name)
+        .chain(map.axes(action))
+        .chain(
+            wheel
+                .iter()
+                .filter(|(bound, _)| bound.as_deref() == Some(action))
+                .map(|(_, name)| name.to_string()),
+        )
+        .collect();
+    if names.is_empty() {
+        "-".to_owned()
+    } else {
+        names.join(", ")
+    }
+}
+
+fn update_controls_text(
+    screen: Res<ControlsScreen>,
+    config: Res<CobbleConfig>,
+    materials: Option<Res<RowMaterials>>,
+    mut row_query: Query<(&mut Handle<ColorMaterial>, &ActionRow)>,
+    mut text_query: QuerySet<(
+        Query<(&mut Text, &ActionBindings)>,
+        Query<&mut Text, With<ControlsMessage>>,
+    )>,
+) {
+    if !screen.open || !(screen.is_changed() || config.is_changed()) {
+        return;
+    }
+    let materials = match materials {
+        Some(materials) => materials,
+        None => return,
+    };
+    let map = match BindingMap::from_bindings(&config.input.bindings) {
+        Ok(map) => map,
+        Err(e) => {
+            error!("Failed to read the bindings: {}", e);
+            return;
+        }
+    };
+    let capturing = screen.capture.map(|capture| capture.action);
+    for (mut material, ActionRow(index)) in row_query.iter_mut() {
+        *material = if capturing == Some(ACTIONS[*index].0) {
+            materials.capturing.clone()
+        } else {
+            materials.normal.clone()
+        };
+    }
+    for (mut text, ActionBindings(index)) in text_query.q0_mut().iter_mut() {
+        let action = ACTIONS[*index].0;
+        text.sections[0].value = if capturing == Some(action) {
+            "Press a key or button, Escape to cancel".to_owned()
+        } else {
+            binding_names(&map, &config, action)
+        };
+    }
+    if let Ok(mut text) = text_query.q1_mut().single_mut() {
+        text.sections[0].value = screen.message.clone();
+    }
+}
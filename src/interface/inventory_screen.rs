@@ -0,0 +1,715 @@
+//! Screen showing the whole inventory and the crafting grid, on which items are moved between
+//! slots with the mouse
+use bevy::{ecs::system::SystemParam, input::mouse::MouseWheel, prelude::*};
+
+use crate::{
+    config::CobbleConfig,
+    crafting::{RecipeBook, GRID_SIZE},
+    interface::{
+        absolute, contains, full_screen,
+        overlay::{Handles, OverlayLabels},
+        text, TextInput, PANEL_COLOR, SCREEN_COLOR,
+    },
+    inventory::{creative_palette, Inventory, Slot, ALL_SLOTS, HOTBAR_SLOTS, SLOTS},
+    item::Item,
+    AppState,
+};
+
+pub struct InventoryScreenPlugin;
+
+impl Plugin for InventoryScreenPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_exit(AppState::Loading)
+                .with_system(setup_inventory_screen.system())
+                .after(OverlayLabels::Setup),
+        )
+        .add_system(handle_clicks.system())
+        .add_system(handle_search.system())
+        .add_system(scroll_palette.system())
+        .add_system(update_screen_visibility.system())
+        .add_system(update_screen_slots.system())
+        .add_system(update_crafting_result.system())
+        .add_system(update_palette.system())
+        .add_system(update_held_item.system());
+    }
+}
+
+/// Width of a slot in pixels
+const SLOT_WIDTH: f32 = 50.0;
+/// Space around the slots and between the crafting grid, the main inventory and the hotbar in
+/// pixels
+const PADDING: f32 = 12.0;
+const ROWS: usize = (SLOTS + HOTBAR_SLOTS - 1) / HOTBAR_SLOTS;
+/// Column of the inventory the crafting grid starts in, the result is shown one column apart
+const GRID_COLUMN: usize = 2;
+const RESULT_COLUMN: usize = GRID_COLUMN + GRID_SIZE + 1;
+/// Rows of the creative palette shown at once, more are reached by scrolling
+const PALETTE_ROWS: usize = 3;
+const PALETTE_SLOTS: usize = PALETTE_ROWS * HOTBAR_SLOTS;
+/// Height of the palette's search field in pixels
+const SEARCH_HEIGHT: f32 = 30.0;
+
+/// Whether the inventory screen is shown, what the player drags, and the state of the creative
+/// palette
+#[derive(Default)]
+pub struct InventoryScreen {
+    open: bool,
+    drag: Option<Drag>,
+    /// Text the palette is filtered by and whether the player is typing it
+    search: String,
+    typing: bool,
+    /// First row of the palette that is shown
+    scroll: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Drag {
+    from: DragSource,
+    /// Button the drag was started with, the right button only drags half of the items
+    button: MouseButton,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DragSource {
+    Slot(usize),
+    /// Item of the creative palette, which is dropped as an infinite stack
+    Palette(Item),
+}
+
+impl InventoryScreen {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+        self.drag = None;
+        self.typing = false;
+    }
+
+    /// Whether keyboard input goes into the palette's search field
+    pub fn is_typing(&self) -> bool {
+        self.typing
+    }
+
+    pub fn stop_typing(&mut self) {
+        self.typing = false;
+    }
+
+    /// Items of the creative palette that are shown after filtering and scrolling
+    fn palette_page(&self) -> Vec<Item> {
+        creative_palette(&self.search)
+            .into_iter()
+            .skip(self.scroll * HOTBAR_SLOTS)
+            .take(PALETTE_SLOTS)
+            .collect()
+    }
+}
+
+/// Slot of the inventory or cell of the crafting grid shown on the screen, its children are the
+/// thumbnail and the count
+struct InventorySlot(usize);
+/// Output of the recipe matching the crafting grid, its children are the thumbnail and the count
+struct CraftingResult;
+/// Slot of the creative palette on the current page, its child is the thumbnail
+struct PaletteSlot(usize);
+struct SlotThumb;
+struct SlotCount;
+struct SearchBox;
+struct SearchText;
+/// Part of the screen that is only visible while it is open
+struct ScreenPart;
+/// Thumbnail of the dragged items that follows the cursor
+struct HeldItem;
+
+/// Position of a slot relative to the top left corner of the screen's panel. The crafting grid is
+/// shown above the main inventory, which is shown above the hotbar
+fn slot_position(slot: usize) -> Vec2 {
+    let (row, column, gaps) = if slot < HOTBAR_SLOTS {
+        (GRID_SIZE + ROWS - 1, slot, 2)
+    } else if slot < SLOTS {
+        let slot = slot - HOTBAR_SLOTS;
+        (GRID_SIZE + slot / HOTBAR_SLOTS, slot % HOTBAR_SLOTS, 1)
+    } else {
+        let cell = slot - SLOTS;
+        (cell / GRID_SIZE, GRID_COLUMN + cell % GRID_SIZE, 0)
+    };
+    Vec2::new(
+        PADDING + column as f32 * SLOT_WIDTH,
+        PADDING + row as f32 * SLOT_WIDTH + gaps as f32 * PADDING,
+    )
+}
+
+fn setup_inventory_screen(
+    mut commands: Commands,
+    config: Res<CobbleConfig>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    handles: Res<Handles>,
+) {
+    let hidden = Visible {
+        is_visible: false,
+        is_transparent: true,
+    };
+    let panel_size = Size::new(
+        Val::Px(HOTBAR_SLOTS as f32 * SLOT_WIDTH + 2.0 * PADDING),
+        Val::Px((GRID_SIZE + ROWS) as f32 * SLOT_WIDTH + 4.0 * PADDING),
+    );
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                // The palette is stacked on top of the inventory
+                flex_direction: FlexDirection::Column,
+                ..full_screen()
+            },
+            material: materials.add(SCREEN_COLOR.into()),
+            visible: hidden.clone(),
+            ..Default::default()
+        })
+        .insert(ScreenPart)
+        .with_children(|parent| {
+            let panel_material = materials.add(PANEL_COLOR.into());
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: panel_size,
+                        margin: Rect::all(Val::Px(PADDING / 2.0)),
+                        ..Default::default()
+                    },
+                    material: panel_material.clone(),
+                    visible: hidden.clone(),
+                    ..Default::default()
+                })
+                .insert(ScreenPart)
+                .with_children(|parent| {
+                    for slot in 0..ALL_SLOTS {
+                        let position = slot_position(slot);
+                        spawn_slot(parent, position, InventorySlot(slot), &handles, &hidden);
+                    }
+                    let result = Vec2::new(
+                        PADDING + RESULT_COLUMN as f32 * SLOT_WIDTH,
+                        PADDING + (GRID_SIZE / 2) as f32 * SLOT_WIDTH,
+                    );
+                    spawn_slot(parent, result, CraftingResult, &handles, &hidden);
+                });
+            if config.game.creative {
+                let search_material = materials.add(Color::rgba(0.0, 0.0, 0.0, 0.6).into());
+                spawn_palette(parent, panel_material, search_material, &handles, &hidden);
+            }
+        });
+    commands
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Px(0.8 * SLOT_WIDTH), Val::Px(0.8 * SLOT_WIDTH)),
+                ..Default::default()
+            },
+            visible: hidden,
+            ..Default::default()
+        })
+        .insert(HeldItem);
+}
+
+fn spawn_slot(
+    parent: &mut ChildBuilder,
+    position: Vec2,
+    marker: impl Component,
+    handles: &Handles,
+    hidden: &Visible,
+) {
+    parent
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                size: Size::new(Val::Px(SLOT_WIDTH), Val::Px(SLOT_WIDTH)),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..absolute(position.x, position.y)
+            },
+            material: handles.slot(false),
+            visible: hidden.clone(),
+            ..Default::default()
+        })
+        .insert_bundle((marker, ScreenPart))
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(80.0), Val::Percent(80.0)),
+                        ..Default::default()
+                    },
+                    visible: hidden.clone(),
+                    ..Default::default()
+                })
+                .insert(SlotThumb);
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            right: Val::Px(4.0),
+                            bottom: Val::Px(2.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: text(handles, "", 16.0),
+                    visible: hidden.clone(),
+                    ..Default::default()
+                })
+                .insert(SlotCount);
+        });
+}
+
+/// Panel of the creative palette with the search field above a grid of items
+fn spawn_palette(
+    parent: &mut ChildBuilder,
+    panel_material: Handle<ColorMaterial>,
+    search_material: Handle<ColorMaterial>,
+    handles: &Handles,
+    hidden: &Visible,
+) {
+    let width = HOTBAR_SLOTS as f32 * SLOT_WIDTH;
+    parent
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(
+                    Val::Px(width + 2.0 * PADDING),
+                    Val::Px(PALETTE_ROWS as f32 * SLOT_WIDTH + SEARCH_HEIGHT + 3.0 * PADDING),
+                ),
+                margin: Rect::all(Val::Px(PADDING / 2.0)),
+                ..Default::default()
+            },
+            material: panel_material,
+            visible: hidden.clone(),
+            ..Default::default()
+        })
+        .insert(ScreenPart)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(width), Val::Px(SEARCH_HEIGHT)),
+                        align_items: AlignItems::Center,
+                        padding: Rect::all(Val::Px(6.0)),
+                        ..absolute(PADDING, PADDING)
+                    },
+                    material: search_material,
+                    visible: hidden.clone(),
+                    ..Default::default()
+                })
+                .insert_bundle((SearchBox, ScreenPart))
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: text(handles, "", 18.0),
+                            visible: hidden.clone(),
+                            ..Default::default()
+                        })
+                        .insert_bundle((SearchText, ScreenPart));
+                });
+            for index in 0..PALETTE_SLOTS {
+                let (row, column) = (index / HOTBAR_SLOTS, index % HOTBAR_SLOTS);
+                parent
+                    .spawn_bundle(ImageBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(SLOT_WIDTH), Val::Px(SLOT_WIDTH)),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            ..absolute(
+                                PADDING + column as f32 * SLOT_WIDTH,
+                                2.0 * PADDING + SEARCH_HEIGHT + row as f32 * SLOT_WIDTH,
+                            )
+                        },
+                        material: handles.slot(false),
+                        visible: hidden.clone(),
+                        ..Default::default()
+                    })
+                    .insert_bundle((PaletteSlot(index), ScreenPart))
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(ImageBundle {
+                                style: Style {
+                                    size: Size::new(Val::Percent(80.0), Val::Percent(80.0)),
+                                    ..Default::default()
+                                },
+                                visible: hidden.clone(),
+                                ..Default::default()
+                            })
+                            .insert(SlotThumb);
+                    });
+            }
+        });
+}
+
+/// Queries of the parts of the screen that react to the mouse
+#[derive(SystemParam)]
+struct ScreenNodes<'a> {
+    windows: Res<'a, Windows>,
+    slots: Query<
+        'a,
+        (
+            &'static Node,
+            &'static GlobalTransform,
+            &'static InventorySlot,
+        ),
+    >,
+    palette: Query<
+        'a,
+        (
+            &'static Node,
+            &'static GlobalTransform,
+            &'static PaletteSlot,
+        ),
+    >,
+    search: Query<'a, (&'static Node, &'static GlobalTransform), With<SearchBox>>,
+    result: Query<'a, (&'static Node, &'static GlobalTransform), With<CraftingResult>>,
+}
+
+/// Part of the screen under the cursor
+enum Hovered {
+    Slot(usize),
+    Palette(usize),
+    Search,
+    Result,
+}
+
+impl<'a> ScreenNodes<'a> {
+    fn hovered(&self) -> Option<Hovered> {
+        let cursor = self.windows.get_primary()?.cursor_position()?;
+        let slot = self
+            .slots
+            .iter()
+            .find(|(node, transform, _)| contains(node, transform, cursor))
+            .map(|(_, _, InventorySlot(slot))| Hovered::Slot(*slot));
+        let palette = || {
+            self.palette
+                .iter()
+                .find(|(node, transform, _)| contains(node, transform, cursor))
+                .map(|(_, _, PaletteSlot(index))| Hovered::Palette(*index))
+        };
+        let search = || {
+            self.search
+                .iter()
+                .find(|(node, transform)| contains(node, transform, cursor))
+                .map(|_| Hovered::Search)
+        };
+        let result = || {
+            self.result
+                .iter()
+                .find(|(node, transform)| contains(node, transform, cursor))
+                .map(|_| Hovered::Result)
+        };
+        slot.or_else(palette).or_else(search).or_else(result)
+    }
+}
+
+/// Drag items from one slot onto another to merge or swap them, drag with the right button to
+/// move half of a stack into an empty slot, and click with shift held to move items between the
+/// hotbar and the main inventory. Items dragged from the creative palette fill a slot with an
+/// infinite stack. Clicking the crafting result crafts it once, with shift held up to a stack
+fn handle_clicks(
+    mut screen: ResMut<InventoryScreen>,
+    mut inventory: ResMut<Inventory>,
+    recipes: Res<RecipeBook>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    nodes: ScreenNodes,
+) {
+    if !screen.open {
+        return;
+    }
+    let hovered = nodes.hovered();
+
+    if let Some(drag) = screen.drag {
+        if buttons.just_released(drag.button) {
+            screen.drag = None;
+            match (hovered, drag.from) {
+                (Some(Hovered::Slot(to)), DragSource::Palette(item)) => {
+                    *inventory.slot_mut(to) = Slot::Infinite(item);
+                }
+                (Some(Hovered::Slot(to)), DragSource::Slot(from)) => {
+                    if drag.button == MouseButton::Right {
+                        inventory.split(from, to);
+                    } else {
+                        inventory.merge(from, to);
+                    }
+                }
+                _ => (),
+            }
+        }
+        return;
+    }
+
+    let shift = keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift);
+    if buttons.just_pressed(MouseButton::Left) {
+        screen.typing = matches!(hovered, Some(Hovered::Search));
+    }
+    match hovered {
+        Some(Hovered::Slot(slot)) if inventory.slot(slot) != Slot::Empty => {
+            if buttons.just_pressed(MouseButton::Left) {
+                if shift {
+                    inventory.quick_move(slot);
+                } else {
+                    screen.drag = Some(Drag {
+                        from: DragSource::Slot(slot),
+                        button: MouseButton::Left,
+                    });
+                }
+            } else if buttons.just_pressed(MouseButton::Right)
+                && inventory
+                    .slot(slot)
+                    .count()
+                    .map_or(false, |count| count > 1)
+            {
+                screen.drag = Some(Drag {
+                    from: DragSource::Slot(slot),
+                    button: MouseButton::Right,
+                });
+            }
+        }
+        Some(Hovered::Palette(index)) if buttons.just_pressed(MouseButton::Left) => {
+            if let Some(item) = screen.palette_page().get(index).copied() {
+                if shift {
+                    // Into the first empty slot of the hotbar, or the active one if it is full
+                    let to = (0..HOTBAR_SLOTS)
+                        .find(|slot| inventory.slots[*slot] == Slot::Empty)
+                        .unwrap_or_else(|| inventory.current_slot());
+                    inventory.slots[to] = Slot::Infinite(item);
+                } else {
+                    screen.drag = Some(Drag {
+                        from: DragSource::Palette(item),
+                        button: MouseButton::Left,
+                    });
+                }
+            }
+        }
+        Some(Hovered::Result) if buttons.just_pressed(MouseButton::Left) => {
+            if let Some(output) = inventory.craft(&recipes) {
+                let mut crafted = output.count;
+                while shift && crafted < output.item.max_stack() {
+                    match inventory.craft(&recipes) {
+                        Some(output) => crafted += output.count,
+                        None => break,
+                    }
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Type into the palette's search field while it is focused
+fn handle_search(mut screen: ResMut<InventoryScreen>, mut input: TextInput) {
+    let search = match input.edit(screen.typing, &screen.search) {
+        Some(search) => search,
+        None => return,
+    };
+    if input.finished() {
+        screen.typing = false;
+    }
+    if search != screen.search {
+        screen.search = search;
+        screen.scroll = 0;
+    }
+}
+
+/// Scroll through the rows of the palette with the mouse wheel
+fn scroll_palette(mut screen: ResMut<InventoryScreen>, mut wheel: EventReader<MouseWheel>) {
+    let delta: f32 = wheel.iter().map(|event| event.y).sum();
+    if !screen.open || delta == 0.0 {
+        return;
+    }
+    let rows = (creative_palette(&screen.search).len() + HOTBAR_SLOTS - 1) / HOTBAR_SLOTS;
+    let max = rows.saturating_sub(PALETTE_ROWS);
+    screen.scroll = if delta > 0.0 {
+        screen.scroll.saturating_sub(1)
+    } else {
+        (screen.scroll + 1).min(max)
+    };
+}
+
+fn update_screen_visibility(
+    screen: Res<InventoryScreen>,
+    mut query: Query<&mut Visible, With<ScreenPart>>,
+) {
+    if !screen.is_changed() {
+        return;
+    }
+    for mut visible in query.iter_mut() {
+        visible.is_visible = screen.open;
+    }
+}
+
+type ThumbQuery<'a> = Query<
+    'a,
+    (&'static mut Handle<ColorMaterial>, &'static mut Visible),
+    (
+        With<SlotThumb>,
+        Without<InventorySlot>,
+        Without<CraftingResult>,
+        Without<SlotCount>,
+    ),
+>;
+type CountQuery<'a> = Query<'a, (&'static mut Text, &'static mut Visible), With<SlotCount>>;
+
+/// Show the thumbnail and the count of a stack in the children of a slot. Instead of a count,
+/// worn tools show how much of their durability is left
+fn show_stack(
+    stack: Slot,
+    children: &Children,
+    handles: &Handles,
+    thumb_query: &mut ThumbQuery,
+    count_query: &mut CountQuery,
+) {
+    if let Some(Ok((mut thumb, mut visible))) =
+        children.first().map(|child| thumb_query.get_mut(*child))
+    {
+        match stack.item() {
+            Some(item) => {
+                *thumb = handles.thumb(item);
+                visible.is_visible = true;
+            }
+            None => visible.is_visible = false,
+        }
+    }
+    if let Some(Ok((mut text, mut visible))) =
+        children.get(1).map(|child| count_query.get_mut(*child))
+    {
+        match (stack.durability(), stack.count()) {
+            (Some(durability), _) if durability < 1.0 => {
+                text.sections[0].value = format!("{:.0}%", durability * 100.0);
+                visible.is_visible = true;
+            }
+            (None, Some(count)) if count > 1 => {
+                text.sections[0].value = count.to_string();
+                visible.is_visible = true;
+            }
+            _ => visible.is_visible = false,
+        }
+    }
+}
+
+fn update_screen_slots(
+    screen: Res<InventoryScreen>,
+    inventory: Res<Inventory>,
+    handles: Res<Handles>,
+    mut slot_query: Query<(&mut Handle<ColorMaterial>, &InventorySlot, &Children)>,
+    mut thumb_query: ThumbQuery,
+    mut count_query: CountQuery,
+) {
+    if !screen.open {
+        for mut visible in thumb_query
+            .iter_mut()
+            .map(|(_, visible)| visible)
+            .chain(count_query.iter_mut().map(|(_, visible)| visible))
+        {
+            visible.is_visible = false;
+        }
+        return;
+    }
+    for (mut material, InventorySlot(slot), children) in slot_query.iter_mut() {
+        let dragged = screen
+            .drag
+            .map_or(false, |drag| drag.from == DragSource::Slot(*slot));
+        *material = handles.slot(*slot == inventory.current_slot() || dragged);
+        let stack = inventory.slot(*slot);
+        show_stack(
+            stack,
+            children,
+            &handles,
+            &mut thumb_query,
+            &mut count_query,
+        );
+    }
+}
+
+fn update_crafting_result(
+    screen: Res<InventoryScreen>,
+    inventory: Res<Inventory>,
+    recipes: Res<RecipeBook>,
+    handles: Res<Handles>,
+    result_query: Query<&Children, With<CraftingResult>>,
+    mut thumb_query: ThumbQuery,
+    mut count_query: CountQuery,
+) {
+    if !screen.open {
+        return;
+    }
+    let stack = recipes
+        .find(&inventory.crafting_grid(), GRID_SIZE)
+        .map_or(Slot::Empty, |recipe| {
+            Slot::finite(recipe.output.item, recipe.output.count)
+        });
+    for children in result_query.iter() {
+        show_stack(
+            stack,
+            children,
+            &handles,
+            &mut thumb_query,
+            &mut count_query,
+        );
+    }
+}
+
+fn update_palette(
+    screen: Res<InventoryScreen>,
+    handles: Res<Handles>,
+    slot_query: Query<(&PaletteSlot, &Children)>,
+    mut thumb_query: Query<(&mut Handle<ColorMaterial>, &mut Visible), With<SlotThumb>>,
+    mut search_query: Query<&mut Text, With<SearchText>>,
+) {
+    if !screen.open {
+        return;
+    }
+    if let Ok(mut text) = search_query.single_mut() {
+        text.sections[0].value = match (screen.search.is_empty(), screen.typing) {
+            (true, false) => "Search...".to_string(),
+            (_, true) => format!("{}_", screen.search),
+            (false, false) => screen.search.clone(),
+        };
+    }
+    let page = screen.palette_page();
+    for (PaletteSlot(index), children) in slot_query.iter() {
+        if let Some(Ok((mut thumb, mut visible))) =
+            children.first().map(|child| thumb_query.get_mut(*child))
+        {
+            match page.get(*index) {
+                Some(block) => {
+                    *thumb = handles.thumb(*block);
+                    visible.is_visible = true;
+                }
+                None => visible.is_visible = false,
+            }
+        }
+    }
+}
+
+fn update_held_item(
+    screen: Res<InventoryScreen>,
+    inventory: Res<Inventory>,
+    handles: Res<Handles>,
+    windows: Res<Windows>,
+    mut query: Query<(&mut Style, &mut Handle<ColorMaterial>, &mut Visible), With<HeldItem>>,
+) {
+    if let Ok((mut style, mut material, mut visible)) = query.single_mut() {
+        let cursor = windows
+            .get_primary()
+            .and_then(|window| window.cursor_position());
+        let held = screen
+            .drag
+            .and_then(|drag| match drag.from {
+                DragSource::Slot(slot) => inventory.item(slot),
+                DragSource::Palette(item) => Some(item),
+            })
+            .zip(cursor);
+        match held {
+            Some((item, cursor)) => {
+                *material = handles.thumb(item);
+                style.position.left = Val::Px(cursor.x - 0.4 * SLOT_WIDTH);
+                style.position.bottom = Val::Px(cursor.y - 0.4 * SLOT_WIDTH);
+                visible.is_visible = true;
+            }
+            None => visible.is_visible = false,
+        }
+    }
+}
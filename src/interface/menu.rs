@@ -0,0 +1,527 @@
+//! Main menu to create, play and delete worlds, and the pause menu shown on top of a world
+use bevy::{app::AppExit, input::mouse::MouseWheel, prelude::*};
+
+use crate::{
+    config::CobbleConfig,
+    interface::{
+        absolute, contains,
+        controller::{set_grab_cursor, ControllerLabels},
+        controls::ControlsScreen,
+        full_screen,
+        overlay::Handles,
+        text, TextInput, ACTIVE_COLOR, BUTTON_COLOR, PANEL_COLOR, SCREEN_COLOR,
+    },
+    inventory::Inventory,
+    save::{self, PlayerSave, SavedWorld, WorldInfo, WorldSave},
+    world::Seed,
+    AppState,
+};
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MainMenu>()
+            .add_system_set(
+                SystemSet::on_exit(AppState::Loading).with_system(setup_menu_materials.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::MainMenu).with_system(enter_main_menu.system()),
+            )
+            .add_system(
+                handle_menu_clicks
+                    .system()
+                    .label(MenuLabels::Clicks)
+                    .after(ControllerLabels::CursorGrab),
+            )
+            .add_system(handle_menu_typing.system().before(MenuLabels::Clicks))
+            .add_system(scroll_worlds.system().before(MenuLabels::Clicks))
+            .add_system(show_menu.system().after(MenuLabels::Clicks));
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug, SystemLabel)]
+enum MenuLabels {
+    Clicks,
+}
+
+/// Worlds shown at once in the main menu, more are reached by scrolling
+const LIST_ROWS: usize = 8;
+const PANEL_WIDTH: f32 = 460.0;
+const ROW_HEIGHT: f32 = 30.0;
+/// Space around the panel's content and between its rows in pixels
+const PADDING: f32 = 12.0;
+/// Name of a world created without typing one
+const NEW_WORLD: &str = "New World";
+
+/// State of the main menu, the worlds are listed again each time it is entered
+#[derive(Default)]
+pub struct MainMenu {
+    worlds: Vec<SavedWorld>,
+    selected: Option<usize>,
+    /// First world of the list that is shown
+    scroll: usize,
+    name: String,
+    seed: String,
+    /// Field the player is typing into
+    typing: Option<Field>,
+    /// Whether the selected world is deleted on the next click on delete
+    confirm_delete: bool,
+    message: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Name,
+    Seed,
+}
+
+/// Part of a menu that reacts to clicks
+#[derive(Clone, Copy, PartialEq)]
+enum MenuButton {
+    /// World at the index of the list of worlds
+    World(usize),
+    Play,
+    Delete,
+    Field(Field),
+    Create,
+    Quit,
+    Resume,
+    Settings,
+    SaveAndQuit,
+}
+
+/// Menu that is shown, if any
+#[derive(Clone, Copy, PartialEq)]
+enum Menu {
+    Main,
+    Pause,
+}
+
+/// Root node of the shown menu, which is despawned with its children to rebuild the menu
+struct MenuRoot;
+
+struct MenuMaterials {
+    background: Handle<ColorMaterial>,
+    /// Background of the pause menu, through which the world is still seen
+    overlay: Handle<ColorMaterial>,
+    panel: Handle<ColorMaterial>,
+    button: Handle<ColorMaterial>,
+    active: Handle<ColorMaterial>,
+}
+
+fn setup_menu_materials(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands.insert_resource(MenuMaterials {
+        background: materials.add(Color::rgb(0.1, 0.12, 0.12).into()),
+        overlay: materials.add(SCREEN_COLOR.into()),
+        panel: materials.add(PANEL_COLOR.into()),
+        button: materials.add(BUTTON_COLOR.into()),
+        active: materials.add(ACTIVE_COLOR.into()),
+    });
+}
+
+/// List the saved worlds and release the cursor, which is grabbed while playing
+fn enter_main_menu(
+    config: Res<CobbleConfig>,
+    mut menu: ResMut<MainMenu>,
+    mut windows: ResMut<Windows>,
+) {
+    let saves = save::saves_directory(&config.game);
+    *menu = MainMenu {
+        worlds: saves.as_deref().map_or_else(Vec::new, save::list_worlds),
+        ..Default::default()
+    };
+    if saves.is_none() {
+        menu.message = "Worlds are not saved".to_owned();
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        set_grab_cursor(window, false);
+    }
+}
+
+/// Load the player saved with `world` and enter it, the player starts at the spawn with full
+/// health and stamina in a new world
+fn play_world(
+    commands: &mut Commands,
+    state: &mut State<AppState>,
+    config: &CobbleConfig,
+    mut world: WorldSave,
+    seed: u32,
+) {
+    let player = world
+        .load_player()
+        .unwrap_or_else(|| PlayerSave::new(Inventory::preset(config.game.creative)));
+    commands.insert_resource(player.inventory.clone());
+    commands.insert_resource(player.health.clone());
+    commands.insert_resource(player.stamina.clone());
+    commands.insert_resource(player);
+    commands.insert_resource(world);
+    commands.insert_resource(Seed(seed));
+    if let Err(e) = state.set(AppState::InGame) {
+        error!("Failed to enter the world: {:?}", e);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_menu_clicks(
+    mut commands: Commands,
+    mut menu: ResMut<MainMenu>,
+    mut state: ResMut<State<AppState>>,
+    mut controls: ResMut<ControlsScreen>,
+    mut windows: ResMut<Windows>,
+    mut exit: EventWriter<AppExit>,
+    config: Res<CobbleConfig>,
+    mouse: Res<Input<MouseButton>>,
+    buttons: Query<(&Node, &GlobalTransform, &MenuButton)>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+    let clicked = window.cursor_position().and_then(|cursor| {
+        buttons
+            .iter()
+            .find(|(node, transform, _)| contains(node, transform, cursor))
+            .map(|(_, _, button)| *button)
+    });
+    let clicked = match clicked {
+        Some(clicked) => clicked,
+        None => {
+            if menu.typing.is_some() {
+                menu.typing = None;
+            }
+            return;
+        }
+    };
+    if *state.current() == AppState::MainMenu {
+        menu.typing = None;
+        menu.message.clear();
+        if clicked != MenuButton::Delete {
+            menu.confirm_delete = false;
+        }
+    }
+    match clicked {
+        MenuButton::World(index) => menu.selected = Some(index),
+        MenuButton::Play => {
+            if let Some(world) = menu.selected.and_then(|index| menu.worlds.get(index)) {
+                let seed = world.info.seed;
+                let world = WorldSave::new(world);
+                play_world(&mut commands, &mut state, &config, world, seed);
+            }
+        }
+        MenuButton::Delete => {
+            let world = match menu.selected.and_then(|index| menu.worlds.get(index)) {
+                Some(world) => world.clone(),
+                None => return,
+            };
+            if !menu.confirm_delete {
+                menu.confirm_delete = true;
+                menu.message = format!("Click delete again to delete {}", world.info.name);
+                return;
+            }
+            menu.confirm_delete = false;
+            menu.selected = None;
+            match save::delete_world(&world) {
+                Ok(()) => menu.message = format!("Deleted {}", world.info.name),
+                Err(e) => menu.message = format!("Failed to delete {}: {}", world.info.name, e),
+            }
+            if let Some(saves) = world.directory.parent() {
+                menu.worlds = save::list_worlds(saves);
+            }
+            menu.scroll = menu.scroll.min(menu.worlds.len().saturating_sub(LIST_ROWS));
+        }
+        MenuButton::Field(field) => menu.typing = Some(field),
+        MenuButton::Create => {
+            let info = WorldInfo {
+                name: match menu.name.trim() {
+                    "" => NEW_WORLD.to_owned(),
+                    name => name.to_owned(),
+                },
+                seed: WorldInfo::parse_seed(&menu.seed),
+            };
+            let seed = info.seed;
+            // Without a saves directory the world is only played, not saved
+            let world = match save::saves_directory(&config.game) {
+                Some(saves) => match save::create_world(&saves, info) {
+                    Ok(world) => WorldSave::new(&world),
+                    Err(e) => {
+                        menu.message = format!("Failed to create the world: {}", e);
+                        return;
+                    }
+                },
+                None => WorldSave::default(),
+            };
+            play_world(&mut commands, &mut state, &config, world, seed);
+        }
+        MenuButton::Quit => exit.send(AppExit),
+        MenuButton::Resume => {
+            if state.pop().is_ok() {
+                set_grab_cursor(window, true);
+            }
+        }
+        MenuButton::Settings => controls.set_open(true),
+        // The player is saved when leaving the world, see `save::save_on_leave`
+        MenuButton::SaveAndQuit => {
+            if let Err(e) = state.replace(AppState::MainMenu) {
+                error!("Failed to leave the world: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Type into the focused field of the main menu
+fn handle_menu_typing(mut menu: ResMut<MainMenu>, mut input: TextInput) {
+    let field = menu.typing;
+    let current = match field {
+        Some(Field::Name) => menu.name.as_str(),
+        Some(Field::Seed) => menu.seed.as_str(),
+        None => "",
+    };
+    let text = match input.edit(field.is_some(), current) {
+        Some(text) => text,
+        None => return,
+    };
+    if input.finished() {
+        menu.typing = None;
+    }
+    // Only changes rebuild the menu
+    match field {
+        Some(Field::Name) if menu.name != text => menu.name = text,
+        Some(Field::Seed) if menu.seed != text => menu.seed = text,
+        _ => (),
+    }
+}
+
+/// Scroll through the list of worlds with the mouse wheel
+fn scroll_worlds(
+    state: Res<State<AppState>>,
+    mut menu: ResMut<MainMenu>,
+    mut wheel: EventReader<MouseWheel>,
+) {
+    let delta: f32 = wheel.iter().map(|event| event.y).sum();
+    if *state.current() != AppState::MainMenu || delta == 0.0 {
+        return;
+    }
+    let max = menu.worlds.len().saturating_sub(LIST_ROWS);
+    menu.scroll = if delta > 0.0 {
+        menu.scroll.saturating_sub(1)
+    } else {
+        (menu.scroll + 1).min(max)
+    };
+}
+
+/// Rebuild the menu when it changes or another menu is shown. The pause menu makes way for the
+/// controls screen while it is open
+#[allow(clippy::too_many_arguments)]
+fn show_menu(
+    mut commands: Commands,
+    state: Res<State<AppState>>,
+    menu: Res<MainMenu>,
+    controls: Res<ControlsScreen>,
+    handles: Res<Handles>,
+    materials: Option<Res<MenuMaterials>>,
+    roots: Query<Entity, With<MenuRoot>>,
+    mut shown: Local<Option<Menu>>,
+) {
+    let materials = match materials {
+        Some(materials) => materials,
+        None => return,
+    };
+    let wanted = match state.current() {
+        AppState::MainMenu => Some(Menu::Main),
+        AppState::Paused if !controls.is_open() => Some(Menu::Pause),
+        _ => None,
+    };
+    if wanted == *shown && !(wanted == Some(Menu::Main) && menu.is_changed()) {
+        return;
+    }
+    *shown = wanted;
+    for root in roots.iter() {
+        commands.entity(root).despawn_recursive();
+    }
+    let builder = MenuBuilder {
+        handles: &handles,
+        materials: &materials,
+    };
+    match wanted {
+        Some(Menu::Main) => builder.main_menu(&mut commands, &menu),
+        Some(Menu::Pause) => builder.pause_menu(&mut commands),
+        None => (),
+    }
+}
+
+/// Spawns the nodes of the menus, all positioned relative to the top left corner of their panel
+struct MenuBuilder<'a> {
+    handles: &'a Handles,
+    materials: &'a MenuMaterials,
+}
+
+impl<'a> MenuBuilder<'a> {
+    /// Full screen background with a centered panel of `height` pixels, the panel is passed to
+    /// `content`
+    fn panel(
+        &self,
+        commands: &mut Commands,
+        background: Handle<ColorMaterial>,
+        height: f32,
+        content: impl FnOnce(&mut ChildBuilder),
+    ) {
+        commands
+            .spawn_bundle(NodeBundle {
+                style: full_screen(),
+                material: background,
+                ..Default::default()
+            })
+            .insert(MenuRoot)
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(PANEL_WIDTH), Val::Px(height)),
+                            ..Default::default()
+                        },
+                        material: self.materials.panel.clone(),
+                        ..Default::default()
+                    })
+                    .with_children(content);
+            });
+    }
+
+    fn label(&self, parent: &mut ChildBuilder, value: &str, font_size: f32, top: f32) {
+        parent.spawn_bundle(TextBundle {
+            style: absolute(PADDING, top),
+            text: text(self.handles, value, font_size),
+            ..Default::default()
+        });
+    }
+
+    /// Button of `width` pixels at `left` and `top`
+    fn button(
+        &self,
+        parent: &mut ChildBuilder,
+        (left, top, width): (f32, f32, f32),
+        label: &str,
+        button: MenuButton,
+        active: bool,
+    ) {
+        parent
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(width), Val::Px(ROW_HEIGHT - 4.0)),
+                    align_items: AlignItems::Center,
+                    padding: Rect::all(Val::Px(6.0)),
+                    ..absolute(left, top)
+                },
+                material: if active {
+                    self.materials.active.clone()
+                } else {
+                    self.materials.button.clone()
+                },
+                ..Default::default()
+            })
+            .insert(button)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle {
+                    text: text(self.handles, label, 18.0),
+                    ..Default::default()
+                });
+            });
+    }
+
+    fn main_menu(&self, commands: &mut Commands, menu: &MainMenu) {
+        let width = PANEL_WIDTH - 2.0 * PADDING;
+        let half = (width - PADDING) / 2.0;
+        let row = |n: usize| 2.0 * PADDING + 40.0 + n as f32 * ROW_HEIGHT;
+        let height = row(LIST_ROWS + 6) + 2.0 * PADDING;
+        self.panel(
+            commands,
+            self.materials.background.clone(),
+            height,
+            |parent| {
+                self.label(parent, "Cobble", 32.0, PADDING);
+                if menu.worlds.is_empty() {
+                    self.label(parent, "No worlds yet", 18.0, row(0));
+                }
+                for (n, (index, world)) in menu
+                    .worlds
+                    .iter()
+                    .enumerate()
+                    .skip(menu.scroll)
+                    .take(LIST_ROWS)
+                    .enumerate()
+                {
+                    let selected = menu.selected == Some(index);
+                    let label = format!("{} (seed {})", world.info.name, world.info.seed);
+                    let position = (PADDING, row(n), width);
+                    self.button(parent, position, &label, MenuButton::World(index), selected);
+                }
+                let delete = if menu.confirm_delete {
+                    "Delete?"
+                } else {
+                    "Delete"
+                };
+                let actions = row(LIST_ROWS);
+                self.button(
+                    parent,
+                    (PADDING, actions, half),
+                    "Play",
+                    MenuButton::Play,
+                    false,
+                );
+                let position = (2.0 * PADDING + half, actions, half);
+                self.button(parent, position, delete, MenuButton::Delete, false);
+
+                self.label(parent, "New world", 22.0, row(LIST_ROWS + 1) + 4.0);
+                let fields = [
+                    (Field::Name, "Name", &menu.name, NEW_WORLD),
+                    (Field::Seed, "Seed", &menu.seed, "random"),
+                ];
+                for (n, (field, label, value, empty)) in fields.iter().enumerate() {
+                    let typing = menu.typing == Some(*field);
+                    let value = match (value.is_empty(), typing) {
+                        (_, true) => format!("{}: {}_", label, value),
+                        (true, false) => format!("{}: {}", label, empty),
+                        (false, false) => format!("{}: {}", label, value),
+                    };
+                    let position = (PADDING, row(LIST_ROWS + 2 + n), width);
+                    self.button(parent, position, &value, MenuButton::Field(*field), typing);
+                }
+                let bottom = row(LIST_ROWS + 4);
+                self.button(
+                    parent,
+                    (PADDING, bottom, half),
+                    "Create",
+                    MenuButton::Create,
+                    false,
+                );
+                let position = (2.0 * PADDING + half, bottom, half);
+                self.button(parent, position, "Quit", MenuButton::Quit, false);
+                self.label(parent, &menu.message, 16.0, row(LIST_ROWS + 5) + 4.0);
+            },
+        );
+    }
+
+    fn pause_menu(&self, commands: &mut Commands) {
+        let width = PANEL_WIDTH - 2.0 * PADDING;
+        let row = |n: usize| 2.0 * PADDING + 40.0 + n as f32 * ROW_HEIGHT;
+        self.panel(
+            commands,
+            self.materials.overlay.clone(),
+            row(3) + PADDING,
+            |parent| {
+                self.label(parent, "Paused", 32.0, PADDING);
+                let buttons = [
+                    ("Resume", MenuButton::Resume),
+                    ("Settings", MenuButton::Settings),
+                    ("Save and quit to menu", MenuButton::SaveAndQuit),
+                ];
+                for (n, (label, button)) in buttons.iter().enumerate() {
+                    self.button(parent, (PADDING, row(n), width), label, *button, false);
+                }
+            },
+        );
+    }
+}
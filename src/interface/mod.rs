@@ -1,3 +1,121 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::AppState;
+use controls::ControlsScreen;
+use inventory_screen::InventoryScreen;
+use overlay::Handles;
+
 pub mod controller;
+pub mod controls;
+pub mod inventory_screen;
+pub mod menu;
 pub mod overlay;
 pub mod selection;
+
+/// Darkens the game behind the screens and the pause menu
+pub const SCREEN_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.4);
+pub const PANEL_COLOR: Color = Color::rgba(0.15, 0.15, 0.15, 0.9);
+/// Rows and buttons of the screens and menus, highlighted while they are active
+pub const BUTTON_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.3);
+pub const ACTIVE_COLOR: Color = Color::rgba(0.45, 0.35, 0.1, 0.8);
+/// Longest text that can be typed into a field
+pub const MAX_TEXT_LENGTH: usize = 32;
+
+/// Whether `point` in window coordinates lies on the UI node
+pub fn contains(node: &Node, transform: &GlobalTransform, point: Vec2) -> bool {
+    let min = transform.translation.truncate() - node.size / 2.0;
+    let max = transform.translation.truncate() + node.size / 2.0;
+    (min.x..max.x).contains(&point.x) && (min.y..max.y).contains(&point.y)
+}
+
+/// Screens that take the input away from the game while they are open, the menus are open
+/// whenever the game is not in the `InGame` state
+#[derive(SystemParam)]
+pub struct Screens<'a> {
+    pub inventory: Res<'a, InventoryScreen>,
+    pub controls: Res<'a, ControlsScreen>,
+    pub state: Res<'a, State<AppState>>,
+}
+
+impl<'a> Screens<'a> {
+    pub fn any_open(&self) -> bool {
+        self.inventory.is_open()
+            || self.controls.is_open()
+            || *self.state.current() != AppState::InGame
+    }
+}
+
+/// Style of a node covering the whole window with its children centered, the background of the
+/// screens and menus
+pub fn full_screen() -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::Center,
+        ..Default::default()
+    }
+}
+
+/// Style of a node at `left` and `top` pixels from the top left corner of its parent
+pub fn absolute(left: f32, top: f32) -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        position: Rect {
+            left: Val::Px(left),
+            top: Val::Px(top),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// White text in the font of the interface
+pub fn text(handles: &Handles, value: &str, font_size: f32) -> Text {
+    Text::with_section(
+        value,
+        TextStyle {
+            font: handles.font(),
+            font_size,
+            color: Color::WHITE,
+        },
+        Default::default(),
+    )
+}
+
+/// Keyboard input of a text field
+#[derive(SystemParam)]
+pub struct TextInput<'a> {
+    characters: EventReader<'a, ReceivedCharacter>,
+    keys: Res<'a, Input<KeyCode>>,
+}
+
+impl<'a> TextInput<'a> {
+    /// `text` with this frame's typing applied if its field is focused, `None` otherwise. Must be
+    /// called every frame so that keys pressed before focusing are not typed
+    pub fn edit(&mut self, focused: bool, text: &str) -> Option<String> {
+        let typed = self
+            .characters
+            .iter()
+            .map(|event| event.char)
+            .filter(|c| !c.is_control());
+        if !focused {
+            return None;
+        }
+        let mut text = text.to_owned();
+        for c in typed {
+            if text.chars().count() < MAX_TEXT_LENGTH {
+                text.push(c);
+            }
+        }
+        if self.keys.just_pressed(KeyCode::Back) {
+            text.pop();
+        }
+        Some(text)
+    }
+
+    /// Whether typing into the focused field was finished with return or escape
+    pub fn finished(&self) -> bool {
+        self.keys.just_pressed(KeyCode::Return) || self.keys.just_pressed(KeyCode::Escape)
+    }
+}
@@ -7,11 +7,20 @@ use bevy::{
     prelude::*,
 };
 
-use crate::{config::CobbleConfig, inventory::Inventory, world::BlockType, AppState};
+use crate::{
+    config::CobbleConfig,
+    health::{Health, Stamina, MAX_HEALTH, MAX_STAMINA},
+    interface::controller::UNDERWATER_COLOR,
+    inventory::Inventory,
+    item::{Item, Tool},
+    world::{BlockType, PlayerPosition},
+    AppState,
+};
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, SystemLabel)]
 pub enum OverlayLabels {
     LoadAssets,
+    Setup,
 }
 
 pub struct OverlayPlugin;
@@ -21,11 +30,14 @@ impl Plugin for OverlayPlugin {
         app.add_system_set(
             SystemSet::on_exit(AppState::Loading)
                 .with_system(setup_overlay.system())
+                .label(OverlayLabels::Setup)
                 .after(OverlayLabels::LoadAssets),
         )
         .add_system(update_fps_counter.system())
         .add_system(update_crosshair.system())
         .add_system(update_toolbar.system())
+        .add_system(update_status_bars.system())
+        .add_system(update_underwater_tint.system())
         .add_system_set(
             SystemSet::on_enter(AppState::Loading)
                 .with_system(load_assets.system())
@@ -37,6 +49,10 @@ impl Plugin for OverlayPlugin {
 
 const CROSSHAIR_SCALE: f32 = 0.0125;
 const N_SLOTS: usize = 9;
+/// Width of a toolbar slot in pixels
+const SLOT_WIDTH: f32 = 50.0;
+/// Height of the status bars above the toolbar in pixels
+const BAR_HEIGHT: f32 = 8.0;
 
 #[derive(Default, Clone)]
 pub struct Handles {
@@ -56,6 +72,16 @@ pub struct Handles {
     bricks: Handle<ColorMaterial>,
     wood: Handle<ColorMaterial>,
     leaves: Handle<ColorMaterial>,
+    ladder: Handle<ColorMaterial>,
+    vines: Handle<ColorMaterial>,
+    water: Handle<ColorMaterial>,
+
+    wooden_pickaxe: Handle<ColorMaterial>,
+    wooden_shovel: Handle<ColorMaterial>,
+    wooden_axe: Handle<ColorMaterial>,
+    stone_pickaxe: Handle<ColorMaterial>,
+    stone_shovel: Handle<ColorMaterial>,
+    stone_axe: Handle<ColorMaterial>,
 }
 
 #[cfg(not(feature = "inline_assets"))]
@@ -97,6 +123,15 @@ impl Handles {
             bricks: load_texture_material!("thumbs/bricks.png"),
             wood: load_texture_material!("thumbs/wood.png"),
             leaves: load_texture_material!("thumbs/leaves.png"),
+            ladder: load_texture_material!("thumbs/ladder.png"),
+            vines: load_texture_material!("thumbs/vines.png"),
+            water: load_texture_material!("thumbs/water.png"),
+            wooden_pickaxe: load_texture_material!("thumbs/wooden_pickaxe.png"),
+            wooden_shovel: load_texture_material!("thumbs/wooden_shovel.png"),
+            wooden_axe: load_texture_material!("thumbs/wooden_axe.png"),
+            stone_pickaxe: load_texture_material!("thumbs/stone_pickaxe.png"),
+            stone_shovel: load_texture_material!("thumbs/stone_shovel.png"),
+            stone_axe: load_texture_material!("thumbs/stone_axe.png"),
             crosshair: load_texture_material!("images/crosshair.png"),
             inactive: load_texture_material!("images/toolbar_slot.png"),
             active: load_texture_material!("images/toolbar_slot_active.png"),
@@ -141,11 +176,61 @@ impl Handles {
             bricks: load_texture_material!("assets/thumbs/bricks.png"),
             wood: load_texture_material!("assets/thumbs/wood.png"),
             leaves: load_texture_material!("assets/thumbs/leaves.png"),
+            ladder: load_texture_material!("assets/thumbs/ladder.png"),
+            vines: load_texture_material!("assets/thumbs/vines.png"),
+            water: load_texture_material!("assets/thumbs/water.png"),
+            wooden_pickaxe: load_texture_material!("assets/thumbs/wooden_pickaxe.png"),
+            wooden_shovel: load_texture_material!("assets/thumbs/wooden_shovel.png"),
+            wooden_axe: load_texture_material!("assets/thumbs/wooden_axe.png"),
+            stone_pickaxe: load_texture_material!("assets/thumbs/stone_pickaxe.png"),
+            stone_shovel: load_texture_material!("assets/thumbs/stone_shovel.png"),
+            stone_axe: load_texture_material!("assets/thumbs/stone_axe.png"),
             crosshair: load_texture_material!("assets/images/crosshair.png"),
             inactive: load_texture_material!("assets/images/toolbar_slot.png"),
             active: load_texture_material!("assets/images/toolbar_slot_active.png"),
         }
     }
+
+    /// Thumbnail of an item as it is shown in the inventory
+    pub fn thumb(&self, item: Item) -> Handle<ColorMaterial> {
+        match item {
+            Item::Block(BlockType::Dirt) => self.dirt.clone(),
+            Item::Block(BlockType::Cobble) => self.cobble.clone(),
+            Item::Block(BlockType::Grass) => self.grass.clone(),
+            Item::Block(BlockType::Planks) => self.planks.clone(),
+            Item::Block(BlockType::Sand) => self.sand.clone(),
+            Item::Block(BlockType::Bricks) => self.bricks.clone(),
+            Item::Block(BlockType::Leaves) => self.leaves.clone(),
+            Item::Block(BlockType::Wood) => self.wood.clone(),
+            Item::Block(BlockType::Gravel) => self.gravel.clone(),
+            Item::Block(BlockType::LadderNorth) => self.ladder.clone(),
+            Item::Block(BlockType::VinesNorth) => self.vines.clone(),
+            Item::Block(BlockType::Water) => self.water.clone(),
+            Item::Tool(Tool::WoodenPickaxe) => self.wooden_pickaxe.clone(),
+            Item::Tool(Tool::WoodenShovel) => self.wooden_shovel.clone(),
+            Item::Tool(Tool::WoodenAxe) => self.wooden_axe.clone(),
+            Item::Tool(Tool::StonePickaxe) => self.stone_pickaxe.clone(),
+            Item::Tool(Tool::StoneShovel) => self.stone_shovel.clone(),
+            Item::Tool(Tool::StoneAxe) => self.stone_axe.clone(),
+            _ => {
+                error!("No thumb for {:?}", item);
+                Handle::default()
+            }
+        }
+    }
+
+    /// Background of an inventory slot, highlighted if the slot is active
+    pub fn slot(&self, active: bool) -> Handle<ColorMaterial> {
+        if active {
+            self.active.clone()
+        } else {
+            self.inactive.clone()
+        }
+    }
+
+    pub fn font(&self) -> Handle<Font> {
+        self.font_mono.clone()
+    }
 }
 
 /// Iterator of critial assets that need to be loaded before InGame is entered.
@@ -204,6 +289,23 @@ fn setup_overlay(
             })
             .insert(FpsText);
     }
+    let mut tint = UNDERWATER_COLOR;
+    tint.set_a(0.35);
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                ..Default::default()
+            },
+            material: materials.add(tint.into()),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(UnderwaterTint);
     commands
         .spawn_bundle(ImageBundle {
             style: Style {
@@ -268,10 +370,48 @@ fn setup_overlay(
                     });
             }
         });
+    if !config.game.creative {
+        for (bar, color) in [
+            (StatusBar::Health, Color::rgb(0.8, 0.1, 0.1)),
+            (StatusBar::Stamina, Color::rgb(0.9, 0.6, 0.1)),
+        ]
+        .iter()
+        {
+            commands
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.5).into()),
+                    ..Default::default()
+                })
+                .insert(*bar)
+                .with_children(|parent| {
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                            ..Default::default()
+                        },
+                        material: materials.add((*color).into()),
+                        ..Default::default()
+                    });
+                });
+        }
+    }
 }
 
 struct ToolbarSlot(usize);
+/// Bar above the toolbar, its child is filled according to the respective value of the player.
+/// Health is shown above the left half of the toolbar, stamina above the right half
+#[derive(Clone, Copy)]
+enum StatusBar {
+    Health,
+    Stamina,
+}
 struct Crosshair;
+/// Colored layer over the whole view while the player's eyes are inside a fluid
+struct UnderwaterTint;
 struct FpsText;
 
 fn update_fps_counter(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text, With<FpsText>>) {
@@ -297,6 +437,15 @@ fn update_crosshair(windows: Res<Windows>, mut query: Query<&mut Style, With<Cro
     }
 }
 
+fn update_underwater_tint(
+    position: Res<PlayerPosition>,
+    mut query: Query<&mut Visible, With<UnderwaterTint>>,
+) {
+    if let Ok(mut visible) = query.single_mut() {
+        visible.is_visible = position.surroundings.head_in_fluid;
+    }
+}
+
 fn update_toolbar(
     inventory: Res<Inventory>,
     handles: ResMut<Handles>,
@@ -311,39 +460,20 @@ fn update_toolbar(
 ) {
     let window = windows.get_primary().unwrap();
 
-    let slot_width: f32 = 50.0;
-    let offset_from_bottom = window.height() * 0.025;
-    let offset_from_left = (window.width() - slot_width * N_SLOTS as f32) / 2.0;
+    let slot_width = SLOT_WIDTH;
+    let (offset_from_left, offset_from_bottom) = toolbar_offset(window);
     for (mut material, mut style, ToolbarSlot(id), children) in slot_query.iter_mut() {
         style.position.left = Val::Px(offset_from_left + *id as f32 * slot_width);
         style.position.bottom = Val::Px(offset_from_bottom);
         style.size.width = Val::Px(slot_width);
         style.size.height = Val::Px(slot_width);
-        *material = if *id == inventory.current_slot() {
-            handles.active.clone()
-        } else {
-            handles.inactive.clone()
-        };
+        *material = handles.slot(*id == inventory.current_slot());
 
         if let Some(child) = children.first() {
             if let Ok((mut block_, mut visible)) = item_query.get_mut(*child) {
                 match inventory.item(*id) {
-                    Some(block) => {
-                        *block_ = match block {
-                            BlockType::Dirt => handles.dirt.clone(),
-                            BlockType::Cobble => handles.cobble.clone(),
-                            BlockType::Grass => handles.grass.clone(),
-                            BlockType::Planks => handles.planks.clone(),
-                            BlockType::Sand => handles.sand.clone(),
-                            BlockType::Bricks => handles.bricks.clone(),
-                            BlockType::Leaves => handles.leaves.clone(),
-                            BlockType::Wood => handles.wood.clone(),
-                            BlockType::Gravel => handles.gravel.clone(),
-                            _ => {
-                                error!("No thumb for {:?}", block);
-                                Handle::default()
-                            }
-                        };
+                    Some(item) => {
+                        *block_ = handles.thumb(item);
                         visible.is_visible = true;
                     }
                     None => visible.is_visible = false,
@@ -352,3 +482,45 @@ fn update_toolbar(
         }
     }
 }
+
+/// Distance of the toolbar to the left and bottom border of the window
+fn toolbar_offset(window: &Window) -> (f32, f32) {
+    (
+        (window.width() - SLOT_WIDTH * N_SLOTS as f32) / 2.0,
+        window.height() * 0.025,
+    )
+}
+
+fn update_status_bars(
+    health: Res<Health>,
+    stamina: Res<Stamina>,
+    windows: Res<Windows>,
+    mut bar_query: Query<(&mut Style, &StatusBar, &Children)>,
+    mut fill_query: Query<&mut Style, Without<StatusBar>>,
+) {
+    let window = windows.get_primary().unwrap();
+    let (offset_from_left, offset_from_bottom) = toolbar_offset(window);
+    let width = SLOT_WIDTH * N_SLOTS as f32 / 2.0 - BAR_HEIGHT / 2.0;
+    for (mut style, bar, children) in bar_query.iter_mut() {
+        let (left, fraction) = match bar {
+            StatusBar::Health => (
+                offset_from_left,
+                health.current() as f32 / MAX_HEALTH as f32,
+            ),
+            StatusBar::Stamina => (
+                offset_from_left + width + BAR_HEIGHT,
+                stamina.current() / MAX_STAMINA,
+            ),
+        };
+        style.position.left = Val::Px(left);
+        style.position.bottom = Val::Px(offset_from_bottom + SLOT_WIDTH + BAR_HEIGHT);
+        style.size.width = Val::Px(width);
+        style.size.height = Val::Px(BAR_HEIGHT);
+
+        if let Some(child) = children.first() {
+            if let Ok(mut fill) = fill_query.get_mut(*child) {
+                fill.size.width = Val::Percent(100.0 * fraction);
+            }
+        }
+    }
+}
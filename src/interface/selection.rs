@@ -19,6 +19,9 @@ impl Plugin for SelectionHintPlugin {
         )
         .add_system_set(
             SystemSet::on_update(AppState::InGame).with_system(update_selection_hint.system()),
+        )
+        .add_system_set(
+            SystemSet::on_exit(AppState::InGame).with_system(despawn_selection_hint.system()),
         );
     }
 }
@@ -58,6 +61,15 @@ pub fn update_selection_hint(
     }
 }
 
+fn despawn_selection_hint(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<SelectionTag>, With<NormalSelectionTag>)>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
 pub fn setup_selection_hint(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -1,109 +1,625 @@
-use crate::world::BlockType;
+use std::convert::TryInto;
 
-#[derive(Copy, Clone)]
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    crafting::{RecipeBook, RecipeOutput, GRID_SIZE, GRID_SLOTS},
+    item::{Item, Tool, TOOLS},
+    world::{BlockType, EXCEPT_AIR},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Slot {
     Empty,
-    /// Finite number of blocks where the number n is zero-indexed meaning `n = 0` means that there
-    /// is one block stored
-    Finite(BlockType, u32),
-    Infinite(BlockType),
+    /// Finite number of items where the number n is zero-indexed meaning `n = 0` means that there
+    /// is one item stored
+    Finite(Item, u32),
+    Infinite(Item),
+    /// Single tool with the number of uses left before it breaks
+    Tool(Tool, u32),
+}
+
+impl Slot {
+    /// Slot holding `count` items, or an empty slot if `count` is zero. Tools do not stack, they
+    /// are stored as a single new tool
+    pub fn finite(item: Item, count: u32) -> Self {
+        match (item, count) {
+            (_, 0) => Slot::Empty,
+            (Item::Tool(tool), _) => Slot::Tool(tool, tool.durability()),
+            (item, n) => Slot::Finite(item, n - 1),
+        }
+    }
+
+    pub fn item(&self) -> Option<Item> {
+        match *self {
+            Slot::Finite(item, _) | Slot::Infinite(item) => Some(item),
+            Slot::Tool(tool, _) => Some(Item::Tool(tool)),
+            Slot::Empty => None,
+        }
+    }
+
+    /// Number of stored items, `None` for infinite slots
+    pub fn count(&self) -> Option<u32> {
+        match *self {
+            Slot::Empty => Some(0),
+            Slot::Finite(_, n) => Some(n + 1),
+            Slot::Tool(..) => Some(1),
+            Slot::Infinite(_) => None,
+        }
+    }
+
+    /// Fraction of the uses left of a tool
+    pub fn durability(&self) -> Option<f32> {
+        match *self {
+            Slot::Tool(tool, uses) => Some(uses as f32 / tool.durability() as f32),
+            _ => None,
+        }
+    }
 }
 
-const SLOTS: usize = 9;
+/// Items that can be taken from the creative palette whose name contains `search`, ignoring
+/// case. Blocks with a facing are only listed once
+pub fn creative_palette(search: &str) -> Vec<Item> {
+    let search = search.trim().to_lowercase();
+    let blocks = EXCEPT_AIR
+        .iter()
+        .filter(|block| block.item() == **block)
+        .map(|block| Item::Block(*block));
+    let tools = TOOLS.iter().map(|tool| Item::Tool(*tool));
+    blocks
+        .chain(tools)
+        .filter(|item| item.name().to_lowercase().contains(&search))
+        .collect()
+}
 
+/// Slots of the hotbar, which come first and hold the items that can be used
+pub const HOTBAR_SLOTS: usize = 9;
+/// Slots of the main inventory behind the hotbar
+pub const MAIN_SLOTS: usize = 27;
+pub const SLOTS: usize = HOTBAR_SLOTS + MAIN_SLOTS;
+/// Slots including the cells of the crafting grid, which are addressed after the inventory's own
+/// slots. Only moving items around reaches the crafting grid, items are never absorbed into it
+pub const ALL_SLOTS: usize = SLOTS + GRID_SLOTS;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Inventory {
+    #[serde(
+        serialize_with = "serialize_slots",
+        deserialize_with = "deserialize_slots"
+    )]
     pub slots: [Slot; SLOTS],
+    pub crafting: [Slot; GRID_SLOTS],
+    #[serde(deserialize_with = "deserialize_hotbar_slot")]
     active_slot: usize,
 }
 
+// Serde only implements its traits for arrays of up to 32 elements, so the slots are written as
+// a list instead
+fn serialize_slots<S: Serializer>(slots: &[Slot; SLOTS], serializer: S) -> Result<S::Ok, S::Error> {
+    slots[..].serialize(serializer)
+}
+
+fn deserialize_slots<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<[Slot; SLOTS], D::Error> {
+    let slots = Vec::<Slot>::deserialize(deserializer)?;
+    let len = slots.len();
+    slots
+        .try_into()
+        .map_err(|_| D::Error::invalid_length(len, &format!("{} slots", SLOTS).as_str()))
+}
+
+fn deserialize_hotbar_slot<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let slot = usize::deserialize(deserializer)?;
+    if slot < HOTBAR_SLOTS {
+        Ok(slot)
+    } else {
+        Err(D::Error::custom(format!(
+            "active slot {} is not part of the hotbar",
+            slot
+        )))
+    }
+}
+
 impl Inventory {
+    /// Inventory a player starts a new world with
+    pub fn preset(creative: bool) -> Self {
+        if creative {
+            Self::creative_preset()
+        } else {
+            Self::survival_preset()
+        }
+    }
+
     pub fn survival_preset() -> Self {
         Self {
             slots: [Slot::Empty; SLOTS],
+            crafting: [Slot::Empty; GRID_SLOTS],
             active_slot: 0,
         }
     }
 
     pub fn creative_preset() -> Self {
-        Self {
-            slots: [
-                Slot::Infinite(BlockType::Dirt),
-                Slot::Infinite(BlockType::Cobble),
-                Slot::Infinite(BlockType::Planks),
-                Slot::Infinite(BlockType::Wood),
-                Slot::Infinite(BlockType::Bricks),
-                Slot::Infinite(BlockType::Gravel),
-                Slot::Infinite(BlockType::Sand),
-                Slot::Infinite(BlockType::Grass),
-                Slot::Infinite(BlockType::Leaves),
-            ],
-            active_slot: 0,
+        let mut inventory = Self::survival_preset();
+        let hotbar = [
+            BlockType::Dirt,
+            BlockType::Cobble,
+            BlockType::Planks,
+            BlockType::Wood,
+            BlockType::Bricks,
+            BlockType::Gravel,
+            BlockType::Sand,
+            BlockType::LadderNorth,
+            BlockType::VinesNorth,
+        ];
+        for (slot, block) in inventory.slots.iter_mut().zip(hotbar.iter()) {
+            *slot = Slot::Infinite(Item::Block(*block));
         }
+        inventory
     }
 
-    pub fn switch_slot(&mut self, slot: usize) {
-        assert!(slot < SLOTS);
+    /// Make `slot` of the hotbar the active slot. Returns whether it is part of the hotbar, other
+    /// slots are not switched to
+    pub fn switch_slot(&mut self, slot: usize) -> bool {
+        if slot >= HOTBAR_SLOTS {
+            return false;
+        }
         self.active_slot = slot;
+        true
+    }
+
+    /// Move the active slot `offset` slots to the right, wrapping around at the ends of the
+    /// hotbar
+    pub fn cycle_slot(&mut self, offset: isize) {
+        let slot = (self.active_slot as isize + offset).rem_euclid(HOTBAR_SLOTS as isize);
+        self.active_slot = slot as usize;
     }
 
     pub fn current_slot(&self) -> usize {
         self.active_slot
     }
 
-    pub fn item(&self, slot: usize) -> Option<BlockType> {
-        match self.slots[slot] {
-            Slot::Finite(b, _) | Slot::Infinite(b) => Some(b),
-            _ => None,
+    /// Slot of the inventory or, from `SLOTS` on, a cell of the crafting grid
+    pub fn slot(&self, slot: usize) -> Slot {
+        if slot < SLOTS {
+            self.slots[slot]
+        } else {
+            self.crafting[slot - SLOTS]
         }
     }
 
-    #[allow(dead_code)]
-    pub fn current_item(&self) -> Option<BlockType> {
+    pub fn slot_mut(&mut self, slot: usize) -> &mut Slot {
+        if slot < SLOTS {
+            &mut self.slots[slot]
+        } else {
+            &mut self.crafting[slot - SLOTS]
+        }
+    }
+
+    pub fn item(&self, slot: usize) -> Option<Item> {
+        self.slot(slot).item()
+    }
+
+    pub fn current_item(&self) -> Option<Item> {
         self.item(self.active_slot)
     }
 
-    pub fn consume_current_slot(&mut self) -> Option<BlockType> {
+    pub fn consume_current_slot(&mut self) -> Option<Item> {
         self.consume(self.active_slot)
     }
 
-    pub fn consume(&mut self, slot: usize) -> Option<BlockType> {
-        match &mut self.slots[slot as usize] {
+    /// Take one item out of `slot`, which empties a tool's slot
+    pub fn consume(&mut self, slot: usize) -> Option<Item> {
+        let stack = self.slot_mut(slot);
+        match stack {
             Slot::Empty => None,
             Slot::Finite(b, 0) => {
                 let b = *b;
-                self.slots[slot as usize] = Slot::Empty;
+                *stack = Slot::Empty;
                 Some(b)
             }
             Slot::Finite(b, n) => {
                 *n -= 1;
                 Some(*b)
             }
+            Slot::Tool(tool, _) => {
+                let tool = *tool;
+                *stack = Slot::Empty;
+                Some(Item::Tool(tool))
+            }
             Slot::Infinite(b) => Some(*b),
         }
     }
 
-    pub fn absorb(&mut self, block: BlockType, quantity: u32) -> Option<usize> {
-        for i in 0..SLOTS {
-            match &mut self.slots[i] {
-                Slot::Infinite(b) if *b == block => {
-                    return Some(i);
-                }
-                Slot::Finite(b, n) if *b == block => {
-                    *n += quantity;
-                    return Some(i);
-                }
-                Slot::Empty => {
-                    self.slots[i] = Slot::Finite(block, quantity);
-                    return Some(i);
+    /// Use up one use of the tool in the active slot, which breaks when it has none left
+    pub fn wear_current_tool(&mut self) {
+        let stack = &mut self.slots[self.active_slot];
+        match stack {
+            Slot::Tool(_, uses) if *uses > 1 => *uses -= 1,
+            Slot::Tool(..) => *stack = Slot::Empty,
+            _ => (),
+        }
+    }
+
+    /// Store `quantity` items, topping up existing stacks of the item before filling empty
+    /// slots, the hotbar before the main inventory. Returns the number of items that did not fit
+    pub fn absorb(&mut self, item: Item, quantity: u32) -> u32 {
+        if quantity == 0 {
+            return 0;
+        }
+        if self.slots.contains(&Slot::Infinite(item)) {
+            return 0;
+        }
+        let max = item.max_stack();
+        let mut remaining = quantity;
+        for slot in self.slots.iter_mut() {
+            if let Slot::Finite(b, n) = slot {
+                if *b == item && *n + 1 < max {
+                    let moved = remaining.min(max - (*n + 1));
+                    *n += moved;
+                    remaining -= moved;
                 }
-                _ => {}
+            }
+            if remaining == 0 {
+                return 0;
             }
         }
-        None
+        for slot in self.slots.iter_mut() {
+            if *slot == Slot::Empty {
+                let moved = remaining.min(max);
+                *slot = Slot::finite(item, moved);
+                remaining -= moved;
+            }
+            if remaining == 0 {
+                return 0;
+            }
+        }
+        remaining
     }
 
-    pub fn absorb_creative(&mut self, block: BlockType) -> Option<usize> {
-        self.slots[self.active_slot] = Slot::Infinite(block);
+    pub fn absorb_creative(&mut self, item: Item) -> Option<usize> {
+        self.slots[self.active_slot] = Slot::Infinite(item);
         Some(self.active_slot)
     }
+
+    /// Move the items of slot `from` onto slot `to`. Finite stacks of the same item are merged
+    /// as far as the stack limit allows, any other slots are swapped
+    pub fn merge(&mut self, from: usize, to: usize) {
+        if from == to {
+            return;
+        }
+        match (self.slot(from), self.slot(to)) {
+            (Slot::Finite(a, n), Slot::Finite(b, m)) if a == b => {
+                let moved = (n + 1).min(a.max_stack().saturating_sub(m + 1));
+                *self.slot_mut(from) = Slot::finite(a, n + 1 - moved);
+                *self.slot_mut(to) = Slot::finite(b, m + 1 + moved);
+            }
+            (a, b) => {
+                *self.slot_mut(from) = b;
+                *self.slot_mut(to) = a;
+            }
+        }
+    }
+
+    /// Move half of the finite stack in slot `from`, rounded up, into the empty slot `to`.
+    /// Returns whether anything was moved
+    pub fn split(&mut self, from: usize, to: usize) -> bool {
+        match (self.slot(from), self.slot(to)) {
+            (Slot::Finite(block, n), Slot::Empty) if n > 0 => {
+                let count = n + 1;
+                let moved = (count + 1) / 2;
+                *self.slot_mut(from) = Slot::finite(block, count - moved);
+                *self.slot_mut(to) = Slot::finite(block, moved);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move the items of `slot` from the hotbar to the main inventory or the other way around,
+    /// and from the crafting grid anywhere into the inventory, onto stacks of the same block
+    /// first. Whatever does not fit stays in place
+    pub fn quick_move(&mut self, slot: usize) {
+        let targets = if slot < HOTBAR_SLOTS {
+            HOTBAR_SLOTS..SLOTS
+        } else if slot < SLOTS {
+            0..HOTBAR_SLOTS
+        } else {
+            0..SLOTS
+        };
+        for same_block in [true, false].iter() {
+            for target in targets.clone() {
+                let fits = match (self.slot(slot), self.slots[target]) {
+                    (Slot::Empty, _) => return,
+                    (Slot::Finite(a, _), Slot::Finite(b, _)) => *same_block && a == b,
+                    (_, Slot::Empty) => !*same_block,
+                    _ => false,
+                };
+                if fits {
+                    self.merge(slot, target);
+                }
+            }
+        }
+    }
+
+    /// Items in the cells of the crafting grid
+    pub fn crafting_grid(&self) -> [Option<Item>; GRID_SLOTS] {
+        let mut grid = [None; GRID_SLOTS];
+        for (cell, slot) in grid.iter_mut().zip(self.crafting.iter()) {
+            *cell = slot.item();
+        }
+        grid
+    }
+
+    /// Craft the recipe matching the crafting grid once, using up one item of each cell, and
+    /// store the output in the inventory. Nothing is crafted if the output does not fit
+    pub fn craft(&mut self, recipes: &RecipeBook) -> Option<RecipeOutput> {
+        let output = recipes.find(&self.crafting_grid(), GRID_SIZE)?.output;
+        let mut crafted = self.clone();
+        for cell in SLOTS..ALL_SLOTS {
+            crafted.consume(cell);
+        }
+        if crafted.absorb(output.item, output.count) > 0 {
+            return None;
+        }
+        *self = crafted;
+        Some(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(block: BlockType) -> Item {
+        Item::Block(block)
+    }
+
+    #[test]
+    fn absorb_stacks_and_overflows() {
+        let mut inventory = Inventory::survival_preset();
+        let dirt_stack = block(BlockType::Dirt).max_stack();
+        assert_eq!(inventory.absorb(block(BlockType::Dirt), dirt_stack + 10), 0);
+        assert_eq!(
+            inventory.slots[0],
+            Slot::finite(block(BlockType::Dirt), dirt_stack)
+        );
+        assert_eq!(inventory.slots[1], Slot::finite(block(BlockType::Dirt), 10));
+        // Existing stacks are topped up first
+        assert_eq!(inventory.absorb(block(BlockType::Dirt), 5), 0);
+        assert_eq!(inventory.slots[1].count(), Some(15));
+        // Blocks that only stack to one take a slot each
+        assert_eq!(inventory.absorb(block(BlockType::Water), 2), 0);
+        assert_eq!(inventory.slots[2], Slot::finite(block(BlockType::Water), 1));
+        assert_eq!(inventory.slots[3], Slot::finite(block(BlockType::Water), 1));
+
+        // More than nine kinds of blocks overflow into the main inventory
+        let kinds = [
+            BlockType::Grass,
+            BlockType::Cobble,
+            BlockType::Bricks,
+            BlockType::Wood,
+            BlockType::Planks,
+            BlockType::Leaves,
+            BlockType::Sand,
+            BlockType::Gravel,
+        ];
+        for kind in kinds.iter() {
+            assert_eq!(inventory.absorb(block(*kind), 1), 0);
+        }
+        assert_eq!(
+            inventory.item(HOTBAR_SLOTS - 1),
+            Some(block(BlockType::Planks))
+        );
+        assert_eq!(inventory.item(HOTBAR_SLOTS), Some(block(BlockType::Leaves)));
+        assert_eq!(
+            inventory.item(HOTBAR_SLOTS + 2),
+            Some(block(BlockType::Gravel))
+        );
+
+        // Whatever does not fit is left over, after topping up the single cobble block
+        let free = inventory
+            .slots
+            .iter()
+            .filter(|slot| **slot == Slot::Empty)
+            .count() as u32;
+        let max = block(BlockType::Cobble).max_stack();
+        assert_eq!(
+            inventory.absorb(block(BlockType::Cobble), free * max + max),
+            1
+        );
+        assert!(inventory.slots.iter().all(|slot| *slot != Slot::Empty));
+        assert_eq!(inventory.absorb(block(BlockType::Water), 1), 1);
+
+        // Infinite slots take everything
+        let mut inventory = Inventory::creative_preset();
+        assert_eq!(inventory.absorb(block(BlockType::Dirt), 1000), 0);
+        assert_eq!(inventory.slots[HOTBAR_SLOTS], Slot::Empty);
+    }
+
+    #[test]
+    fn split_and_merge() {
+        let mut inventory = Inventory::survival_preset();
+        inventory.slots[0] = Slot::finite(block(BlockType::Dirt), 7);
+        assert!(inventory.split(0, 5));
+        assert_eq!(inventory.slots[0].count(), Some(3));
+        assert_eq!(inventory.slots[5], Slot::finite(block(BlockType::Dirt), 4));
+        // Only into empty slots and only stacks of more than one block
+        assert!(!inventory.split(0, 5));
+        inventory.slots[1] = Slot::finite(block(BlockType::Sand), 1);
+        assert!(!inventory.split(1, 2));
+
+        inventory.merge(5, 0);
+        assert_eq!(inventory.slots[0].count(), Some(7));
+        assert_eq!(inventory.slots[5], Slot::Empty);
+        // Different blocks are swapped
+        inventory.merge(1, 0);
+        assert_eq!(inventory.slots[0], Slot::finite(block(BlockType::Sand), 1));
+        assert_eq!(inventory.slots[1], Slot::finite(block(BlockType::Dirt), 7));
+        // Merging respects the stack limit
+        let max = block(BlockType::Dirt).max_stack();
+        inventory.slots[2] = Slot::finite(block(BlockType::Dirt), max - 2);
+        inventory.merge(1, 2);
+        assert_eq!(inventory.slots[2].count(), Some(max));
+        assert_eq!(inventory.slots[1].count(), Some(5));
+    }
+
+    #[test]
+    fn quick_move_between_hotbar_and_main() {
+        let mut inventory = Inventory::survival_preset();
+        let max = block(BlockType::Dirt).max_stack();
+        inventory.slots[0] = Slot::finite(block(BlockType::Dirt), 10);
+        inventory.slots[HOTBAR_SLOTS + 4] = Slot::finite(block(BlockType::Dirt), max - 4);
+        inventory.quick_move(0);
+        // The existing stack is filled up before the first empty slot is used
+        assert_eq!(inventory.slots[HOTBAR_SLOTS + 4].count(), Some(max));
+        assert_eq!(
+            inventory.slots[HOTBAR_SLOTS],
+            Slot::finite(block(BlockType::Dirt), 6)
+        );
+        assert_eq!(inventory.slots[0], Slot::Empty);
+
+        inventory.quick_move(HOTBAR_SLOTS + 4);
+        assert_eq!(inventory.slots[0].count(), Some(max));
+        assert_eq!(inventory.slots[HOTBAR_SLOTS + 4], Slot::Empty);
+
+        // Nothing moves into a full hotbar
+        for slot in inventory.slots[..HOTBAR_SLOTS].iter_mut() {
+            *slot = Slot::Infinite(block(BlockType::Sand));
+        }
+        inventory.quick_move(HOTBAR_SLOTS);
+        assert_eq!(
+            inventory.slots[HOTBAR_SLOTS],
+            Slot::finite(block(BlockType::Dirt), 6)
+        );
+    }
+
+    #[test]
+    fn craft_from_grid() {
+        let recipes = RecipeBook::bundled();
+        let mut inventory = Inventory::survival_preset();
+        inventory.crafting[0] = Slot::finite(block(BlockType::Wood), 2);
+        assert_eq!(
+            inventory.craft(&recipes).map(|output| output.item),
+            Some(block(BlockType::Planks))
+        );
+        assert_eq!(
+            inventory.crafting[0],
+            Slot::finite(block(BlockType::Wood), 1)
+        );
+        assert_eq!(
+            inventory.slots[0],
+            Slot::finite(block(BlockType::Planks), 4)
+        );
+        inventory.craft(&recipes);
+        assert_eq!(inventory.crafting[0], Slot::Empty);
+        assert_eq!(inventory.slots[0].count(), Some(8));
+        assert_eq!(inventory.craft(&recipes), None);
+
+        // Blocks move into and out of the grid like between other slots
+        inventory.merge(0, SLOTS + 4);
+        assert_eq!(inventory.item(SLOTS + 4), Some(block(BlockType::Planks)));
+        assert_eq!(inventory.crafting_grid()[4], Some(block(BlockType::Planks)));
+        inventory.quick_move(SLOTS + 4);
+        assert_eq!(inventory.crafting[4], Slot::Empty);
+        assert_eq!(inventory.slots[0].count(), Some(8));
+
+        // Nothing is used up if the output does not fit
+        for slot in inventory.slots.iter_mut() {
+            *slot = Slot::finite(block(BlockType::Dirt), 1);
+        }
+        inventory.crafting[8] = Slot::finite(block(BlockType::Wood), 1);
+        assert_eq!(inventory.craft(&recipes), None);
+        assert_eq!(
+            inventory.crafting[8],
+            Slot::finite(block(BlockType::Wood), 1)
+        );
+    }
+
+    #[test]
+    fn tools_wear_out() {
+        let mut inventory = Inventory::survival_preset();
+        let tool = Tool::WoodenAxe;
+        assert_eq!(inventory.absorb(Item::Tool(tool), 2), 0);
+        assert_eq!(inventory.slots[0], Slot::Tool(tool, tool.durability()));
+        assert_eq!(inventory.slots[1], Slot::Tool(tool, tool.durability()));
+        assert_eq!(inventory.slots[0].durability(), Some(1.0));
+
+        for _ in 1..tool.durability() {
+            inventory.wear_current_tool();
+        }
+        assert_eq!(inventory.slots[0], Slot::Tool(tool, 1));
+        inventory.wear_current_tool();
+        assert_eq!(inventory.slots[0], Slot::Empty);
+        assert_eq!(inventory.slots[1].count(), Some(1));
+
+        // Tools in the creative inventory do not wear
+        inventory.slots[0] = Slot::Infinite(Item::Tool(tool));
+        inventory.wear_current_tool();
+        assert_eq!(inventory.slots[0], Slot::Infinite(Item::Tool(tool)));
+    }
+
+    #[test]
+    fn palette_search() {
+        let all = creative_palette("");
+        assert!(all.contains(&block(BlockType::Water)));
+        assert!(all.contains(&block(BlockType::LadderNorth)));
+        assert!(!all.contains(&block(BlockType::LadderEast)));
+        assert!(!all.contains(&block(BlockType::Air)));
+        assert!(all.contains(&Item::Tool(Tool::StonePickaxe)));
+        assert_eq!(
+            creative_palette(" lAdD"),
+            vec![block(BlockType::LadderNorth)]
+        );
+        assert_eq!(
+            creative_palette("s"),
+            vec![
+                block(BlockType::Grass),
+                block(BlockType::Cobble),
+                block(BlockType::Bricks),
+                block(BlockType::Planks),
+                block(BlockType::Leaves),
+                block(BlockType::Sand),
+                block(BlockType::VinesNorth),
+                Item::Tool(Tool::WoodenShovel),
+                Item::Tool(Tool::StonePickaxe),
+                Item::Tool(Tool::StoneShovel),
+                Item::Tool(Tool::StoneAxe),
+            ]
+        );
+        assert!(creative_palette("bedrock").is_empty());
+    }
+
+    #[test]
+    fn switch_and_cycle_slots() {
+        let mut inventory = Inventory::creative_preset();
+        assert!(inventory.switch_slot(HOTBAR_SLOTS - 1));
+        assert!(!inventory.switch_slot(HOTBAR_SLOTS));
+        assert_eq!(inventory.current_slot(), HOTBAR_SLOTS - 1);
+        inventory.cycle_slot(1);
+        assert_eq!(inventory.current_slot(), 0);
+        inventory.cycle_slot(-1);
+        assert_eq!(inventory.current_slot(), HOTBAR_SLOTS - 1);
+        inventory.cycle_slot(-2);
+        assert_eq!(inventory.current_slot(), HOTBAR_SLOTS - 3);
+    }
+
+    #[test]
+    fn serialized_inventory() {
+        let mut inventory = Inventory::creative_preset();
+        inventory.slots[HOTBAR_SLOTS + 3] = Slot::finite(block(BlockType::Sand), 12);
+        inventory.slots[SLOTS - 1] = Slot::Tool(Tool::WoodenAxe, 7);
+        inventory.crafting[4] = Slot::finite(block(BlockType::Wood), 1);
+        inventory.switch_slot(5);
+        let yaml = serde_yaml::to_string(&inventory).unwrap();
+        assert_eq!(serde_yaml::from_str::<Inventory>(&yaml).unwrap(), inventory);
+
+        // Too few slots and active slots outside of the hotbar are rejected
+        let mut value: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        value["active_slot"] = HOTBAR_SLOTS.into();
+        assert!(serde_yaml::from_value::<Inventory>(value.clone()).is_err());
+        value["active_slot"] = 0.into();
+        value["slots"] = serde_yaml::to_value(vec![Slot::Empty; SLOTS - 1]).unwrap();
+        assert!(serde_yaml::from_value::<Inventory>(value).is_err());
+    }
 }
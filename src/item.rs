@@ -0,0 +1,148 @@
+//! Things the player holds in the inventory, either blocks that are placed or tools
+use serde::{Deserialize, Serialize};
+
+use crate::world::{properties, BlockCategory, BlockType};
+
+/// Anything that fits into an inventory slot. In files, items are written as the name of the block
+/// or tool
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Item {
+    Block(BlockType),
+    Tool(Tool),
+}
+
+impl From<BlockType> for Item {
+    /// Item of a block, blocks with a facing are all the same item
+    fn from(block: BlockType) -> Self {
+        Item::Block(block.item())
+    }
+}
+
+impl Item {
+    /// The item as it is held in the inventory, i.e., blocks with a facing as the item that all
+    /// facings share
+    pub fn held(self) -> Self {
+        match self {
+            Item::Block(block) => block.into(),
+            tool => tool,
+        }
+    }
+
+    /// Name of the item as it is shown to the player
+    pub fn name(&self) -> &'static str {
+        match self {
+            Item::Block(block) => block.name(),
+            Item::Tool(tool) => tool.name(),
+        }
+    }
+
+    /// Most items of this kind a single slot holds
+    pub fn max_stack(&self) -> u32 {
+        match self {
+            Item::Block(block) => properties(block).max_stack.max(1),
+            Item::Tool(_) => 1,
+        }
+    }
+
+    /// Key to order items by regardless of their kind
+    pub fn id(&self) -> (u8, u8) {
+        match *self {
+            Item::Block(block) => (0, block as u8),
+            Item::Tool(tool) => (1, tool as u8),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize, Serialize)]
+pub enum Tool {
+    WoodenPickaxe,
+    WoodenShovel,
+    WoodenAxe,
+    StonePickaxe,
+    StoneShovel,
+    StoneAxe,
+}
+
+pub const TOOLS: [Tool; 6] = [
+    Tool::WoodenPickaxe,
+    Tool::WoodenShovel,
+    Tool::WoodenAxe,
+    Tool::StonePickaxe,
+    Tool::StoneShovel,
+    Tool::StoneAxe,
+];
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::WoodenPickaxe => "Wooden Pickaxe",
+            Tool::WoodenShovel => "Wooden Shovel",
+            Tool::WoodenAxe => "Wooden Axe",
+            Tool::StonePickaxe => "Stone Pickaxe",
+            Tool::StoneShovel => "Stone Shovel",
+            Tool::StoneAxe => "Stone Axe",
+        }
+    }
+
+    /// Blocks the tool breaks faster
+    pub fn category(&self) -> BlockCategory {
+        match self {
+            Tool::WoodenPickaxe | Tool::StonePickaxe => BlockCategory::Stone,
+            Tool::WoodenShovel | Tool::StoneShovel => BlockCategory::Soil,
+            Tool::WoodenAxe | Tool::StoneAxe => BlockCategory::Wood,
+        }
+    }
+
+    /// Number of blocks a new tool breaks before it breaks itself
+    pub fn durability(&self) -> u32 {
+        match self {
+            Tool::WoodenPickaxe | Tool::WoodenShovel | Tool::WoodenAxe => 60,
+            Tool::StonePickaxe | Tool::StoneShovel | Tool::StoneAxe => 132,
+        }
+    }
+
+    /// How many times faster than by hand the tool breaks `block`
+    pub fn break_speed(&self, block: BlockType) -> f32 {
+        if properties(&block).category != self.category() {
+            return 1.0;
+        }
+        match self {
+            Tool::WoodenPickaxe | Tool::WoodenShovel | Tool::WoodenAxe => 2.0,
+            Tool::StonePickaxe | Tool::StoneShovel | Tool::StoneAxe => 4.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_from_names() {
+        let items: Vec<Item> = serde_yaml::from_str("[Dirt, LadderEast, StoneAxe]").unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item::Block(BlockType::Dirt),
+                Item::Block(BlockType::LadderEast),
+                Item::Tool(Tool::StoneAxe)
+            ]
+        );
+        assert!(serde_yaml::from_str::<Item>("Bedrock").is_err());
+        assert_eq!(
+            Item::from(BlockType::LadderEast),
+            BlockType::LadderNorth.into()
+        );
+    }
+
+    #[test]
+    fn tools_break_their_category_faster() {
+        assert_eq!(Tool::StonePickaxe.break_speed(BlockType::Cobble), 4.0);
+        assert_eq!(Tool::WoodenPickaxe.break_speed(BlockType::Bricks), 2.0);
+        assert_eq!(Tool::StonePickaxe.break_speed(BlockType::Dirt), 1.0);
+        assert_eq!(Tool::WoodenShovel.break_speed(BlockType::Gravel), 2.0);
+        assert_eq!(Tool::StoneAxe.break_speed(BlockType::LadderWest), 4.0);
+        assert_eq!(Tool::StoneAxe.break_speed(BlockType::Leaves), 1.0);
+    }
+}
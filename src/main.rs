@@ -1,6 +1,10 @@
 mod config;
+mod crafting;
+mod health;
 mod interface;
 mod inventory;
+mod item;
+mod save;
 mod shader;
 mod utils;
 mod world;
@@ -9,16 +13,22 @@ use bevy::{asset::LoadState, prelude::*};
 
 use bevy_rapier3d::{
     physics::{PhysicsInterpolationComponent, RapierConfiguration, RapierPhysicsPlugin},
-    rapier::{dynamics::RigidBodyBuilder, geometry::ColliderBuilder},
+    rapier::{dynamics::RigidBodyBuilder, geometry::ColliderBuilder, math::Vector},
+};
+use health::HealthPlugin;
+use interface::controller::{
+    BodyTag, CameraTag, InputState, MovementSettings, NoCameraPlayerPlugin, YawTag,
 };
-use interface::controller::{BodyTag, CameraTag, NoCameraPlayerPlugin, YawTag};
 #[cfg(not(feature = "inline_assets"))]
 use interface::overlay;
 
-use interface::overlay::OverlayPlugin;
+use interface::{
+    controls::ControlsScreenPlugin, inventory_screen::InventoryScreenPlugin, menu::MenuPlugin,
+    overlay::OverlayPlugin,
+};
 use kurinji::KurinjiPlugin;
-use world::raycast::VoxelRaycastPlugin;
 use world::ChunkManager;
+use world::{drops::DroppedItemPlugin, raycast::VoxelRaycastPlugin};
 
 #[cfg(feature = "inline_assets")]
 use crate::utils::inline_assets::{InlineAssets, InlineAssetsPlugin};
@@ -33,12 +43,22 @@ use std::collections::HashMap;
 use std::path::Path;
 
 use crate::interface::{overlay::OverlayLabels, selection::SelectionHintPlugin};
-use crate::{inventory::Inventory, world::WorldPlugin};
+use crate::{
+    crafting::RecipeBook,
+    inventory::Inventory,
+    save::{PlayerSave, SavePlugin, WorldSave},
+    world::WorldPlugin,
+};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum AppState {
     InGame,
     Loading,
+    /// Choosing, creating and deleting worlds, entered once the assets are loaded and when
+    /// leaving a world
+    MainMenu,
+    /// Pushed on top of `InGame`, the world stays loaded but does not advance
+    Paused,
 }
 
 #[bevy_main]
@@ -51,6 +71,13 @@ fn main() {
     if config.debug.print_default_config {
         println!("{}", CobbleConfig::default_as_yaml().unwrap());
     }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        if let Some(args) = world::preview::PreviewArgs::parse(std::env::args().skip(1)) {
+            preview(&config, args);
+            return;
+        }
+    }
 
     let mut app = App::build();
     app.insert_resource(Msaa {
@@ -72,10 +99,19 @@ fn main() {
             "assets/thumbs/dirt.png",
             "assets/thumbs/grass.png",
             "assets/thumbs/gravel.png",
+            "assets/thumbs/ladder.png",
             "assets/thumbs/leaves.png",
             "assets/thumbs/planks.png",
             "assets/thumbs/sand.png",
+            "assets/thumbs/stone_axe.png",
+            "assets/thumbs/stone_pickaxe.png",
+            "assets/thumbs/stone_shovel.png",
+            "assets/thumbs/vines.png",
+            "assets/thumbs/water.png",
             "assets/thumbs/wood.png",
+            "assets/thumbs/wooden_axe.png",
+            "assets/thumbs/wooden_pickaxe.png",
+            "assets/thumbs/wooden_shovel.png",
         ];
         app.insert_resource(inline_assets);
     }
@@ -99,16 +135,21 @@ fn main() {
     .add_plugin(FrameTimeDiagnosticsPlugin::default())
     .add_plugin(RapierPhysicsPlugin)
     .insert_resource(RapierConfiguration {
-        time_dependent_number_of_timesteps: true,
-        // Disable physics until all assets are loaded
+        // The physics world is stepped by the player's movement in fixed steps, see
+        // `world::PhysicsClock`
+        time_dependent_number_of_timesteps: false,
         physics_pipeline_active: false,
         query_pipeline_active: false,
         ..Default::default()
     })
     .add_plugin(KurinjiPlugin::default())
-    .add_plugin(VoxelRaycastPlugin);
+    .add_plugin(VoxelRaycastPlugin)
+    .add_plugin(DroppedItemPlugin)
+    .add_plugin(HealthPlugin);
     if config.video.show_interface {
-        app.add_plugin(OverlayPlugin);
+        app.add_plugin(OverlayPlugin)
+            .add_plugin(InventoryScreenPlugin)
+            .add_plugin(ControlsScreenPlugin);
     }
 
     #[cfg(feature = "inline_assets")]
@@ -117,17 +158,20 @@ fn main() {
     app.add_plugin(bevy_webgl2::WebGL2Plugin);
 
     app.add_plugin(NoCameraPlayerPlugin)
-        .add_startup_system(setup_player.system());
+        .add_plugin(MenuPlugin)
+        .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(setup_player.system()))
+        .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(despawn_player.system()));
     if config.debug.log_diagnostics {
         app.add_plugin(LogDiagnosticsPlugin::default());
     }
     app.insert_resource(world::NineSurroundChunk::empty())
+        .insert_resource(world::ActiveGenerator::from_config(&config.game.generator))
         .add_plugin(WorldPlugin)
-        .insert_resource(if config.game.creative {
-            Inventory::creative_preset()
-        } else {
-            Inventory::survival_preset()
-        })
+        // Replaced by the inventory saved with the world when entering one
+        .insert_resource(Inventory::preset(config.game.creative))
+        .insert_resource(WorldSave::default())
+        .add_plugin(SavePlugin)
+        .insert_resource(RecipeBook::from_config(&config.game.recipes))
         .add_system_set(
             SystemSet::on_update(AppState::Loading)
                 .with_system(check_loading_finished.system())
@@ -142,6 +186,30 @@ fn main() {
     app.run();
 }
 
+/// Render a preview of the configured world generator without creating a window
+#[cfg(not(target_arch = "wasm32"))]
+fn preview(config: &CobbleConfig, args: Result<world::preview::PreviewArgs, String>) {
+    let args = match args {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, world::preview::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let generator = world::ActiveGenerator::from_config(&config.game.generator);
+    match world::preview::render_preview(&*generator.0, config.game.world_height(), &args) {
+        Ok(written) => {
+            for path in written {
+                println!("Wrote {}", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to write preview: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 #[cfg(feature = "inline_assets")]
 fn setup_inline_assets(
     inline_assets: Res<InlineAssets>,
@@ -153,17 +221,32 @@ fn setup_inline_assets(
 
 const SPAWN_POSITION: [f32; 3] = [0.0, 10.0, 0.0];
 
-fn setup_player(mut commands: Commands) {
-    let spawn_position = Vec3::from(SPAWN_POSITION);
+/// Spawn the player where they were saved, or at the spawn position in a new world
+fn setup_player(
+    mut commands: Commands,
+    player: Res<PlayerSave>,
+    config: Res<CobbleConfig>,
+    mut settings: ResMut<MovementSettings>,
+    mut input_state: ResMut<InputState>,
+) {
+    let spawn_position = Vec3::from(player.position);
+    let yaw = player.yaw.to_radians();
+    let pitch = player.pitch.to_radians();
+    input_state.yaw = player.yaw;
+    input_state.pitch = pitch;
+    // Flying is only possible in creative mode, which may have been turned off since saving
+    settings.fly = player.fly && config.game.creative;
     let body_rigid_body = RigidBodyBuilder::new_dynamic()
         .translation(spawn_position.x, spawn_position.y, spawn_position.z)
+        .rotation(Vector::y() * yaw)
         .additional_mass(75.0)
         .linear_damping(1.0)
         .restrict_rotations(false, false, false)
         .user_data(world::COLLIDER_PLAYER_UD);
-    let body_collider = ColliderBuilder::round_cylinder(0.8, 0.1, 0.0)
-        .collision_groups(world::GROUP_PLAYER)
-        .user_data(world::COLLIDER_PLAYER_UD);
+    let body_collider =
+        ColliderBuilder::round_cylinder(world::PLAYER_HALF_HEIGHT, world::PLAYER_RADIUS, 0.0)
+            .collision_groups(world::GROUP_PLAYER)
+            .user_data(world::COLLIDER_PLAYER_UD);
     let body = commands
         .spawn_bundle((
             Transform::identity(),
@@ -171,7 +254,7 @@ fn setup_player(mut commands: Commands) {
             BodyTag,
             body_rigid_body,
             body_collider,
-            PhysicsInterpolationComponent::new(spawn_position, Quat::IDENTITY),
+            PhysicsInterpolationComponent::new(spawn_position, Quat::from_rotation_y(yaw)),
         ))
         .id();
     let yaw = commands
@@ -181,8 +264,8 @@ fn setup_player(mut commands: Commands) {
         .spawn_bundle(PerspectiveCameraBundle {
             global_transform: GlobalTransform::identity(),
             transform: Transform::from_matrix(Mat4::from_rotation_translation(
-                Quat::from_axis_angle(Vec3::X, 0.0),
-                Vec3::from([0.0, 0.3, 0.0]),
+                Quat::from_axis_angle(Vec3::X, pitch),
+                Vec3::new(0.0, interface::controller::CAMERA_HEIGHT, 0.0),
             )),
             perspective_projection: bevy::render::camera::PerspectiveProjection {
                 fov: std::f32::consts::PI / 3.0,
@@ -197,6 +280,12 @@ fn setup_player(mut commands: Commands) {
     commands.entity(yaw).push_children(&[camera]);
 }
 
+fn despawn_player(mut commands: Commands, query: Query<Entity, With<BodyTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 #[cfg(not(feature = "inline_assets"))]
 fn check_loading_finished(
     asset_server: Res<AssetServer>,
@@ -213,7 +302,7 @@ fn check_loading_finished(
                 .chain(overlay_handles.clone().into_iter()),
         ) == LoadState::Loaded
     {
-        state.set(AppState::InGame).unwrap();
+        state.set(AppState::MainMenu).unwrap();
         *loaded = true;
     }
 }
@@ -229,7 +318,7 @@ fn check_loading_finished(
         && asset_server.get_group_load_state(inline_asset_handles.values().map(|h| h.id))
             == LoadState::Loaded
     {
-        state.set(AppState::InGame).unwrap();
+        state.set(AppState::MainMenu).unwrap();
         *loaded = true;
     }
 }
@@ -0,0 +1,426 @@
+//! Worlds in the saves directory, and saving the player with the world and restoring them when
+//! the world is loaded again
+use std::{
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+use bevy_rapier3d::{physics::RigidBodyHandleComponent, rapier::dynamics::RigidBodySet};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    config::GameConfig,
+    health::{Health, Stamina},
+    interface::controller::{BodyTag, InputState, MovementSettings},
+    inventory::Inventory,
+    AppState,
+};
+
+/// Directory of a world whose name has no characters usable in a file name
+pub const DEFAULT_WORLD: &str = "world";
+/// File in the world's directory the player is saved in
+pub const PLAYER_FILE: &str = "player.yaml";
+/// File in the world's directory its name and seed are saved in
+pub const WORLD_FILE: &str = "world.yaml";
+/// Seconds between two saves while playing, the player is also saved on exit
+const AUTOSAVE_INTERVAL: f32 = 60.0;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // The exit requested by closing the window is sent during the update, running last sees
+        // it before the app stops
+        app.add_system_to_stage(CoreStage::Last, save_player.system())
+            .add_system_set(
+                SystemSet::on_exit(AppState::InGame).with_system(save_on_leave.system()),
+            );
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Parse(serde_yaml::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Everything about the player that is kept between sessions
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PlayerSave {
+    /// Center of the player's body
+    pub position: [f32; 3],
+    /// Rotation of the body around the vertical axis in degrees
+    pub yaw: f32,
+    /// Rotation of the camera around the horizontal axis in degrees
+    pub pitch: f32,
+    pub fly: bool,
+    pub inventory: Inventory,
+    /// Missing from players saved before health and stamina were kept, they start healthy and
+    /// rested
+    #[serde(default)]
+    pub health: Health,
+    #[serde(default)]
+    pub stamina: Stamina,
+}
+
+impl PlayerSave {
+    /// Player at the spawn position, as they enter a new world
+    pub fn new(inventory: Inventory) -> Self {
+        Self {
+            position: crate::SPAWN_POSITION,
+            yaw: 0.0,
+            pitch: 0.0,
+            fly: false,
+            inventory,
+            health: Health::default(),
+            stamina: Stamina::default(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, SaveError> {
+        read_yaml(path)
+    }
+
+    pub fn to_file(&self, path: &Path) -> Result<(), SaveError> {
+        write_yaml(path, self)
+    }
+}
+
+fn read_yaml<T: DeserializeOwned>(path: &Path) -> Result<T, SaveError> {
+    let reader = File::open(path).map_err(SaveError::Io)?;
+    serde_yaml::from_reader(reader).map_err(SaveError::Parse)
+}
+
+/// Write `value` to `path`. The previous file is only replaced once the new one is written
+/// completely
+pub fn write_yaml<T: Serialize>(path: &Path, value: &T) -> Result<(), SaveError> {
+    let partial = path.with_extension("yaml.partial");
+    let writer = File::create(&partial).map_err(SaveError::Io)?;
+    serde_yaml::to_writer(writer, value).map_err(SaveError::Parse)?;
+    fs::rename(&partial, path).map_err(SaveError::Io)
+}
+
+/// What a world is generated from, kept in its directory next to the player
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct WorldInfo {
+    pub name: String,
+    pub seed: u32,
+}
+
+impl WorldInfo {
+    /// Seed entered by the player. Numbers are used as they are, any other text is hashed, and
+    /// a random seed is picked if there is no text
+    pub fn parse_seed(text: &str) -> u32 {
+        let text = text.trim();
+        if text.is_empty() {
+            rand::random()
+        } else {
+            text.parse().unwrap_or_else(|_| {
+                text.bytes().fold(0u32, |hash, byte| {
+                    hash.wrapping_mul(31).wrapping_add(byte as u32)
+                })
+            })
+        }
+    }
+}
+
+/// World with a directory in the saves directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedWorld {
+    pub directory: PathBuf,
+    pub info: WorldInfo,
+}
+
+/// Worlds in `saves` sorted by their name and directory. Worlds saved before they had a name are listed by
+/// the name of their directory
+pub fn list_worlds(saves: &Path) -> Vec<SavedWorld> {
+    let entries = match fs::read_dir(saves) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut worlds: Vec<SavedWorld> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|directory| directory.is_dir())
+        .filter_map(|directory| {
+            let info = if directory.join(WORLD_FILE).exists() {
+                match read_yaml(&directory.join(WORLD_FILE)) {
+                    Ok(info) => info,
+                    Err(e) => {
+                        error!("Failed to load the world `{}`: {}", directory.display(), e);
+                        return None;
+                    }
+                }
+            } else if directory.join(PLAYER_FILE).exists() {
+                WorldInfo {
+                    name: directory.file_name()?.to_string_lossy().into_owned(),
+                    seed: 0,
+                }
+            } else {
+                return None;
+            };
+            Some(SavedWorld { directory, info })
+        })
+        .collect();
+    worlds.sort_by(|a, b| (&a.info.name, &a.directory).cmp(&(&b.info.name, &b.directory)));
+    worlds
+}
+
+/// Create the directory of a new world in `saves`. The directory is named after the world,
+/// with a number added if there already is one of that name
+pub fn create_world(saves: &Path, info: WorldInfo) -> Result<SavedWorld, SaveError> {
+    let mut name: String = info
+        .name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.chars().all(|c| c == '_') {
+        name = DEFAULT_WORLD.to_owned();
+    }
+    let directory = (1..)
+        .map(|n| match n {
+            1 => saves.join(&name),
+            n => saves.join(format!("{}-{}", name, n)),
+        })
+        .find(|directory| !directory.exists())
+        .unwrap();
+    fs::create_dir_all(&directory).map_err(SaveError::Io)?;
+    write_yaml(&directory.join(WORLD_FILE), &info)?;
+    Ok(SavedWorld { directory, info })
+}
+
+pub fn delete_world(world: &SavedWorld) -> Result<(), SaveError> {
+    fs::remove_dir_all(&world.directory).map_err(SaveError::Io)
+}
+
+/// Directory worlds are saved in, nothing is saved without one
+pub fn saves_directory(config: &GameConfig) -> Option<PathBuf> {
+    // There is no file system to save to on the web
+    if cfg!(target_arch = "wasm32") {
+        None
+    } else {
+        config.saves.as_ref().map(PathBuf::from)
+    }
+}
+
+/// Directory the world that is played is saved in, nothing is saved without one
+#[derive(Default)]
+pub struct WorldSave {
+    directory: Option<PathBuf>,
+}
+
+impl WorldSave {
+    pub fn new(world: &SavedWorld) -> Self {
+        Self {
+            directory: Some(world.directory.clone()),
+        }
+    }
+
+    /// The player saved with the world, `None` for a new world. A save that fails to load is
+    /// kept as it is, saving is turned off for the session instead of overwriting it
+    pub fn load_player(&mut self) -> Option<PlayerSave> {
+        let path = self.directory.as_ref()?.join(PLAYER_FILE);
+        if !path.exists() {
+            return None;
+        }
+        match PlayerSave::from_file(&path) {
+            Ok(player) => Some(player),
+            Err(e) => {
+                error!(
+                    "Failed to load the player from `{}`, the world will not be saved: {}",
+                    path.display(),
+                    e
+                );
+                self.directory = None;
+                None
+            }
+        }
+    }
+
+    pub fn store_player(&self, player: &PlayerSave) -> Result<(), SaveError> {
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return Ok(()),
+        };
+        fs::create_dir_all(directory).map_err(SaveError::Io)?;
+        player.to_file(&directory.join(PLAYER_FILE))
+    }
+}
+
+#[derive(SystemParam)]
+pub struct PlayerState<'a> {
+    inventory: Res<'a, Inventory>,
+    health: Res<'a, Health>,
+    stamina: Res<'a, Stamina>,
+    settings: Res<'a, MovementSettings>,
+    input: Res<'a, InputState>,
+    bodies: Res<'a, RigidBodySet>,
+    query: Query<'a, &'static RigidBodyHandleComponent, With<BodyTag>>,
+}
+
+impl<'a> PlayerState<'a> {
+    fn save(&self) -> Option<PlayerSave> {
+        let handle = self.query.single().ok()?;
+        let body = self.bodies.get(handle.handle())?;
+        let translation = body.position().translation;
+        Some(PlayerSave {
+            position: [translation.x, translation.y, translation.z],
+            yaw: self.input.yaw,
+            pitch: self.input.pitch.to_degrees(),
+            fly: self.settings.fly,
+            inventory: self.inventory.clone(),
+            health: self.health.clone(),
+            stamina: self.stamina.clone(),
+        })
+    }
+
+    fn store(&self, world: &WorldSave) {
+        if let Some(save) = self.save() {
+            if let Err(e) = world.store_player(&save) {
+                error!("Failed to save the player: {}", e);
+            }
+        }
+    }
+}
+
+/// Save the player every `AUTOSAVE_INTERVAL` seconds and when the game is closed
+fn save_player(
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    world: Res<WorldSave>,
+    player: PlayerState,
+    mut exit: EventReader<AppExit>,
+    mut since_save: Local<f32>,
+) {
+    let exiting = exit.iter().count() > 0;
+    *since_save += time.delta_seconds();
+    // Outside of a world the player has not been placed yet or was already saved when leaving
+    let playing = matches!(state.current(), AppState::InGame | AppState::Paused);
+    if !playing || !(exiting || *since_save >= AUTOSAVE_INTERVAL) {
+        return;
+    }
+    *since_save = 0.0;
+    player.store(&world);
+}
+
+/// Save the player when they leave the world for the main menu, before the world is torn down
+fn save_on_leave(world: Res<WorldSave>, player: PlayerState) {
+    player.store(&world);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        inventory::Slot,
+        item::{Item, Tool},
+        world::BlockType,
+    };
+
+    #[test]
+    fn player_round_trip() {
+        let mut inventory = Inventory::survival_preset();
+        inventory.slots[2] = Slot::finite(Item::Block(BlockType::LadderNorth), 5);
+        inventory.slots[3] = Slot::Tool(Tool::StonePickaxe, 100);
+        inventory.switch_slot(3);
+        let mut health = Health::default();
+        health.damage(3);
+        let mut stamina = Stamina::default();
+        stamina.exert(7.5);
+        let player = PlayerSave {
+            position: [12.5, 40.0, -3.25],
+            yaw: -95.0,
+            pitch: 30.0,
+            fly: true,
+            inventory,
+            health,
+            stamina,
+        };
+
+        let path = std::env::temp_dir().join(format!("cobble-player-{}.yaml", std::process::id()));
+        player.to_file(&path).unwrap();
+        let loaded = PlayerSave::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), player);
+
+        assert!(matches!(
+            PlayerSave::from_file(&path),
+            Err(SaveError::Io(_))
+        ));
+        assert!(serde_yaml::from_str::<PlayerSave>("position: [0.0, 10.0, 0.0]").is_err());
+        let old = serde_yaml::to_string(&PlayerSave::new(Inventory::survival_preset()))
+            .unwrap()
+            .replace("health:", "unused_health:")
+            .replace("stamina:", "unused_stamina:");
+        let old = serde_yaml::from_str::<PlayerSave>(&old).unwrap();
+        assert_eq!(old.health, Health::default());
+        assert_eq!(old.stamina, Stamina::default());
+    }
+
+    #[test]
+    fn create_list_and_delete_worlds() {
+        let saves = std::env::temp_dir().join(format!("cobble-saves-{}", std::process::id()));
+        let info = |name: &str, seed| WorldInfo {
+            name: name.to_owned(),
+            seed,
+        };
+        let first = create_world(&saves, info("My World", 7)).unwrap();
+        let second = create_world(&saves, info("My World", 8)).unwrap();
+        let unnamed = create_world(&saves, info(" ?! ", 9)).unwrap();
+        assert_eq!(first.directory, saves.join("My_World"));
+        assert_eq!(second.directory, saves.join("My_World-2"));
+        assert_eq!(unnamed.directory, saves.join(DEFAULT_WORLD));
+        // A world saved before worlds had a name
+        fs::create_dir_all(saves.join("old")).unwrap();
+        PlayerSave::new(Inventory::survival_preset())
+            .to_file(&saves.join("old").join(PLAYER_FILE))
+            .unwrap();
+        fs::create_dir_all(saves.join("empty")).unwrap();
+
+        let names: Vec<(String, u32)> = list_worlds(&saves)
+            .into_iter()
+            .map(|world| (world.info.name, world.info.seed))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (" ?! ".to_owned(), 9),
+                ("My World".to_owned(), 7),
+                ("My World".to_owned(), 8),
+                ("old".to_owned(), 0),
+            ]
+        );
+        delete_world(&second).unwrap();
+        assert_eq!(list_worlds(&saves).len(), 3);
+        fs::remove_dir_all(&saves).unwrap();
+        assert!(list_worlds(&saves).is_empty());
+
+        assert_eq!(WorldInfo::parse_seed(" 1234 "), 1234);
+        assert_eq!(
+            WorldInfo::parse_seed("cobble"),
+            WorldInfo::parse_seed("cobble")
+        );
+        assert_ne!(
+            WorldInfo::parse_seed("cobble"),
+            WorldInfo::parse_seed("Cobble")
+        );
+    }
+}
@@ -286,6 +286,10 @@ void main() {
                                       StandardMaterial_base_color_texture_sampler),
                             v_Uv);
 #endif
+    // Cut out transparent texels, e.g., the gaps between the rungs of a ladder
+    if (output_color.a < 0.1) {
+        discard;
+    }
 
 #ifndef STANDARDMATERIAL_UNLIT
     // calculate non-linear roughness from linear perceptualRoughness
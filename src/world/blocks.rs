@@ -1,7 +1,9 @@
+use bevy::math::IVec3;
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, PartialEq, Clone, Hash, Eq, Copy)]
+#[derive(Debug, PartialEq, Clone, Hash, Eq, Copy, Deserialize, Serialize)]
 #[repr(u8)]
 pub enum BlockType {
     Air = 0,
@@ -14,11 +16,20 @@ pub enum BlockType {
     Leaves = 7,
     Sand = 8,
     Gravel = 9,
+    Water = 10,
+    LadderNorth = 11,
+    LadderEast = 12,
+    LadderSouth = 13,
+    LadderWest = 14,
+    VinesNorth = 15,
+    VinesEast = 16,
+    VinesSouth = 17,
+    VinesWest = 18,
 }
 
-pub const TEXTURE_LAYERS: u32 = 12;
+pub const TEXTURE_LAYERS: u32 = 15;
 
-pub const EXCEPT_AIR: [BlockType; 9] = [
+pub const EXCEPT_AIR: [BlockType; 18] = [
     BlockType::Dirt,
     BlockType::Grass,
     BlockType::Cobble,
@@ -28,9 +39,19 @@ pub const EXCEPT_AIR: [BlockType; 9] = [
     BlockType::Leaves,
     BlockType::Sand,
     BlockType::Gravel,
+    BlockType::Water,
+    BlockType::LadderNorth,
+    BlockType::LadderEast,
+    BlockType::LadderSouth,
+    BlockType::LadderWest,
+    BlockType::VinesNorth,
+    BlockType::VinesEast,
+    BlockType::VinesSouth,
+    BlockType::VinesWest,
 ];
 
-pub const EXCEPT_NONE_MESH_GROUP: [MeshGroup; 1] = [MeshGroup::Cube];
+pub const EXCEPT_NONE_MESH_GROUP: [MeshGroup; 3] =
+    [MeshGroup::Cube, MeshGroup::Fluid, MeshGroup::Panel];
 
 lazy_static! {
     pub static ref EXCEPT_AIR_SET: HashSet<BlockType> =
@@ -46,7 +67,7 @@ lazy_static! {
     pub static ref BLOCK_TEX_ID: HashMap<BlockType, [u32; 6]> = {
         use BlockType::*;
 
-        let mut m = HashMap::with_capacity(9);
+        let mut m = HashMap::with_capacity(18);
         m.insert(Dirt, [1; 6]);
         m.insert(Grass, [2, 1, 3, 3, 3, 3]);
         m.insert(Cobble, [4; 6]);
@@ -56,6 +77,13 @@ lazy_static! {
         m.insert(Gravel, [8; 6]);
         m.insert(Leaves, [9; 6]);
         m.insert(Wood, [10, 10, 11, 11, 11, 11]);
+        m.insert(Water, [12; 6]);
+        for ladder in [LadderNorth, LadderEast, LadderSouth, LadderWest].iter() {
+            m.insert(*ladder, [13; 6]);
+        }
+        for vines in [VinesNorth, VinesEast, VinesSouth, VinesWest].iter() {
+            m.insert(*vines, [14; 6]);
+        }
         m
     };
 }
@@ -64,13 +92,56 @@ lazy_static! {
 pub enum MeshGroup {
     None,
     Cube,
+    /// Translucent blocks that only hide faces of their own kind
+    Fluid,
+    /// Thin, double-sided blocks attached to the wall opposite of their facing
+    Panel,
 }
 
 pub struct BlockProperties {
     pub mesh_group: MeshGroup,
+    /// Whether entities collide with the block
+    pub solid: bool,
+    /// Whether the player can swim in the block
+    pub fluid: bool,
+    /// Whether the player can climb the block
+    pub climbable: bool,
+    /// Most blocks of this type a single inventory slot holds
+    pub max_stack: u32,
+    /// Kind of tool that breaks the block faster
+    pub category: BlockCategory,
+    /// Seconds it takes to break the block by hand
+    pub hardness: f32,
+}
+
+/// Groups of blocks that are broken faster with the same tool
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum BlockCategory {
+    None,
+    Soil,
+    Stone,
+    Wood,
+    Plant,
+}
+
+/// Category and hardness of a block
+fn breaking(block_type: &BlockType) -> (BlockCategory, f32) {
+    use BlockType::*;
+
+    match block_type {
+        Dirt | Sand => (BlockCategory::Soil, 0.5),
+        Grass | Gravel => (BlockCategory::Soil, 0.6),
+        Cobble | Bricks => (BlockCategory::Stone, 3.0),
+        Wood => (BlockCategory::Wood, 2.0),
+        Planks => (BlockCategory::Wood, 1.5),
+        LadderNorth | LadderEast | LadderSouth | LadderWest => (BlockCategory::Wood, 0.4),
+        Leaves | VinesNorth | VinesEast | VinesSouth | VinesWest => (BlockCategory::Plant, 0.2),
+        Air | Water => (BlockCategory::None, 0.0),
+    }
 }
 
 pub fn properties(block_type: &BlockType) -> BlockProperties {
+    let (category, hardness) = breaking(block_type);
     match block_type {
         BlockType::Dirt
         | BlockType::Grass
@@ -82,9 +153,170 @@ pub fn properties(block_type: &BlockType) -> BlockProperties {
         | BlockType::Sand
         | BlockType::Gravel => BlockProperties {
             mesh_group: MeshGroup::Cube,
+            solid: true,
+            fluid: false,
+            climbable: false,
+            max_stack: 64,
+            category,
+            hardness,
+        },
+        BlockType::Water => BlockProperties {
+            mesh_group: MeshGroup::Fluid,
+            solid: false,
+            fluid: true,
+            climbable: false,
+            max_stack: 1,
+            category,
+            hardness,
+        },
+        BlockType::LadderNorth
+        | BlockType::LadderEast
+        | BlockType::LadderSouth
+        | BlockType::LadderWest
+        | BlockType::VinesNorth
+        | BlockType::VinesEast
+        | BlockType::VinesSouth
+        | BlockType::VinesWest => BlockProperties {
+            mesh_group: MeshGroup::Panel,
+            solid: false,
+            fluid: false,
+            climbable: true,
+            max_stack: 64,
+            category,
+            hardness,
         },
         _ => BlockProperties {
             mesh_group: MeshGroup::None,
+            solid: false,
+            fluid: false,
+            climbable: false,
+            max_stack: 1,
+            category,
+            hardness,
         },
     }
 }
+
+/// Horizontal direction a block is facing, i.e., pointing away from the wall it is attached to
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Facing {
+    /// Towards -Z
+    North,
+    /// Towards +X
+    East,
+    /// Towards +Z
+    South,
+    /// Towards -X
+    West,
+}
+
+impl Facing {
+    pub fn normal(&self) -> IVec3 {
+        match self {
+            Facing::North => -IVec3::Z,
+            Facing::East => IVec3::X,
+            Facing::South => IVec3::Z,
+            Facing::West => -IVec3::X,
+        }
+    }
+
+    /// Facing of a horizontal unit normal, `None` for any other vector
+    pub fn from_normal(normal: IVec3) -> Option<Facing> {
+        [Facing::North, Facing::East, Facing::South, Facing::West]
+            .iter()
+            .find(|facing| facing.normal() == normal)
+            .copied()
+    }
+}
+
+impl BlockType {
+    /// Facing of blocks that have one
+    pub fn facing(&self) -> Option<Facing> {
+        use BlockType::*;
+
+        match self {
+            LadderNorth | VinesNorth => Some(Facing::North),
+            LadderEast | VinesEast => Some(Facing::East),
+            LadderSouth | VinesSouth => Some(Facing::South),
+            LadderWest | VinesWest => Some(Facing::West),
+            _ => None,
+        }
+    }
+
+    /// Variant of the same block facing in the given direction, or the block itself if it has
+    /// no facing
+    pub fn with_facing(&self, facing: Facing) -> BlockType {
+        use BlockType::*;
+
+        let [north, east, south, west] = match self {
+            LadderNorth | LadderEast | LadderSouth | LadderWest => {
+                [LadderNorth, LadderEast, LadderSouth, LadderWest]
+            }
+            VinesNorth | VinesEast | VinesSouth | VinesWest => {
+                [VinesNorth, VinesEast, VinesSouth, VinesWest]
+            }
+            _ => return *self,
+        };
+        match facing {
+            Facing::North => north,
+            Facing::East => east,
+            Facing::South => south,
+            Facing::West => west,
+        }
+    }
+
+    /// Form of the block as it is held in the inventory, so that differently oriented blocks
+    /// stack
+    pub fn item(&self) -> BlockType {
+        self.with_facing(Facing::North)
+    }
+
+    /// Name of the block as it is shown to the player
+    pub fn name(&self) -> &'static str {
+        use BlockType::*;
+
+        match self {
+            Air => "Air",
+            Dirt => "Dirt",
+            Grass => "Grass",
+            Cobble => "Cobblestone",
+            Bricks => "Bricks",
+            Wood => "Wood",
+            Planks => "Planks",
+            Leaves => "Leaves",
+            Sand => "Sand",
+            Gravel => "Gravel",
+            Water => "Water",
+            LadderNorth | LadderEast | LadderSouth | LadderWest => "Ladder",
+            VinesNorth | VinesEast | VinesSouth | VinesWest => "Vines",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn facing_variants() {
+        for block in EXCEPT_AIR.iter() {
+            match block.facing() {
+                Some(facing) => {
+                    assert_eq!(block.with_facing(facing), *block);
+                    assert_eq!(block.item().facing(), Some(Facing::North));
+                    assert_eq!(block.item().with_facing(facing), *block);
+                }
+                None => {
+                    assert_eq!(block.with_facing(Facing::East), *block);
+                    assert_eq!(block.item(), *block);
+                }
+            }
+            assert!(BLOCK_TEX_ID.contains_key(block), "{:?}", block);
+        }
+        for facing in [Facing::North, Facing::East, Facing::South, Facing::West].iter() {
+            assert_eq!(Facing::from_normal(facing.normal()), Some(*facing));
+        }
+        assert_eq!(Facing::from_normal(IVec3::Y), None);
+        assert_eq!(Facing::from_normal(IVec3::ZERO), None);
+    }
+}
@@ -0,0 +1,229 @@
+//! Dropped items lying in the world until they are picked up by the player or despawn
+use bevy::{prelude::*, render::pipeline::RenderPipeline};
+use bevy_rapier3d::{
+    physics::PhysicsInterpolationComponent,
+    rapier::{dynamics::RigidBodyBuilder, geometry::ColliderBuilder},
+};
+
+use crate::{inventory::Inventory, AppState};
+
+use super::{
+    block_mesh, BlockType, Handles, PlayerPosition, COLLIDER_ITEM_UD, GROUP_ITEM, PLAYER_EYE_HEIGHT,
+};
+
+/// Edge length of the model and collider of a dropped item
+const ITEM_SIZE: f32 = 0.25;
+/// Rotation of the model in radians per second
+const SPIN_SPEED: f32 = 1.5;
+/// Seconds before a dropped item can be picked up
+const PICKUP_DELAY: f32 = 0.5;
+/// Seconds after which an item that was not picked up despawns
+const DESPAWN_AFTER: f32 = 300.0;
+/// Distance between an item and the player's body at which the item is picked up
+const PICKUP_RADIUS: f32 = 1.5;
+/// Distance within which identical items merge into one
+const MERGE_RADIUS: f32 = 1.0;
+
+/// Request to drop a stack of blocks into the world at an absolute position
+pub struct EventDropItem {
+    pub position: Vec3,
+    pub block: BlockType,
+    pub count: u32,
+}
+
+/// Stack of blocks lying in the world
+pub struct DroppedItem {
+    pub block: BlockType,
+    pub count: u32,
+    /// Seconds since the item was dropped
+    age: f32,
+}
+
+/// System labels for ECS
+#[derive(Clone, PartialEq, Eq, Hash, Debug, SystemLabel)]
+enum DropLabels {
+    Merge,
+}
+
+/// Spinning model of a dropped item, child of the item's rigid body
+struct DroppedItemModel;
+
+pub struct DroppedItemPlugin;
+
+impl Plugin for DroppedItemPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<EventDropItem>()
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(spawn_drops.system())
+                    .with_system(spin_drops.system())
+                    .with_system(age_drops.system())
+                    .with_system(merge_drops.system().label(DropLabels::Merge))
+                    .with_system(pickup_drops.system().after(DropLabels::Merge)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::InGame).with_system(despawn_drops.system()),
+            );
+    }
+}
+
+fn spawn_drops(
+    mut commands: Commands,
+    mut events: EventReader<EventDropItem>,
+    mut handles: ResMut<Handles>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for event in events.iter() {
+        let mesh = handles
+            .drop_meshes
+            .entry(event.block)
+            .or_insert_with(|| meshes.add(block_mesh(event.block)))
+            .clone();
+        let position = event.position;
+        let rigid_body = RigidBodyBuilder::new_dynamic()
+            .translation(position.x, position.y, position.z)
+            .linvel(0.0, 2.0, 0.0)
+            .lock_rotations()
+            .user_data(COLLIDER_ITEM_UD);
+        let collider = ColliderBuilder::cuboid(ITEM_SIZE / 2.0, ITEM_SIZE / 2.0, ITEM_SIZE / 2.0)
+            .collision_groups(GROUP_ITEM)
+            .user_data(COLLIDER_ITEM_UD);
+        let material = handles.atlas_material.clone();
+        let pipeline = handles.pipeline.clone();
+        commands
+            .spawn_bundle((
+                Transform::from_translation(position),
+                GlobalTransform::identity(),
+                rigid_body,
+                collider,
+                PhysicsInterpolationComponent::default(),
+                DroppedItem {
+                    block: event.block,
+                    count: event.count,
+                    age: 0.0,
+                },
+            ))
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(PbrBundle {
+                        mesh,
+                        material,
+                        render_pipelines: RenderPipelines::from_pipelines(vec![
+                            RenderPipeline::new(pipeline),
+                        ]),
+                        visible: Visible {
+                            is_transparent: true,
+                            ..Default::default()
+                        },
+                        transform: Transform {
+                            translation: Vec3::splat(-ITEM_SIZE / 2.0),
+                            scale: Vec3::splat(ITEM_SIZE),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .insert(DroppedItemModel);
+            });
+    }
+}
+
+/// Remove the items left lying in the world when leaving it
+fn despawn_drops(mut commands: Commands, query: Query<Entity, With<DroppedItem>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Rotate the models around the center of their item
+fn spin_drops(time: Res<Time>, mut query: Query<&mut Transform, With<DroppedItemModel>>) {
+    let rotation = Quat::from_rotation_y(time.seconds_since_startup() as f32 * SPIN_SPEED);
+    for mut transform in query.iter_mut() {
+        transform.rotation = rotation;
+        transform.translation = rotation * Vec3::splat(-ITEM_SIZE / 2.0);
+    }
+}
+
+fn age_drops(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut DroppedItem)>,
+) {
+    for (entity, mut item) in query.iter_mut() {
+        item.age += time.delta_seconds();
+        if item.age >= DESPAWN_AFTER {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Merge items of the same block that lie close to each other into the older one. The merged
+/// items are emptied right away, as they are only despawned at the end of the stage
+fn merge_drops(mut commands: Commands, mut query: Query<(Entity, &Transform, &mut DroppedItem)>) {
+    let mut items: Vec<(Entity, Vec3, BlockType, u32, f32)> = query
+        .iter_mut()
+        .filter(|(_, _, item)| item.count > 0)
+        .map(|(entity, transform, item)| {
+            (
+                entity,
+                transform.translation,
+                item.block,
+                item.count,
+                item.age,
+            )
+        })
+        .collect();
+    // Oldest first
+    items.sort_by(|a, b| b.4.partial_cmp(&a.4).unwrap_or(std::cmp::Ordering::Equal));
+
+    for i in 0..items.len() {
+        if items[i].3 == 0 {
+            continue;
+        }
+        for j in i + 1..items.len() {
+            if items[j].3 > 0
+                && items[j].2 == items[i].2
+                && items[j].1.distance(items[i].1) <= MERGE_RADIUS
+            {
+                items[i].3 += items[j].3;
+                items[j].3 = 0;
+                commands.entity(items[j].0).despawn_recursive();
+            }
+        }
+    }
+    for (entity, _, _, count, _) in items {
+        if let Ok((_, _, mut item)) = query.get_mut(entity) {
+            if item.count != count {
+                item.count = count;
+            }
+        }
+    }
+}
+
+fn pickup_drops(
+    mut commands: Commands,
+    position: Res<PlayerPosition>,
+    mut inventory: ResMut<Inventory>,
+    mut query: Query<(Entity, &Transform, &mut DroppedItem)>,
+) {
+    let eyes = position.absolut;
+    for (entity, transform, mut item) in query.iter_mut() {
+        // Merged items are empty until they are despawned
+        if item.age < PICKUP_DELAY || item.count == 0 {
+            continue;
+        }
+        // Closest point of the player's body, approximated as a vertical line
+        let item_position = transform.translation;
+        let body = Vec3::new(
+            eyes.x,
+            item_position.y.clamp(eyes.y - PLAYER_EYE_HEIGHT, eyes.y),
+            eyes.z,
+        );
+        if item_position.distance(body) <= PICKUP_RADIUS {
+            // Whatever does not fit into the inventory stays on the ground
+            item.count = inventory.absorb(item.block.into(), item.count);
+            if item.count == 0 {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
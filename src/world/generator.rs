@@ -1,18 +1,19 @@
 use super::*;
+use crate::config::{CobbleConfig, GeneratorConfig};
 use bevy::prelude::*;
 use noise::NoiseFn;
 use noise::*;
 
 pub trait WorldGenerator {
-    /// Statelessly generate a chunk
+    /// Statelessly generate a chunk column of `height` blocks, a multiple of the section size
     ///
     /// # Example
     /// ```
-    /// fn chunk(at: IVec2, seed: u32) -> GameChunk {
+    /// fn chunk(&self, at: IVec2, seed: u32, height: usize) -> GameChunk {
     ///     const max_height: usize = 3;
-    ///     assert!(max_height < defaults::CHUNK_HEIGHT);
+    ///     assert!(max_height < height);
     ///
-    ///     let mut voxels = Box::new(VoxelChunk::air(defaults::CHUNK_SHAPE));
+    ///     let mut voxels = VoxelChunk::air([defaults::CHUNK_WIDTH, height, defaults::CHUNK_WIDTH]);
     ///     for x in 0..defaults::CHUNK_WIDTH {
     ///         for z in 0..defaults::CHUNK_WIDTH {
     ///            for y in 0..max_height {
@@ -20,16 +21,266 @@ pub trait WorldGenerator {
     ///            }
     ///         }
     ///     }
+    ///     GameChunk::from_voxels(at, &voxels)
     /// }
     /// ```
-    fn chunk(at: IVec2, seed: u32) -> GameChunk;
+    fn chunk(&self, at: IVec2, seed: u32, height: usize) -> GameChunk;
+
+    /// Check whether the generator's definition changed since it was loaded and reload it if so.
+    /// Returns true if previously generated chunks are now outdated
+    fn reload(&mut self) -> bool {
+        false
+    }
+}
+
+/// The generator used for all chunk generation requests
+pub struct ActiveGenerator(pub Box<dyn WorldGenerator + Send + Sync>);
+
+impl ActiveGenerator {
+    pub fn from_config(config: &GeneratorConfig) -> Self {
+        match config {
+            GeneratorConfig::Basic => Self(Box::new(BasicWorld)),
+            GeneratorConfig::Graph { path, watch } => match GraphWorld::load(path, *watch) {
+                Ok(generator) => Self(Box::new(generator)),
+                Err(e) => {
+                    error!(
+                        "Failed to load noise graph `{}`, falling back to the basic generator: {}",
+                        path, e
+                    );
+                    Self(Box::new(BasicWorld))
+                }
+            },
+            GeneratorConfig::Heightmap(heightmap) => match HeightmapWorld::load(heightmap) {
+                Ok(generator) => Self(Box::new(generator)),
+                Err(e) => {
+                    error!(
+                        "Failed to load heightmap `{}`, falling back to the basic generator: {}",
+                        heightmap.path, e
+                    );
+                    Self(Box::new(BasicWorld))
+                }
+            },
+        }
+    }
+}
+
+/// Poll the active generator for changes of its definition and regenerate all loaded chunks if it
+/// changed. Note that this discards all modifications made to the loaded chunks
+pub fn generator_hot_reload(
+    mut generator: ResMut<ActiveGenerator>,
+    mut chunk_store: ResMut<NineSurroundChunk>,
+    mut event_chunk: EventWriter<EventChunkCommand>,
+    seed: Option<Res<Seed>>,
+    config: Res<CobbleConfig>,
+    time: Res<Time>,
+    mut timer: Local<Timer>,
+) {
+    if timer.duration() == std::time::Duration::default() {
+        *timer = Timer::from_seconds(1.0, true);
+    }
+    if !timer.tick(time.delta()).just_finished() || !generator.0.reload() {
+        return;
+    }
+    let seed = seed.map_or_else(|| 0u32, |s| s.0);
+    info!("World generator changed, regenerating loaded chunks");
+    let loaded: Vec<IVec2> = chunk_store.data.keys().cloned().collect();
+    for index in loaded {
+        chunk_store.data.insert(
+            index,
+            generator.0.chunk(index, seed, config.game.world_height()),
+        );
+        event_chunk.send(EventChunkCommand::Update(index));
+    }
+}
+
+/// Noise functions sampled, in world coordinates, by the surface and tree passes shared between
+/// generators
+pub struct TerrainNoise<'a> {
+    /// Height of the terrain surface
+    pub height: &'a dyn NoiseFn<[f64; 2]>,
+    /// Sand is placed where the biome value exceeds 0.5
+    pub biome: &'a dyn NoiseFn<[f64; 2]>,
+    /// Minimum terrain height at which grass is placed on top
+    pub level_grass: &'a dyn NoiseFn<[f64; 2]>,
+    /// Minimum terrain height at which dirt instead of gravel is placed
+    pub level_dirt: &'a dyn NoiseFn<[f64; 2]>,
+    /// Trees are planted where the distribution value is at least 0.96
+    pub tree_distribution: &'a dyn NoiseFn<[f64; 2]>,
+    pub tree_height: &'a dyn NoiseFn<[f64; 2]>,
+    /// Block replacing the topmost block of a column, if any
+    pub surface_block: Option<&'a dyn Fn([f64; 2]) -> Option<BlockType>>,
+    /// Air below this height is filled with water, no trees are planted in flooded columns
+    pub water_level: Option<usize>,
+}
+
+impl<'a> TerrainNoise<'a> {
+    /// Generate a chunk by running the surface and tree passes over every column. The terrain is
+    /// cut off at the top of the world
+    pub fn chunk(&self, at: IVec2, seed: u32, world_height: usize) -> GameChunk {
+        let mut voxels =
+            VoxelChunk::air([defaults::CHUNK_WIDTH, world_height, defaults::CHUNK_WIDTH]);
+        let chunk_offset_x: f64 = at.x as f64 * defaults::CHUNK_WIDTH as f64;
+        let chunk_offset_y: f64 = at.y as f64 * defaults::CHUNK_WIDTH as f64;
+        macro_rules! offset {
+            ($x:expr, $z:expr) => {
+                [($x as f64 + chunk_offset_x), ($z as f64 + chunk_offset_y)]
+            };
+        }
+        for x in 0..defaults::CHUNK_WIDTH {
+            for z in 0..defaults::CHUNK_WIDTH {
+                let height = (self.height.get(offset!(x, z)) as usize).clamp(0, world_height - 1);
+                let biome = self.biome.get(offset!(x, z));
+                surface_pass(
+                    &mut voxels,
+                    (x, z),
+                    height,
+                    biome,
+                    self.level_grass.get(offset!(x, z)),
+                    self.level_dirt.get(offset!(x, z)),
+                );
+                if let Some(block) = self.surface_block.and_then(|f| f(offset!(x, z))) {
+                    if height > 0 {
+                        voxels[(x, height - 1, z)] = block;
+                    }
+                }
+                let flooded = self.water_level.map_or(false, |level| height < level);
+                if biome <= 0.5 {
+                    if !flooded {
+                        tree_pass(
+                            &mut voxels,
+                            (x, z),
+                            height,
+                            seed,
+                            [chunk_offset_x, chunk_offset_y],
+                            self.tree_distribution,
+                            self.tree_height,
+                        );
+                    }
+                    voxels[(x, 0, z)] = BlockType::Cobble;
+                }
+                if let Some(level) = self.water_level {
+                    for y in height..level.min(world_height) {
+                        if voxels[(x, y, z)] == BlockType::Air {
+                            voxels[(x, y, z)] = BlockType::Water;
+                        }
+                    }
+                }
+            }
+        }
+        GameChunk::from_voxels(at, &voxels)
+    }
+}
+
+/// Fill a column up to `height` with sand, if the biome value exceeds 0.5, or layers of grass,
+/// dirt and gravel otherwise
+pub fn surface_pass(
+    voxels: &mut VoxelChunk<Block>,
+    (x, z): (usize, usize),
+    height: usize,
+    biome: f64,
+    level_grass: f64,
+    level_dirt: f64,
+) {
+    for y in 0..height {
+        voxels[(x, y, z)] = if biome <= 0.5 {
+            if height as f64 >= level_grass && y + 1 == height {
+                BlockType::Grass
+            } else if height as f64 >= level_dirt {
+                BlockType::Dirt
+            } else {
+                BlockType::Gravel
+            }
+        } else {
+            BlockType::Sand
+        };
+    }
+}
+
+/// Attempt to plant a tree on top of a column. `chunk_offset` is the world position of the
+/// chunk's origin on the x- and z-axis
+pub fn tree_pass(
+    voxels: &mut VoxelChunk<Block>,
+    (x, z): (usize, usize),
+    height: usize,
+    seed: u32,
+    chunk_offset: [f64; 2],
+    tree_distr: &dyn NoiseFn<[f64; 2]>,
+    height_tree: &dyn NoiseFn<[f64; 2]>,
+) {
+    let [chunk_offset_x, chunk_offset_y] = chunk_offset;
+    macro_rules! offset {
+        ($x:expr, $z:expr) => {
+            [($x as f64 + chunk_offset_x), ($z as f64 + chunk_offset_y)]
+        };
+    }
+    macro_rules! offset3 {
+        ($x:expr, $y:expr, $z:expr) => {
+            [
+                ($x as f64 + chunk_offset_x),
+                $y as f64,
+                ($z as f64 + chunk_offset_y),
+            ]
+        };
+    }
+    for attempt in 0..3 {
+        let val = tree_distr.get(offset!(x + attempt * 2000, z + attempt * 120));
+        if val >= 0.96
+            && ![0, 1, defaults::CHUNK_WIDTH - 2, defaults::CHUNK_WIDTH - 1].contains(&x)
+            && ![0, 1, defaults::CHUNK_WIDTH - 2, defaults::CHUNK_WIDTH - 1].contains(&z)
+        {
+            let height_tree = height_tree.get(offset!(x, z)) as usize;
+            let leaves = Fbm::new()
+                .set_seed(
+                    seed.wrapping_add(x.rem_euclid(u32::MAX as usize) as u32)
+                        .wrapping_add((z.wrapping_mul(2)).rem_euclid(u32::MAX as usize) as u32),
+                )
+                .set_frequency(2.0)
+                .set_lacunarity(2.0)
+                .set_octaves(15);
+            let leaves = ScalePoint::new(&leaves).set_scale(0.1);
+            let leaves = ScaleBias::new(&leaves).set_scale(0.5).set_bias(0.9);
+            let leaves = Clamp::new(&leaves).set_bounds(0.0, 1.0);
+            let top = voxels.height() - 1;
+            for y in height..(height + height_tree).clamp(0, top) {
+                voxels[(x, y, z)] = BlockType::Wood;
+            }
+
+            let lower_height = (height + height_tree).clamp(0, top);
+            let upper_height = (height + height_tree + 4).clamp(0, top);
+            for y in lower_height..upper_height {
+                for a in -4..4 {
+                    for b in -4..4 {
+                        if ((a as f32).powi(2)
+                            + (y as f32
+                                - lower_height as f32
+                                - (upper_height as f32 - lower_height as f32) / 3.0)
+                                .powi(2)
+                            + (b as f32).powi(2))
+                        .sqrt()
+                            / (3.0f32.powi(2) * 3.0).sqrt()
+                            * (leaves.get(offset3!(x as i32 + a, y * 2, z as i32 + b)) as f32)
+                            < 0.4
+                        {
+                            voxels[(
+                                (x as i32 - a).clamp(0, defaults::CHUNK_WIDTH as i32 - 1) as usize,
+                                y,
+                                (z as i32 - b).clamp(0, defaults::CHUNK_WIDTH as i32 - 1) as usize,
+                            )] = BlockType::Leaves;
+                        }
+                    }
+                }
+            }
+            break;
+        }
+    }
 }
 
 pub struct BasicWorld;
 impl WorldGenerator for BasicWorld {
     /// A basic procedural world generation algorithm. Note that this implementation has no
-    /// philosophy behind it and was tuned to make the end-result look okay
-    fn chunk(at: IVec2, seed: u32) -> GameChunk {
+    /// philosophy behind it and was tuned to make the end-result look okay. The terrain is scaled
+    /// to `defaults::TERRAIN_HEIGHT`, only mountain peaks reach above it in taller worlds
+    fn chunk(&self, at: IVec2, seed: u32, world_height: usize) -> GameChunk {
         let level_dirt = RidgedMulti::new().set_seed(seed);
         let level_dirt = ScalePoint::new(level_dirt).set_scale(0.01);
         let level_dirt_power_const = Constant::new(1.0);
@@ -37,7 +288,7 @@ impl WorldGenerator for BasicWorld {
         let level_dirt_offset = Constant::new(1.0);
         let level_dirt = Add::new(&level_dirt, &level_dirt_offset);
         let level_dirt = ScaleBias::new(&level_dirt)
-            .set_scale(defaults::CHUNK_HEIGHT as f64 / 4.0)
+            .set_scale(defaults::TERRAIN_HEIGHT as f64 / 4.0)
             .set_bias(0.0);
 
         let level_grass = RidgedMulti::new().set_seed(seed.wrapping_add(1));
@@ -47,7 +298,7 @@ impl WorldGenerator for BasicWorld {
         let level_grass_offset = Constant::new(0.1);
         let level_grass = Add::new(&level_grass, &level_grass_offset);
         let level_grass = ScaleBias::new(&level_grass)
-            .set_scale(defaults::CHUNK_HEIGHT as f64 / 4.0)
+            .set_scale(defaults::TERRAIN_HEIGHT as f64 / 4.0)
             .set_bias(0.0);
 
         let mix_nd = OpenSimplex::new().set_seed(seed);
@@ -89,7 +340,7 @@ impl WorldGenerator for BasicWorld {
         let height_mountains2 = ScalePoint::new(height_mountains2).set_scale(0.15);
         let height_mountains = Add::new(&height_mountains, &height_mountains2);
         let height_mountains = ScaleBias::<[f64; 2]>::new(&height_mountains)
-            .set_scale(defaults::CHUNK_HEIGHT as f64 / 1.0)
+            .set_scale(defaults::TERRAIN_HEIGHT as f64 / 1.0)
             .set_bias(0.0);
 
         let height_dirt = Perlin::new().set_seed(seed);
@@ -109,17 +360,17 @@ impl WorldGenerator for BasicWorld {
 
         let height_dirt = Multiply::new(&height_dirt, &height_dirt2);
         let height_dirt = ScaleBias::<[f64; 2]>::new(&height_dirt)
-            .set_scale(defaults::CHUNK_HEIGHT as f64 / 2.0)
+            .set_scale(defaults::TERRAIN_HEIGHT as f64 / 2.0)
             .set_bias(0.0);
         let height_sand = ScaleBias::<[f64; 2]>::new(&height_sand)
-            .set_scale(defaults::CHUNK_HEIGHT as f64 / 2.0)
+            .set_scale(defaults::TERRAIN_HEIGHT as f64 / 2.0)
             .set_bias(0.0);
         let height = Blend::new(&height_dirt, &height_mountains, &mix_nm);
         let height = Blend::new(&height, &height_sand, &mix_nd);
 
-        let height_offset = Constant::new(defaults::CHUNK_HEIGHT as f64 / 4.0);
+        let height_offset = Constant::new(defaults::TERRAIN_HEIGHT as f64 / 4.0);
         let add = Add::new(&height, &height_offset);
-        let clamp = Clamp::new(&add).set_bounds(2.0, defaults::CHUNK_HEIGHT as f64);
+        let clamp = Clamp::new(&add).set_bounds(2.0, world_height as f64);
         let output = &clamp;
 
         let tree_distr = SuperSimplex::new().set_seed(seed.wrapping_add(6));
@@ -133,119 +384,78 @@ impl WorldGenerator for BasicWorld {
         let height_tree = ScaleBias::new(&height_tree).set_scale(3.0).set_bias(3.0);
         let height_tree = ScalePoint::new(&height_tree).set_scale(1.1);
 
-        let mut voxels = Box::new(VoxelChunk::air(defaults::CHUNK_SHAPE));
-        let chunk_offset_x: f64 = at.x as f64 * defaults::CHUNK_WIDTH as f64;
-        let chunk_offset_y: f64 = at.y as f64 * defaults::CHUNK_WIDTH as f64;
-        macro_rules! offset {
-            ($x:expr, $z:expr) => {
-                [($x as f64 + chunk_offset_x), ($z as f64 + chunk_offset_y)]
-            };
-        }
-        macro_rules! offset3 {
-            ($x:expr, $y:expr, $z:expr) => {
-                [
-                    ($x as f64 + chunk_offset_x),
-                    $y as f64,
-                    ($z as f64 + chunk_offset_y),
-                ]
-            };
+        TerrainNoise {
+            height: output,
+            biome: &mix_nd_dithered,
+            level_grass: &level_grass,
+            level_dirt: &level_dirt,
+            tree_distribution: &tree_distr,
+            tree_height: &height_tree,
+            surface_block: None,
+            water_level: None,
         }
-        for x in 0..defaults::CHUNK_WIDTH {
-            for z in 0..defaults::CHUNK_WIDTH {
-                let height =
-                    (output.get(offset!(x, z)) as usize).clamp(0, defaults::CHUNK_HEIGHT - 1);
-
-                let mix_val = mix_nd_dithered.get(offset!(x, z));
-                for y in 0..height {
-                    voxels[(x, y, z)] = if mix_val <= 0.5 {
-                        if height as f64 >= level_grass.get(offset!(x, z)) && y + 1 == height {
-                            BlockType::Grass
-                        } else if height as f64 >= level_dirt.get(offset!(x, z)) {
-                            BlockType::Dirt
-                        } else {
-                            BlockType::Gravel
-                        }
-                    } else {
-                        BlockType::Sand
-                    };
-                }
-                if mix_val <= 0.5 {
-                    for attempt in 0..3 {
-                        let val = tree_distr.get(offset!(x + attempt * 2000, z + attempt * 120));
-                        if val >= 0.96
-                            && ![0, 1, defaults::CHUNK_WIDTH - 2, defaults::CHUNK_WIDTH - 1]
-                                .contains(&x)
-                            && ![0, 1, defaults::CHUNK_WIDTH - 2, defaults::CHUNK_WIDTH - 1]
-                                .contains(&z)
-                        {
-                            let height_tree = height_tree.get(offset!(x, z)) as usize;
-                            let leaves = Fbm::new()
-                                .set_seed(
-                                    seed.wrapping_add(x.rem_euclid(u32::MAX as usize) as u32)
-                                        .wrapping_add(
-                                            (z.wrapping_mul(2)).rem_euclid(u32::MAX as usize)
-                                                as u32,
-                                        ),
-                                )
-                                .set_frequency(2.0)
-                                .set_lacunarity(2.0)
-                                .set_octaves(15);
-                            let leaves = ScalePoint::new(&leaves).set_scale(0.1);
-                            let leaves = ScaleBias::new(&leaves).set_scale(0.5).set_bias(0.9);
-                            let leaves = Clamp::new(&leaves).set_bounds(0.0, 1.0);
-                            for y in
-                                height..(height + height_tree).clamp(0, defaults::CHUNK_HEIGHT - 1)
-                            {
-                                voxels[(x, y, z)] = BlockType::Wood;
-                            }
-
-                            let lower_height =
-                                (height + height_tree).clamp(0, defaults::CHUNK_HEIGHT - 1);
-                            let upper_height =
-                                (height + height_tree + 4).clamp(0, defaults::CHUNK_HEIGHT - 1);
-                            for y in lower_height..upper_height {
-                                for a in -4..4 {
-                                    for b in -4..4 {
-                                        if ((a as f32).powi(2)
-                                            + (y as f32
-                                                - lower_height as f32
-                                                - (upper_height as f32 - lower_height as f32)
-                                                    / 3.0)
-                                                .powi(2)
-                                            + (b as f32).powi(2))
-                                        .sqrt()
-                                            / (3.0f32.powi(2) * 3.0).sqrt()
-                                            * (leaves.get(offset3!(
-                                                x as i32 + a,
-                                                y * 2,
-                                                z as i32 + b
-                                            ))
-                                                as f32)
-                                            < 0.4
-                                        {
-                                            voxels[(
-                                                (x as i32 - a)
-                                                    .clamp(0, defaults::CHUNK_WIDTH as i32 - 1)
-                                                    as usize,
-                                                y,
-                                                (z as i32 - b)
-                                                    .clamp(0, defaults::CHUNK_WIDTH as i32 - 1)
-                                                    as usize,
-                                            )] = BlockType::Leaves;
-                                        }
-                                    }
-                                }
-                            }
-                            break;
-                        }
-                    }
-                    voxels[(x, 0, z)] = BlockType::Cobble;
-                }
+        .chunk(at, seed, world_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fmt::Write, fs, path::PathBuf};
+
+    const SEEDS: [u32; 4] = [0, 1, 42, 3_141_592];
+    const CHUNKS: [(i32, i32); 6] = [(0, 0), (-1, 0), (0, -1), (3, -7), (-12, 25), (100, -100)];
+
+    fn golden_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/goldens/basic_world.txt")
+    }
+
+    /// One line of `seed chunk_x chunk_z hash` per generated chunk
+    fn hashes(generator: &dyn WorldGenerator) -> String {
+        let mut out = String::new();
+        for seed in SEEDS.iter() {
+            for (x, z) in CHUNKS.iter() {
+                let hash = generator
+                    .chunk(IVec2::new(*x, *z), *seed, defaults::WORLD_HEIGHT)
+                    .content_hash();
+                writeln!(out, "{} {} {} {:016x}", seed, x, z, hash).unwrap();
             }
         }
-        GameChunk {
-            voxel: voxels,
-            index: at,
+        out
+    }
+
+    /// Guards the output of `BasicWorld` against accidental changes. After an intentional
+    /// change, regenerate the goldens with `UPDATE_GOLDENS=1 cargo test basic_world_goldens`
+    #[test]
+    fn basic_world_goldens() {
+        let actual = hashes(&BasicWorld);
+        if env::var_os("UPDATE_GOLDENS").is_some() {
+            fs::create_dir_all(golden_path().parent().unwrap()).unwrap();
+            fs::write(golden_path(), &actual).unwrap();
+            return;
         }
+        let expected = fs::read_to_string(golden_path()).expect("Failed to read the goldens");
+        let mismatches: Vec<_> = expected
+            .lines()
+            .zip(actual.lines())
+            .filter(|(e, a)| e != a)
+            .map(|(e, a)| format!("expected `{}`, got `{}`", e, a))
+            .collect();
+        assert!(
+            mismatches.is_empty() && expected.lines().count() == actual.lines().count(),
+            "BasicWorld output changed, regenerate the goldens with UPDATE_GOLDENS=1 if this is \
+             intentional:\n{}",
+            mismatches.join("\n")
+        );
+    }
+
+    #[test]
+    fn basic_graph_matches_basic_world() {
+        let graph = GraphWorld::load(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets/worldgen/basic.yaml"),
+            false,
+        )
+        .unwrap();
+        assert_eq!(hashes(&graph), hashes(&BasicWorld));
     }
 }
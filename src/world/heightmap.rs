@@ -0,0 +1,239 @@
+use bevy::math::IVec2;
+use image::{ImageBuffer, Luma, RgbImage};
+use noise::{
+    Clamp, Constant, NoiseFn, Perlin, Power, ScaleBias, ScalePoint, Seedable, SuperSimplex,
+};
+
+use crate::config::{HeightmapConfig, OutOfBounds};
+
+use super::{BlockType, GameChunk, TerrainNoise, WorldGenerator};
+
+/// Colors of the block image and the blocks placed on the surface for them. Pixels are mapped to
+/// the block with the closest color, black pixels leave the surface untouched
+pub const BLOCK_COLORS: [([u8; 3], Option<BlockType>); 11] = [
+    ([0, 0, 0], None),
+    ([95, 159, 53], Some(BlockType::Grass)),
+    ([134, 96, 67], Some(BlockType::Dirt)),
+    ([219, 211, 160], Some(BlockType::Sand)),
+    ([136, 126, 126], Some(BlockType::Gravel)),
+    ([100, 100, 100], Some(BlockType::Cobble)),
+    ([150, 97, 83], Some(BlockType::Bricks)),
+    ([157, 128, 79], Some(BlockType::Planks)),
+    ([60, 110, 30], Some(BlockType::Leaves)),
+    ([102, 81, 50], Some(BlockType::Wood)),
+    ([63, 118, 228], Some(BlockType::Water)),
+];
+
+/// World generator taking the terrain height, and optionally the surface blocks, from images
+/// instead of noise. The images are centred on the world origin
+pub struct HeightmapWorld {
+    height: ImageBuffer<Luma<u16>, Vec<u16>>,
+    blocks: Option<RgbImage>,
+    config: HeightmapConfig,
+}
+
+impl HeightmapWorld {
+    pub fn load(config: &HeightmapConfig) -> Result<Self, image::ImageError> {
+        let height = image::open(&config.path)?.into_luma16();
+        let blocks = match &config.blocks {
+            Some(path) => Some(image::open(path)?.into_rgb8()),
+            None => None,
+        };
+        Ok(Self::new(height, blocks, config.clone()))
+    }
+
+    pub fn new(
+        height: ImageBuffer<Luma<u16>, Vec<u16>>,
+        blocks: Option<RgbImage>,
+        config: HeightmapConfig,
+    ) -> Self {
+        Self {
+            height,
+            blocks,
+            config,
+        }
+    }
+
+    #[cfg(test)]
+    fn from_gray(height: &image::GrayImage, config: HeightmapConfig) -> Self {
+        let height = ImageBuffer::from_fn(height.width(), height.height(), |x, y| {
+            Luma([height.get_pixel(x, y).0[0] as u16 * 257])
+        });
+        Self::new(height, None, config)
+    }
+
+    /// Convert world coordinates into (continuous) pixel coordinates of an image
+    fn to_pixel(&self, point: [f64; 2], (width, height): (u32, u32)) -> [f64; 2] {
+        [
+            point[0] / self.config.scale + width as f64 / 2.0,
+            point[1] / self.config.scale + height as f64 / 2.0,
+        ]
+    }
+
+    fn pixel_height(&self, x: i64, y: i64, world_height: usize) -> Option<f64> {
+        let (width, height) = self.height.dimensions();
+        let (x, y) = match self.config.out_of_bounds {
+            OutOfBounds::Height(_) if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 => {
+                return None
+            }
+            OutOfBounds::Height(_) => (x as u32, y as u32),
+            OutOfBounds::Edge => (
+                x.clamp(0, width as i64 - 1) as u32,
+                y.clamp(0, height as i64 - 1) as u32,
+            ),
+        };
+        let value = self.height.get_pixel(x, y).0[0] as f64;
+        Some(if self.config.scale_to_height {
+            value / u16::MAX as f64 * (world_height - 1) as f64
+        } else {
+            // Interpret the image as 8-bit, one block per step
+            value / 257.0
+        })
+    }
+
+    /// Bilinearly interpolated terrain height at a world position
+    fn sample_height(&self, point: [f64; 2], world_height: usize) -> f64 {
+        let [x, y] = self.to_pixel(point, self.height.dimensions());
+        // Pixel centers lie at half coordinates
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+        let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+        let fill = match self.config.out_of_bounds {
+            OutOfBounds::Height(h) => h,
+            OutOfBounds::Edge => 0.0,
+        };
+        let h = |x, y| self.pixel_height(x, y, world_height).unwrap_or(fill);
+        let top = h(x0, y0) * (1.0 - tx) + h(x0 + 1, y0) * tx;
+        let bottom = h(x0, y0 + 1) * (1.0 - tx) + h(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    /// Surface block, if any, at a world position using the nearest pixel of the block image
+    fn sample_block(&self, point: [f64; 2]) -> Option<BlockType> {
+        let blocks = self.blocks.as_ref()?;
+        let [x, y] = self.to_pixel(point, blocks.dimensions());
+        let (x, y) = (x.floor() as i64, y.floor() as i64);
+        if x < 0 || y < 0 || x >= blocks.width() as i64 || y >= blocks.height() as i64 {
+            return None;
+        }
+        let pixel = blocks.get_pixel(x as u32, y as u32).0;
+        closest_block(pixel)
+    }
+}
+
+fn closest_block(color: [u8; 3]) -> Option<BlockType> {
+    BLOCK_COLORS
+        .iter()
+        .min_by_key(|(reference, _)| {
+            reference
+                .iter()
+                .zip(color.iter())
+                .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+                .sum::<i32>()
+        })
+        .and_then(|(_, block)| *block)
+}
+
+/// Height of the terrain sampled from the heightmap for a given world height
+struct HeightSampler<'a>(&'a HeightmapWorld, usize);
+
+impl<'a> NoiseFn<[f64; 2]> for HeightSampler<'a> {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        self.0.sample_height(point, self.1)
+    }
+}
+
+/// Biome value derived from the block image, only sand surfaces are treated as desert
+struct BiomeSampler<'a>(&'a HeightmapWorld);
+
+impl<'a> NoiseFn<[f64; 2]> for BiomeSampler<'a> {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        match self.0.sample_block(point) {
+            Some(BlockType::Sand) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+impl WorldGenerator for HeightmapWorld {
+    fn chunk(&self, at: IVec2, seed: u32, world_height: usize) -> GameChunk {
+        let height = HeightSampler(self, world_height);
+        let biome = BiomeSampler(self);
+        // Always place grass on top of dirt
+        let level = Constant::new(0.0);
+
+        let tree_distr = SuperSimplex::new().set_seed(seed.wrapping_add(6));
+        let tree_distr = ScalePoint::new(tree_distr).set_scale(0.15);
+        let tree_distr_exp = Constant::new(1.3);
+        let tree_distr = Power::new(&tree_distr, &tree_distr_exp);
+        let tree_distr = Clamp::new(&tree_distr).set_bounds(0.0, 1.0);
+        let no_trees = Constant::new(0.0);
+
+        let height_tree = Perlin::new().set_seed(seed.wrapping_add(13));
+        let height_tree = ScaleBias::new(&height_tree).set_scale(3.0).set_bias(3.0);
+        let height_tree = ScalePoint::new(&height_tree).set_scale(1.1);
+
+        let tree_distribution: &dyn NoiseFn<[f64; 2]> = if self.config.trees {
+            &tree_distr
+        } else {
+            &no_trees
+        };
+        let surface_block = |point: [f64; 2]| self.sample_block(point);
+        TerrainNoise {
+            height: &height,
+            biome: &biome,
+            level_grass: &level,
+            level_dirt: &level,
+            tree_distribution,
+            tree_height: &height_tree,
+            surface_block: self
+                .blocks
+                .as_ref()
+                .map(|_| &surface_block as &dyn Fn([f64; 2]) -> Option<BlockType>),
+            water_level: self.config.water_level,
+        }
+        .chunk(at, seed, world_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    fn config(scale_to_height: bool, out_of_bounds: OutOfBounds) -> HeightmapConfig {
+        HeightmapConfig {
+            scale_to_height,
+            out_of_bounds,
+            trees: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sample_heightmap() {
+        let image = GrayImage::from_fn(4, 4, |x, _| Luma([x as u8 * 10]));
+        let world = HeightmapWorld::from_gray(&image, config(false, OutOfBounds::Height(3.0)));
+
+        // The image is centred on the origin, so world x = -2 is the first column of pixels
+        const HEIGHT: usize = 64;
+        assert!((world.sample_height([-1.5, 0.5], HEIGHT) - 0.0).abs() < 1e-6);
+        assert!((world.sample_height([0.5, 0.5], HEIGHT) - 20.0).abs() < 1e-6);
+        assert!((world.sample_height([0.0, 0.5], HEIGHT) - 15.0).abs() < 1e-6);
+        assert!((world.sample_height([100.0, 100.0], HEIGHT) - 3.0).abs() < 1e-6);
+
+        let world = HeightmapWorld::from_gray(&image, config(false, OutOfBounds::Edge));
+        assert!((world.sample_height([100.0, 0.5], HEIGHT) - 30.0).abs() < 1e-6);
+
+        let image = GrayImage::from_pixel(2, 2, Luma([255]));
+        let world = HeightmapWorld::from_gray(&image, config(true, OutOfBounds::Edge));
+        assert!((world.sample_height([0.0, 0.0], HEIGHT) - (HEIGHT - 1) as f64).abs() < 1e-6);
+    }
+
+    #[test]
+    fn block_colors() {
+        assert_eq!(closest_block([0, 0, 0]), None);
+        assert_eq!(closest_block([230, 220, 150]), Some(BlockType::Sand));
+        assert_eq!(closest_block([90, 160, 50]), Some(BlockType::Grass));
+    }
+}
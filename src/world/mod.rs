@@ -1,6 +1,10 @@
 pub mod blocks;
+pub mod drops;
 pub mod generator;
+pub mod heightmap;
+pub mod noise_graph;
 pub mod physics;
+pub mod preview;
 pub mod raycast;
 pub mod voxel;
 
@@ -20,21 +24,27 @@ use bevy::{
         texture::{AddressMode, SamplerDescriptor},
     },
     tasks::AsyncComputeTaskPool,
+    transform::TransformSystem,
 };
 use bevy_rapier3d::physics::RapierConfiguration;
 
 use crate::{
     config::CobbleConfig,
-    interface::controller::{CameraTag, ControllerLabels},
+    interface::controller::{CameraTag, CAMERA_HEIGHT},
     inventory::Inventory,
+    save::PlayerSave,
     shader, AppState,
 };
 
 pub(super) use self::blocks::*;
 pub(super) use self::generator::*;
+pub(super) use self::heightmap::*;
+pub(super) use self::noise_graph::*;
 pub(super) use self::physics::*;
 pub(super) use self::voxel::*;
 
+use self::drops::EventDropItem;
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug, SystemLabel)]
 enum WorldLabels {
     VoxelModification,
@@ -50,17 +60,30 @@ impl Plugin for WorldPlugin {
         app.insert_resource(NineSurroundChunk::empty())
             .insert_resource(Handles::default())
             .insert_resource(PlayerPosition::default())
+            .insert_resource(SectionColliders::default())
+            .insert_resource(PhysicsClock::default())
             .add_event::<EventChunkCommand>()
             .add_event::<EventChunkAction>()
             .add_system_to_stage(
                 CoreStage::PostUpdate,
                 movement.system().label(WorldLabels::Movement),
             )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate_bodies
+                    .system()
+                    .before(TransformSystem::TransformPropagate),
+            )
             .add_system_set(
                 SystemSet::on_update(AppState::InGame)
                     .with_system(voxel_action.system())
                     .label(WorldLabels::VoxelModification),
             )
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(generator_hot_reload.system())
+                    .before(WorldLabels::ChunkLoad),
+            )
             .add_system_set(
                 SystemSet::on_update(AppState::InGame)
                     .with_system(chunk_load.system())
@@ -76,13 +99,12 @@ impl Plugin for WorldPlugin {
             )
             .add_system_set(
                 SystemSet::on_update(AppState::InGame)
-                    .with_system(update_colliders.system())
-                    .with_run_criteria(run_criteria_update_colliders.system())
+                    .with_system(section_colliders.system())
                     .label(WorldLabels::UpdateColliders)
-                    .before(ControllerLabels::PlayerMove),
+                    .after(WorldLabels::ChunkLoad),
             )
             .add_system_set(
-                SystemSet::on_enter(AppState::InGame).with_system(setup_collider.system()),
+                SystemSet::on_enter(AppState::InGame).with_system(setup_sensor.system()),
             )
             .add_system_set(
                 SystemSet::on_enter(AppState::InGame).with_system(setup_lights.system()),
@@ -91,7 +113,16 @@ impl Plugin for WorldPlugin {
                 SystemSet::on_update(AppState::InGame).with_system(update_lights.system()),
             )
             .add_system_set(
-                SystemSet::on_exit(AppState::Loading).with_system(initial_chunk_load.system()),
+                SystemSet::on_enter(AppState::InGame).with_system(initial_chunk_load.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::InGame).with_system(teardown_world.system()),
+            )
+            .add_system_set(
+                SystemSet::on_pause(AppState::InGame).with_system(pause_physics.system()),
+            )
+            .add_system_set(
+                SystemSet::on_resume(AppState::InGame).with_system(resume_physics.system()),
             )
             .add_system_set(
                 SystemSet::on_enter(AppState::Loading).with_system(load_textures.system()),
@@ -107,6 +138,7 @@ fn load_textures(
     mut handles: ResMut<Handles>,
     asset_server: Res<AssetServer>,
     mut rapier: ResMut<RapierConfiguration>,
+    mut clock: ResMut<PhysicsClock>,
 
     #[cfg(feature = "inline_assets")] inline_asset_handles: Res<
         HashMap<&'static Path, HandleUntyped>,
@@ -114,7 +146,7 @@ fn load_textures(
 ) {
     handles.atlas = asset_server.load("images/atlas.png");
     // Deactive the physics pipeline
-    rapier.physics_pipeline_active = false;
+    clock.active = false;
     rapier.query_pipeline_active = false;
 }
 
@@ -122,6 +154,7 @@ fn load_textures(
 fn load_textures(
     mut handles: ResMut<Handles>,
     mut rapier: ResMut<RapierConfiguration>,
+    mut clock: ResMut<PhysicsClock>,
     inline_asset_handles: Res<HashMap<&'static Path, HandleUntyped>>,
 ) {
     handles.atlas = inline_asset_handles
@@ -130,24 +163,66 @@ fn load_textures(
         .clone()
         .typed();
     // Deactive the physics pipeline
-    rapier.physics_pipeline_active = false;
+    clock.active = false;
     rapier.query_pipeline_active = false;
 }
 
 fn initial_chunk_load(
     chunk_store: ResMut<NineSurroundChunk>,
+    player: Res<PlayerSave>,
     mut event: EventWriter<EventChunkCommand>,
     mut rapier: ResMut<RapierConfiguration>,
+    mut clock: ResMut<PhysicsClock>,
 ) {
-    // Load the surrounding chunks on startup
-    for missing_chunk in chunk_store.missing_chunks(&Vec3::ZERO) {
+    // Load the chunks surrounding the player on entering the world, who is not at the origin if
+    // they were restored from a save
+    for missing_chunk in chunk_store.missing_chunks(&Vec3::from(player.position)) {
         event.send(EventChunkCommand::Load(missing_chunk));
     }
     // Active the physics pipeline
-    rapier.physics_pipeline_active = true;
+    clock.active = true;
     rapier.query_pipeline_active = true;
 }
 
+/// Remove everything of the world that was left, so that the next world starts from scratch.
+/// The player and other entities are removed by the plugins that spawn them
+#[allow(clippy::too_many_arguments)]
+fn teardown_world(
+    mut commands: Commands,
+    mut chunk_store: ResMut<NineSurroundChunk>,
+    mut handles: ResMut<Handles>,
+    mut colliders: ResMut<SectionColliders>,
+    mut position: ResMut<PlayerPosition>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut rapier: ResMut<RapierConfiguration>,
+    mut clock: ResMut<PhysicsClock>,
+    query: Query<Entity, Or<(With<AssociatedChunk>, With<SunTag>, With<PlayerSensor>)>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for (_, (handle, _)) in handles.sections.drain() {
+        meshes.remove(handle);
+    }
+    handles.chunks_entities = ChunkEntitySet::new();
+    for entity in colliders.0.drain().flat_map(|(_, entities)| entities) {
+        commands.entity(entity).despawn();
+    }
+    *chunk_store = NineSurroundChunk::empty();
+    *position = PlayerPosition::default();
+    *clock = PhysicsClock::default();
+    rapier.query_pipeline_active = false;
+}
+
+/// Stop the simulation while the game is paused, the time in between is not simulated later
+fn pause_physics(mut clock: ResMut<PhysicsClock>) {
+    *clock = PhysicsClock::default();
+}
+
+fn resume_physics(mut clock: ResMut<PhysicsClock>) {
+    clock.active = true;
+}
+
 struct SunTag;
 
 fn setup_lights(mut commands: Commands, mut ambient_light: ResMut<AmbientLight>) {
@@ -170,7 +245,10 @@ fn setup_lights(mut commands: Commands, mut ambient_light: ResMut<AmbientLight>)
 
 /// Event to request loading or unloading of a specific chunk
 pub enum EventChunkCommand {
+    /// Rebuild the meshes of all sections of a chunk
     Update(IVec2),
+    /// Rebuild the meshes of a single section of a chunk
+    UpdateSection(IVec2, usize),
     Load(IVec2),
     Unload(IVec2),
 }
@@ -188,11 +266,14 @@ struct ChunkEntitySet(HashMap<IVec2, (HashSet<Entity>, HashSet<MeshGroup>)>);
 /// Stores handles to the current loaded meshes and other related assets, such as materials
 #[derive(Clone)]
 pub struct Handles {
-    chunks: HashMap<(IVec2, MeshGroup), Handle<Mesh>>,
+    /// Mesh and entity of every section and mesh group that has a non-empty mesh
+    sections: HashMap<(IVec2, usize, MeshGroup), (Handle<Mesh>, Entity)>,
     chunks_entities: ChunkEntitySet,
     atlas: Handle<Texture>,
     atlas_material: Handle<StandardMaterial>,
     pipeline: Handle<PipelineDescriptor>,
+    /// Models of dropped items, created on first use
+    drop_meshes: HashMap<BlockType, Handle<Mesh>>,
 }
 
 impl IntoIterator for Handles {
@@ -221,26 +302,48 @@ impl ChunkEntitySet {
 impl Default for Handles {
     fn default() -> Self {
         Self {
-            chunks: HashMap::with_capacity(9),
+            sections: HashMap::with_capacity(9 * 4),
             chunks_entities: ChunkEntitySet::new(),
             atlas: Default::default(),
             atlas_material: Default::default(),
             pipeline: Default::default(),
+            drop_meshes: HashMap::new(),
         }
     }
 }
 
+/// Distance between the player's eyes, i.e., the camera above the body's center, and feet
+pub const PLAYER_EYE_HEIGHT: f32 = PLAYER_HALF_HEIGHT + CAMERA_HEIGHT;
+/// Half height and radius of the player's body collider
+pub const PLAYER_HALF_HEIGHT: f32 = 0.8;
+pub const PLAYER_RADIUS: f32 = 0.1;
+
 #[derive(Default)]
 pub struct PlayerPosition {
+    /// Position of the player's eyes
     pub absolut: Vec3,
     /// Chunk the player is in
     pub chunk: IVec2,
     /// Position inside the chunk
     pub index: UVec3,
+    /// Blocks around the player that affect their movement
+    pub surroundings: Surroundings,
+}
+
+/// Blocks around the player's body that affect their movement
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Surroundings {
+    /// Whether the player's eyes are inside a fluid block
+    pub head_in_fluid: bool,
+    /// Whether the player's feet are inside a fluid block
+    pub feet_in_fluid: bool,
+    /// Whether the player's body overlaps a climbable block
+    pub climbing: bool,
 }
 
 struct AssociatedChunk {
     chunk: IVec2,
+    section: usize,
     mesh_group: MeshGroup,
 }
 
@@ -285,10 +388,11 @@ fn voxel_action(
     mut chunk_store: ResMut<NineSurroundChunk>,
     mut chunk_mod: EventReader<EventChunkAction>,
     mut voxel_update: EventWriter<EventChunkCommand>,
+    mut drops: EventWriter<EventDropItem>,
     mut inventory: ResMut<Inventory>,
     config: Res<CobbleConfig>,
 ) {
-    let mut voxels_to_update = HashSet::new();
+    let mut sections_to_update = HashSet::new();
     for event in chunk_mod.iter() {
         match *event {
             EventChunkAction::ModifyBlock(chunk, index, block_type, absorb) => {
@@ -296,25 +400,61 @@ fn voxel_action(
                     return;
                 }
                 if let Some(chunk_data) = chunk_store.data.get_mut(&chunk) {
+                    let previous = match chunk_data.get(index) {
+                        Some(previous) => previous,
+                        None => continue,
+                    };
                     if absorb && block_type == BlockType::Air {
-                        inventory.absorb(chunk_data.voxel[index], 1);
+                        // In creative mode the block goes straight into the inventory, what does
+                        // not fit is dropped
+                        let count = if config.game.creative {
+                            inventory.absorb(previous.into(), 1)
+                        } else {
+                            1
+                        };
+                        if count > 0 {
+                            drops.send(EventDropItem {
+                                position: index_to_absolut::<{ defaults::CHUNK_WIDTH }>(
+                                    chunk, index,
+                                )
+                                .as_f32()
+                                    + Vec3::splat(0.5),
+                                block: previous.item(),
+                                count,
+                            });
+                        }
+                    }
+                    chunk_data.set(index, block_type);
+                    // Blocks on the border of a section also affect the faces of the adjacent one
+                    let y = index.y as usize;
+                    let section = y / defaults::SECTION_SIZE;
+                    sections_to_update.insert((chunk, section));
+                    if y % defaults::SECTION_SIZE == 0 && section > 0 {
+                        sections_to_update.insert((chunk, section - 1));
+                    }
+                    if y % defaults::SECTION_SIZE == defaults::SECTION_SIZE - 1
+                        && y + 1 < chunk_data.height()
+                    {
+                        sections_to_update.insert((chunk, section + 1));
                     }
-                    chunk_data.voxel[index] = block_type;
-                    voxels_to_update.insert(chunk);
                 }
             }
             EventChunkAction::PickBlock(chunk, index) if config.game.creative => {
-                if let Some(chunk_data) = chunk_store.data.get_mut(&chunk) {
-                    inventory.absorb_creative(chunk_data.voxel[index]);
+                if let Some(block) = chunk_store
+                    .data
+                    .get(&chunk)
+                    .and_then(|chunk_data| chunk_data.get(index))
+                {
+                    inventory.absorb_creative(block.into());
                 }
             }
             _ => {}
         }
     }
     voxel_update.send_batch(
-        voxels_to_update
+        sections_to_update
             .iter()
-            .map(|voxel| EventChunkCommand::Update(*voxel)),
+            .map(|(chunk, section)| EventChunkCommand::UpdateSection(*chunk, *section)),
     );
 }
 
@@ -327,6 +467,7 @@ fn movement(
 ) {
     if let Ok(transform) = query.single() {
         position.absolut = transform.translation;
+        position.surroundings = chunk_store.surroundings(transform.translation);
         let (new_chunk, new_index) =
             absolut_to_index::<{ defaults::CHUNK_WIDTH }>(&transform.translation);
         if new_chunk != *last_chunk {
@@ -344,11 +485,15 @@ fn movement(
 }
 
 /// Update the position of the sun-light relative to the player position on the x- and z-axis
-fn update_lights(mut query: Query<&mut Transform, With<SunTag>>, position: Res<PlayerPosition>) {
+fn update_lights(
+    mut query: Query<&mut Transform, With<SunTag>>,
+    position: Res<PlayerPosition>,
+    config: Res<CobbleConfig>,
+) {
     if let Ok(mut transform) = query.single_mut() {
         let light_position = Vec3::from([
             position.absolut.x + 20.0,
-            defaults::CHUNK_HEIGHT as f32 + 30.0,
+            config.game.world_height() as f32 + 30.0,
             position.absolut.z + 20.0,
         ]);
         *transform = Transform::from_translation(light_position);
@@ -357,13 +502,15 @@ fn update_lights(mut query: Query<&mut Transform, With<SunTag>>, position: Res<P
 
 /// Seed used for world generation
 #[derive(Default, Copy, Clone)]
-pub struct Seed(u32);
+pub struct Seed(pub u32);
 
 /// Generate or load a chunk (only the voxel data) into the chunk store on request. This also include unloading chunks
 fn chunk_load(
     mut chunk_store: ResMut<NineSurroundChunk>,
     mut event_chunk: EventReader<EventChunkCommand>,
+    generator: Res<ActiveGenerator>,
     seed: Option<Res<Seed>>,
+    config: Res<CobbleConfig>,
     _commands: Commands,
     _thread_pool: Res<AsyncComputeTaskPool>,
 ) {
@@ -378,7 +525,10 @@ fn chunk_load(
                 }
                 if chunk_store
                     .data
-                    .insert(*index, BasicWorld::chunk(*index, seed))
+                    .insert(
+                        *index,
+                        generator.0.chunk(*index, seed, config.game.world_height()),
+                    )
                     .is_some()
                 {
                     info!("Loaded (overrode) an already loaded chunk at {}", index);
@@ -395,7 +545,7 @@ fn chunk_load(
                     return;
                 }
             }
-            EventChunkCommand::Update(_) => {
+            EventChunkCommand::Update(_) | EventChunkCommand::UpdateSection(..) => {
                 // Chunk is already in memory, no further actions needed here
             }
         }
@@ -411,7 +561,8 @@ pub fn run_criteria_chunk_mesh(chunk_store: Res<NineSurroundChunk>) -> ShouldRun
     }
 }
 
-/// Build/update a chunk mesh for a load request and remove a mesh on a unload request
+/// Build/update the section meshes of a chunk for a load request and remove them on a unload
+/// request
 fn chunk_mesh(
     mut commands: Commands,
     chunk_store: ResMut<NineSurroundChunk>,
@@ -420,65 +571,78 @@ fn chunk_mesh(
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
     for event in event_chunk.iter() {
-        match event {
-            EventChunkCommand::Load(index) | EventChunkCommand::Update(index) => {
-                let new_meshes = match chunk_store.data.get(index) {
-                    Some(chunk) => chunk.build(),
-                    None => panic!(
-                        "Chunk {} was requested to be meshed, but is not loaded. Loaded chunks are {:?}",
-                        index,
-                        chunk_store.data.keys()
-                    ),
-                };
-
-                for (mesh_group, new_mesh) in new_meshes {
-                    if let Some(new_mesh) = new_mesh {
-                        let meta_index = (*index, mesh_group);
-                        // If the mesh already exists then update its mesh, otherwise create a new entity
-                        if let Some(handle) = handles.chunks.get(&meta_index).cloned() {
-                            debug!("Reloading previously meshed chunk {:?}", meta_index);
-                            //unimplemented!();
-                            handles
-                                .chunks
-                                .insert(meta_index, meshes.set(handle, new_mesh));
-                        } else {
-                            let handle = meshes.add(new_mesh);
-                            handles.chunks.insert(meta_index, handle.clone());
-                            let _id = commands
-                                .spawn_bundle(PbrBundle {
-                                    mesh: handle,
-                                    material: handles.atlas_material.clone(),
-                                    render_pipelines: RenderPipelines::from_pipelines(vec![
-                                        RenderPipeline::new(handles.pipeline.clone()),
-                                    ]),
-                                    visible: Visible {
-                                        is_transparent: true,
-                                        ..Default::default()
-                                    },
-                                    transform: Transform::from_xyz(
-                                        (index.x * defaults::CHUNK_WIDTH as i32) as f32,
-                                        0.0,
-                                        (index.y * defaults::CHUNK_WIDTH as i32) as f32,
-                                    ),
-                                    ..Default::default()
-                                })
-                                .insert(AssociatedChunk {
-                                    chunk: *index,
-                                    mesh_group,
-                                })
-                                .id();
-                        }
+        let (index, sections) = match event {
+            EventChunkCommand::Load(index) | EventChunkCommand::Update(index) => (index, None),
+            EventChunkCommand::UpdateSection(index, section) => (index, Some(*section)),
+            EventChunkCommand::Unload(index) => {
+                for ((chunk, _, _), (handle, _)) in handles.sections.iter() {
+                    if chunk == index {
+                        meshes.remove(handle);
                     }
                 }
+                unimplemented!();
             }
-            EventChunkCommand::Unload(index) => {
-                for mesh_group in blocks::EXCEPT_NONE_MESH_GROUP.iter() {
-                    handles
-                        .chunks
-                        .get(&(*index, *mesh_group))
-                        .map(|handle| meshes.remove(handle));
+        };
+        let chunk = match chunk_store.data.get(index) {
+            Some(chunk) => chunk,
+            None => panic!(
+                "Chunk {} was requested to be meshed, but is not loaded. Loaded chunks are {:?}",
+                index,
+                chunk_store.data.keys()
+            ),
+        };
+        let sections = match sections {
+            Some(section) => section..section + 1,
+            None => 0..chunk.sections.len(),
+        };
+
+        for section in sections {
+            for (mesh_group, new_mesh) in chunk.build(section) {
+                let meta_index = (*index, section, mesh_group);
+                match (new_mesh, handles.sections.get(&meta_index).cloned()) {
+                    // If the mesh already exists then update its mesh, otherwise create a new entity
+                    (Some(new_mesh), Some((handle, entity))) => {
+                        debug!("Reloading previously meshed section {:?}", meta_index);
+                        handles
+                            .sections
+                            .insert(meta_index, (meshes.set(handle, new_mesh), entity));
+                    }
+                    (Some(new_mesh), None) => {
+                        let handle = meshes.add(new_mesh);
+                        let entity = commands
+                            .spawn_bundle(PbrBundle {
+                                mesh: handle.clone(),
+                                material: handles.atlas_material.clone(),
+                                render_pipelines: RenderPipelines::from_pipelines(vec![
+                                    RenderPipeline::new(handles.pipeline.clone()),
+                                ]),
+                                visible: Visible {
+                                    is_transparent: true,
+                                    ..Default::default()
+                                },
+                                transform: Transform::from_xyz(
+                                    (index.x * defaults::CHUNK_WIDTH as i32) as f32,
+                                    (section * defaults::SECTION_SIZE) as f32,
+                                    (index.y * defaults::CHUNK_WIDTH as i32) as f32,
+                                ),
+                                ..Default::default()
+                            })
+                            .insert(AssociatedChunk {
+                                chunk: *index,
+                                section,
+                                mesh_group,
+                            })
+                            .id();
+                        handles.sections.insert(meta_index, (handle, entity));
+                    }
+                    // The section no longer contains any blocks of this mesh group
+                    (None, Some((handle, entity))) => {
+                        meshes.remove(handle);
+                        commands.entity(entity).despawn();
+                        handles.sections.remove(&meta_index);
+                    }
+                    (None, None) => (),
                 }
-                unimplemented!();
             }
         }
     }
@@ -490,14 +654,38 @@ pub struct NineSurroundChunk {
 }
 
 impl NineSurroundChunk {
+    /// Blocks around a player with their eyes at `eyes`
+    pub fn surroundings(&self, eyes: Vec3) -> Surroundings {
+        let has = |point: Vec3, property: fn(&BlockProperties) -> bool| {
+            point.y >= 0.0
+                && self
+                    .get(&point)
+                    .map_or(false, |block| property(&properties(&block)))
+        };
+        let fluid = |properties: &BlockProperties| properties.fluid;
+        Surroundings {
+            head_in_fluid: has(eyes, fluid),
+            // Sample slightly above the bottom of the player's body
+            feet_in_fluid: has(eyes - Vec3::Y * (PLAYER_EYE_HEIGHT - 0.1), fluid),
+            // Sample the corners of the player's body at the feet, the waist, and the eyes
+            climbing: [PLAYER_EYE_HEIGHT - 0.1, PLAYER_EYE_HEIGHT / 2.0, 0.0]
+                .iter()
+                .flat_map(|height| {
+                    [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+                        .iter()
+                        .map(move |(x, z)| {
+                            eyes + Vec3::new(x * PLAYER_RADIUS, -height, z * PLAYER_RADIUS)
+                        })
+                })
+                .any(|point| has(point, |properties| properties.climbable)),
+        }
+    }
+
     pub fn get(&self, absolut: &Vec3) -> Option<BlockType> {
         let (chunk, index) = absolut_to_index::<{ defaults::CHUNK_WIDTH }>(absolut);
         assert!((index.x as usize) < defaults::CHUNK_WIDTH);
         assert!((index.z as usize) < defaults::CHUNK_WIDTH);
-        if index.y >= defaults::CHUNK_HEIGHT as u32 {
-            return None;
-        }
-        self.data.get(&chunk).map(|chunk| chunk.voxel[index])
+        self.data.get(&chunk).and_then(|chunk| chunk.get(index))
     }
 
     fn from_data(data: HashMap<IVec2, GameChunk>) -> Self {
@@ -584,10 +772,14 @@ pub trait ChunkManager {
 }
 
 pub mod defaults {
-    pub const CHUNK_SHAPE: [usize; 3] = [CHUNK_WIDTH, CHUNK_HEIGHT, CHUNK_WIDTH];
-
     pub const CHUNK_WIDTH: usize = 16;
-    pub const CHUNK_HEIGHT: usize = 32;
+    /// Edge length of the cubic sections a chunk column is split into
+    pub const SECTION_SIZE: usize = CHUNK_WIDTH;
+    pub const SECTION_SHAPE: [usize; 3] = [SECTION_SIZE; 3];
+    /// World height if none is configured
+    pub const WORLD_HEIGHT: usize = 64;
+    /// Height the built-in generators scale their terrain to, independent of the world height
+    pub const TERRAIN_HEIGHT: usize = 32;
 }
 
 #[cfg(test)]
@@ -599,10 +791,7 @@ mod tests {
         let mut loaded_chunks: HashMap<IVec2, GameChunk> = HashMap::new();
         loaded_chunks.insert(
             IVec2::new(1, 0),
-            GameChunk {
-                voxel: VoxelChunk::air(defaults::CHUNK_SHAPE).into(),
-                index: IVec2::new(1, 0),
-            },
+            GameChunk::empty(IVec2::new(1, 0), defaults::WORLD_HEIGHT),
         );
         let c = NineSurroundChunk::from_data(loaded_chunks);
         let position = Vec3::new(0.0, 9.0, 0.0);
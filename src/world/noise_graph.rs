@@ -0,0 +1,643 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::math::IVec2;
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti, Seedable, SuperSimplex};
+use serde::{Deserialize, Serialize};
+
+use super::{GameChunk, TerrainNoise, WorldGenerator};
+
+/// Declarative definition of a noise graph, usually read from a YAML file
+///
+/// # Example
+/// ```yaml
+/// nodes:
+///   base: { type: Perlin, seed_offset: 1 }
+///   scaled: { type: ScalePoint, source: base, scale: 0.05 }
+///   height: { type: ScaleBias, source: scaled, scale: 8.0, bias: 12.0 }
+///   zero: { type: Constant, value: 0.0 }
+///   never: { type: Constant, value: 0.0 }
+/// outputs:
+///   height: height
+///   biome: zero
+///   level_grass: zero
+///   level_dirt: zero
+///   tree_distribution: never
+///   tree_height: zero
+/// water_level: 10
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NoiseGraphDef {
+    pub nodes: HashMap<String, NoiseNode>,
+    pub outputs: TerrainOutputs,
+    /// Air below this height is filled with water, see [`TerrainNoise`]
+    #[serde(default)]
+    pub water_level: Option<usize>,
+}
+
+/// Names of the nodes used as inputs of the terrain passes, see [`TerrainNoise`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TerrainOutputs {
+    pub height: String,
+    pub biome: String,
+    pub level_grass: String,
+    pub level_dirt: String,
+    pub tree_distribution: String,
+    pub tree_height: String,
+}
+
+/// A single node of a noise graph. Sources of other nodes are referenced by their name.
+/// Generators are seeded with the world seed plus `seed_offset` or with `fixed_seed`, if set
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum NoiseNode {
+    Perlin {
+        #[serde(default)]
+        seed_offset: u32,
+        fixed_seed: Option<u32>,
+    },
+    OpenSimplex {
+        #[serde(default)]
+        seed_offset: u32,
+        fixed_seed: Option<u32>,
+    },
+    SuperSimplex {
+        #[serde(default)]
+        seed_offset: u32,
+        fixed_seed: Option<u32>,
+    },
+    RidgedMulti {
+        #[serde(default)]
+        seed_offset: u32,
+        fixed_seed: Option<u32>,
+        octaves: Option<usize>,
+        frequency: Option<f64>,
+        lacunarity: Option<f64>,
+        persistence: Option<f64>,
+    },
+    Fbm {
+        #[serde(default)]
+        seed_offset: u32,
+        fixed_seed: Option<u32>,
+        octaves: Option<usize>,
+        frequency: Option<f64>,
+        lacunarity: Option<f64>,
+        persistence: Option<f64>,
+    },
+    Constant {
+        value: f64,
+    },
+    ScalePoint {
+        source: String,
+        scale: f64,
+    },
+    ScaleBias {
+        source: String,
+        scale: f64,
+        bias: f64,
+    },
+    Add {
+        a: String,
+        b: String,
+    },
+    Multiply {
+        a: String,
+        b: String,
+    },
+    Power {
+        base: String,
+        exponent: String,
+    },
+    Clamp {
+        source: String,
+        lower: f64,
+        upper: f64,
+    },
+    /// Linear interpolation between `a` and `b` weighted by `control`
+    Blend {
+        a: String,
+        b: String,
+        control: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum NoiseGraphError {
+    Io(std::io::Error),
+    Parse(serde_yaml::Error),
+    /// A node (first) references another node (second) which does not exist
+    UnknownNode(String, String),
+    /// A node is (indirectly) its own source
+    Cycle(String),
+}
+
+impl fmt::Display for NoiseGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoiseGraphError::Io(e) => write!(f, "{}", e),
+            NoiseGraphError::Parse(e) => write!(f, "{}", e),
+            NoiseGraphError::UnknownNode(node, reference) => write!(
+                f,
+                "node `{}` references `{}`, which does not exist",
+                node, reference
+            ),
+            NoiseGraphError::Cycle(node) => write!(f, "node `{}` is part of a cycle", node),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum GeneratorKind {
+    Perlin,
+    OpenSimplex,
+    SuperSimplex,
+    RidgedMulti,
+    Fbm,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FractalParams {
+    octaves: Option<usize>,
+    frequency: Option<f64>,
+    lacunarity: Option<f64>,
+    persistence: Option<f64>,
+}
+
+/// A node whose sources are resolved to indices into [`NoiseGraph::nodes`]
+#[derive(Debug, Clone)]
+enum CompiledNode {
+    Generator(GeneratorKind, u32, Option<u32>, FractalParams),
+    Constant(f64),
+    ScalePoint(usize, f64),
+    ScaleBias(usize, f64, f64),
+    Add(usize, usize),
+    Multiply(usize, usize),
+    Power(usize, usize),
+    Clamp(usize, f64, f64),
+    Blend(usize, usize, usize),
+}
+
+/// Noise graph with resolved references, ready to be seeded
+#[derive(Debug, Clone)]
+pub struct NoiseGraph {
+    nodes: Vec<CompiledNode>,
+    index: HashMap<String, usize>,
+    outputs: [usize; 6],
+    water_level: Option<usize>,
+}
+
+impl NoiseGraph {
+    pub fn compile(def: &NoiseGraphDef) -> Result<Self, NoiseGraphError> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            Unvisited,
+            InProgress,
+            Done(usize),
+        }
+
+        fn visit(
+            name: &str,
+            def: &NoiseGraphDef,
+            marks: &mut HashMap<String, Mark>,
+            nodes: &mut Vec<CompiledNode>,
+        ) -> Result<usize, NoiseGraphError> {
+            match marks.get(name).copied().unwrap_or(Mark::Unvisited) {
+                Mark::Done(i) => return Ok(i),
+                Mark::InProgress => return Err(NoiseGraphError::Cycle(name.to_owned())),
+                Mark::Unvisited => {}
+            }
+            marks.insert(name.to_owned(), Mark::InProgress);
+
+            let node = &def.nodes[name];
+            let mut source = |reference: &String| match def.nodes.get(reference) {
+                Some(_) => visit(reference, def, marks, nodes),
+                None => Err(NoiseGraphError::UnknownNode(
+                    name.to_owned(),
+                    reference.to_owned(),
+                )),
+            };
+            let compiled = match node {
+                NoiseNode::Perlin {
+                    seed_offset,
+                    fixed_seed,
+                } => CompiledNode::Generator(
+                    GeneratorKind::Perlin,
+                    *seed_offset,
+                    *fixed_seed,
+                    FractalParams::default(),
+                ),
+                NoiseNode::OpenSimplex {
+                    seed_offset,
+                    fixed_seed,
+                } => CompiledNode::Generator(
+                    GeneratorKind::OpenSimplex,
+                    *seed_offset,
+                    *fixed_seed,
+                    FractalParams::default(),
+                ),
+                NoiseNode::SuperSimplex {
+                    seed_offset,
+                    fixed_seed,
+                } => CompiledNode::Generator(
+                    GeneratorKind::SuperSimplex,
+                    *seed_offset,
+                    *fixed_seed,
+                    FractalParams::default(),
+                ),
+                NoiseNode::RidgedMulti {
+                    seed_offset,
+                    fixed_seed,
+                    octaves,
+                    frequency,
+                    lacunarity,
+                    persistence,
+                } => CompiledNode::Generator(
+                    GeneratorKind::RidgedMulti,
+                    *seed_offset,
+                    *fixed_seed,
+                    FractalParams {
+                        octaves: *octaves,
+                        frequency: *frequency,
+                        lacunarity: *lacunarity,
+                        persistence: *persistence,
+                    },
+                ),
+                NoiseNode::Fbm {
+                    seed_offset,
+                    fixed_seed,
+                    octaves,
+                    frequency,
+                    lacunarity,
+                    persistence,
+                } => CompiledNode::Generator(
+                    GeneratorKind::Fbm,
+                    *seed_offset,
+                    *fixed_seed,
+                    FractalParams {
+                        octaves: *octaves,
+                        frequency: *frequency,
+                        lacunarity: *lacunarity,
+                        persistence: *persistence,
+                    },
+                ),
+                NoiseNode::Constant { value } => CompiledNode::Constant(*value),
+                NoiseNode::ScalePoint { source: s, scale } => {
+                    CompiledNode::ScalePoint(source(s)?, *scale)
+                }
+                NoiseNode::ScaleBias {
+                    source: s,
+                    scale,
+                    bias,
+                } => CompiledNode::ScaleBias(source(s)?, *scale, *bias),
+                NoiseNode::Add { a, b } => CompiledNode::Add(source(a)?, source(b)?),
+                NoiseNode::Multiply { a, b } => CompiledNode::Multiply(source(a)?, source(b)?),
+                NoiseNode::Power { base, exponent } => {
+                    CompiledNode::Power(source(base)?, source(exponent)?)
+                }
+                NoiseNode::Clamp {
+                    source: s,
+                    lower,
+                    upper,
+                } => CompiledNode::Clamp(source(s)?, *lower, *upper),
+                NoiseNode::Blend { a, b, control } => {
+                    CompiledNode::Blend(source(a)?, source(b)?, source(control)?)
+                }
+            };
+            nodes.push(compiled);
+            let i = nodes.len() - 1;
+            marks.insert(name.to_owned(), Mark::Done(i));
+            Ok(i)
+        }
+
+        let mut marks = HashMap::with_capacity(def.nodes.len());
+        let mut nodes = Vec::with_capacity(def.nodes.len());
+        for name in def.nodes.keys() {
+            visit(name, def, &mut marks, &mut nodes)?;
+        }
+        let index: HashMap<String, usize> = marks
+            .into_iter()
+            .filter_map(|(name, mark)| match mark {
+                Mark::Done(i) => Some((name, i)),
+                _ => None,
+            })
+            .collect();
+
+        let output = |name: &String| {
+            index
+                .get(name)
+                .copied()
+                .ok_or_else(|| NoiseGraphError::UnknownNode("outputs".to_owned(), name.to_owned()))
+        };
+        let outputs = [
+            output(&def.outputs.height)?,
+            output(&def.outputs.biome)?,
+            output(&def.outputs.level_grass)?,
+            output(&def.outputs.level_dirt)?,
+            output(&def.outputs.tree_distribution)?,
+            output(&def.outputs.tree_height)?,
+        ];
+        Ok(Self {
+            nodes,
+            index,
+            outputs,
+            water_level: def.water_level,
+        })
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, NoiseGraphError> {
+        let def: NoiseGraphDef = serde_yaml::from_str(yaml).map_err(NoiseGraphError::Parse)?;
+        Self::compile(&def)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, NoiseGraphError> {
+        let reader = File::open(path).map_err(NoiseGraphError::Io)?;
+        let def: NoiseGraphDef = serde_yaml::from_reader(reader).map_err(NoiseGraphError::Parse)?;
+        Self::compile(&def)
+    }
+
+    /// Instantiate all generators of the graph for the given world seed
+    pub fn seeded(&self, seed: u32) -> SeededNoiseGraph<'_> {
+        SeededNoiseGraph {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| match *node {
+                    CompiledNode::Generator(kind, seed_offset, fixed_seed, params) => {
+                        let seed = fixed_seed.unwrap_or_else(|| seed.wrapping_add(seed_offset));
+                        SeededNode::Generator(instantiate(kind, seed, params))
+                    }
+                    CompiledNode::Constant(v) => SeededNode::Constant(v),
+                    CompiledNode::ScalePoint(s, scale) => SeededNode::ScalePoint(s, scale),
+                    CompiledNode::ScaleBias(s, scale, bias) => {
+                        SeededNode::ScaleBias(s, scale, bias)
+                    }
+                    CompiledNode::Add(a, b) => SeededNode::Add(a, b),
+                    CompiledNode::Multiply(a, b) => SeededNode::Multiply(a, b),
+                    CompiledNode::Power(a, b) => SeededNode::Power(a, b),
+                    CompiledNode::Clamp(s, lower, upper) => SeededNode::Clamp(s, lower, upper),
+                    CompiledNode::Blend(a, b, c) => SeededNode::Blend(a, b, c),
+                })
+                .collect(),
+            index: &self.index,
+        }
+    }
+}
+
+fn instantiate(
+    kind: GeneratorKind,
+    seed: u32,
+    params: FractalParams,
+) -> Box<dyn NoiseFn<[f64; 2]>> {
+    macro_rules! fractal {
+        ($noise:expr) => {{
+            let mut noise = $noise.set_seed(seed);
+            if let Some(octaves) = params.octaves {
+                noise = noise.set_octaves(octaves);
+            }
+            if let Some(frequency) = params.frequency {
+                noise = noise.set_frequency(frequency);
+            }
+            if let Some(lacunarity) = params.lacunarity {
+                noise = noise.set_lacunarity(lacunarity);
+            }
+            if let Some(persistence) = params.persistence {
+                noise = noise.set_persistence(persistence);
+            }
+            Box::new(noise)
+        }};
+    }
+    match kind {
+        GeneratorKind::Perlin => Box::new(Perlin::new().set_seed(seed)),
+        GeneratorKind::OpenSimplex => Box::new(OpenSimplex::new().set_seed(seed)),
+        GeneratorKind::SuperSimplex => Box::new(SuperSimplex::new().set_seed(seed)),
+        GeneratorKind::RidgedMulti => fractal!(RidgedMulti::new()),
+        GeneratorKind::Fbm => fractal!(Fbm::new()),
+    }
+}
+
+enum SeededNode {
+    Generator(Box<dyn NoiseFn<[f64; 2]>>),
+    Constant(f64),
+    ScalePoint(usize, f64),
+    ScaleBias(usize, f64, f64),
+    Add(usize, usize),
+    Multiply(usize, usize),
+    Power(usize, usize),
+    Clamp(usize, f64, f64),
+    Blend(usize, usize, usize),
+}
+
+/// Noise graph with instantiated generators that can be evaluated
+pub struct SeededNoiseGraph<'a> {
+    nodes: Vec<SeededNode>,
+    index: &'a HashMap<String, usize>,
+}
+
+impl<'a> SeededNoiseGraph<'a> {
+    fn eval(&self, node: usize, point: [f64; 2]) -> f64 {
+        match &self.nodes[node] {
+            SeededNode::Generator(noise) => noise.get(point),
+            SeededNode::Constant(v) => *v,
+            SeededNode::ScalePoint(s, scale) => self.eval(*s, [point[0] * scale, point[1] * scale]),
+            SeededNode::ScaleBias(s, scale, bias) => self.eval(*s, point).mul_add(*scale, *bias),
+            SeededNode::Add(a, b) => self.eval(*a, point) + self.eval(*b, point),
+            SeededNode::Multiply(a, b) => self.eval(*a, point) * self.eval(*b, point),
+            SeededNode::Power(a, b) => self.eval(*a, point).powf(self.eval(*b, point)),
+            SeededNode::Clamp(s, lower, upper) => self.eval(*s, point).clamp(*lower, *upper),
+            SeededNode::Blend(a, b, control) => {
+                let lower = self.eval(*a, point);
+                let upper = self.eval(*b, point);
+                self.eval(*control, point).mul_add(upper - lower, lower)
+            }
+        }
+    }
+
+    /// Evaluate a node, given by its name, at a point
+    pub fn get(&self, name: &str, point: [f64; 2]) -> Option<f64> {
+        self.index.get(name).map(|&node| self.eval(node, point))
+    }
+
+    /// The node at `index` as a noise function
+    fn output(&self, index: usize) -> GraphNode<'_, 'a> {
+        GraphNode { graph: self, index }
+    }
+}
+
+struct GraphNode<'g, 'a> {
+    graph: &'g SeededNoiseGraph<'a>,
+    index: usize,
+}
+
+impl<'g, 'a> NoiseFn<[f64; 2]> for GraphNode<'g, 'a> {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        self.graph.eval(self.index, point)
+    }
+}
+
+/// World generator using a noise graph loaded from a file for the surface and tree passes. If
+/// `watch` is set, the file is reloaded whenever it is modified
+pub struct GraphWorld {
+    graph: NoiseGraph,
+    path: PathBuf,
+    watch: bool,
+    modified: Option<SystemTime>,
+}
+
+impl GraphWorld {
+    pub fn load<P: AsRef<Path>>(path: P, watch: bool) -> Result<Self, NoiseGraphError> {
+        let path = path.as_ref().to_owned();
+        let modified = modification_time(&path);
+        Ok(Self {
+            graph: NoiseGraph::from_file(&path)?,
+            path,
+            watch,
+            modified,
+        })
+    }
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl WorldGenerator for GraphWorld {
+    fn chunk(&self, at: IVec2, seed: u32, world_height: usize) -> GameChunk {
+        let graph = self.graph.seeded(seed);
+        let [height, biome, level_grass, level_dirt, tree_distribution, tree_height] =
+            self.graph.outputs;
+        TerrainNoise {
+            height: &graph.output(height),
+            biome: &graph.output(biome),
+            level_grass: &graph.output(level_grass),
+            level_dirt: &graph.output(level_dirt),
+            tree_distribution: &graph.output(tree_distribution),
+            tree_height: &graph.output(tree_height),
+            surface_block: None,
+            water_level: self.graph.water_level,
+        }
+        .chunk(at, seed, world_height)
+    }
+
+    fn reload(&mut self) -> bool {
+        if !self.watch {
+            return false;
+        }
+        let modified = modification_time(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        match NoiseGraph::from_file(&self.path) {
+            Ok(graph) => {
+                self.graph = graph;
+                true
+            }
+            Err(e) => {
+                bevy::log::error!(
+                    "Failed to reload noise graph `{}`: {}",
+                    self.path.display(),
+                    e
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::BlockType;
+    use noise::{Add, Constant, ScaleBias, ScalePoint};
+
+    const GRAPH: &str = "
+nodes:
+  base: { type: Perlin, seed_offset: 3 }
+  scaled: { type: ScalePoint, source: base, scale: 0.05 }
+  biased: { type: ScaleBias, source: scaled, scale: 8.0, bias: 2.0 }
+  uneven: { type: ScaleBias, source: scaled, scale: 0.3, bias: 0.1 }
+  offset: { type: Constant, value: 4.0 }
+  height: { type: Add, a: biased, b: offset }
+outputs:
+  height: height
+  biome: offset
+  level_grass: offset
+  level_dirt: offset
+  tree_distribution: offset
+  tree_height: offset
+";
+
+    #[test]
+    fn graph_matches_combinators() {
+        let graph = NoiseGraph::from_yaml(GRAPH).unwrap();
+        let seeded = graph.seeded(11);
+
+        let base = Perlin::new().set_seed(14);
+        let scaled = ScalePoint::new(base).set_scale(0.05);
+        let biased = ScaleBias::<[f64; 2]>::new(&scaled)
+            .set_scale(8.0)
+            .set_bias(2.0);
+        let offset = Constant::new(4.0);
+        let height = Add::new(&biased, &offset);
+        // Only exact with the fused multiply-add of the combinator
+        let uneven = ScaleBias::<[f64; 2]>::new(&scaled)
+            .set_scale(0.3)
+            .set_bias(0.1);
+
+        for point in [[0.0, 0.0], [13.5, -7.25], [-120.0, 64.0], [3.3, 71.9]].iter() {
+            assert_eq!(seeded.get("height", *point), Some(height.get(*point)));
+            assert_eq!(seeded.get("uneven", *point), Some(uneven.get(*point)));
+        }
+    }
+
+    #[test]
+    fn water_level_floods_columns() {
+        let yaml = "
+nodes:
+  height: { type: Constant, value: 4.0 }
+  zero: { type: Constant, value: 0.0 }
+outputs: { height: height, biome: zero, level_grass: zero, level_dirt: zero, tree_distribution: zero, tree_height: zero }
+water_level: 8
+";
+        let world = GraphWorld {
+            graph: NoiseGraph::from_yaml(yaml).unwrap(),
+            path: PathBuf::new(),
+            watch: false,
+            modified: None,
+        };
+        let chunk = world.chunk(IVec2::ZERO, 0, 16);
+        assert_eq!(chunk[(3, 3, 3)], BlockType::Grass);
+        for y in 4..8 {
+            assert_eq!(chunk[(3, y, 3)], BlockType::Water);
+        }
+        assert_eq!(chunk[(3, 8, 3)], BlockType::Air);
+    }
+
+    #[test]
+    fn invalid_references() {
+        let cycle = "
+nodes:
+  a: { type: ScalePoint, source: b, scale: 1.0 }
+  b: { type: ScalePoint, source: a, scale: 1.0 }
+outputs: { height: a, biome: a, level_grass: a, level_dirt: a, tree_distribution: a, tree_height: a }
+";
+        assert!(matches!(
+            NoiseGraph::from_yaml(cycle),
+            Err(NoiseGraphError::Cycle(_))
+        ));
+        let unknown = "
+nodes:
+  a: { type: ScalePoint, source: c, scale: 1.0 }
+outputs: { height: a, biome: a, level_grass: a, level_dirt: a, tree_distribution: a, tree_height: a }
+";
+        assert!(matches!(
+            NoiseGraph::from_yaml(unknown),
+            Err(NoiseGraphError::UnknownNode(_, _))
+        ));
+    }
+}
@@ -1,79 +1,266 @@
-use std::ops::RangeInclusive;
+use std::collections::HashMap;
 
-use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_rapier3d::{
     na::Isometry3,
     na::Translation3,
     na::UnitQuaternion,
-    physics::{EventQueue, RigidBodyHandleComponent},
+    physics::{
+        EventQueue, InteractionPairFilters, PhysicsInterpolationComponent, RapierConfiguration,
+        RigidBodyHandleComponent,
+    },
     rapier::{
-        dynamics::{RigidBodyBuilder, RigidBodySet},
-        geometry::{ColliderBuilder, ColliderSet, InteractionGroups},
+        dynamics::{
+            CCDSolver, IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodyHandle,
+            RigidBodySet,
+        },
+        geometry::{
+            BroadPhase, ColliderBuilder, ColliderHandle, ColliderSet, InteractionGroups,
+            NarrowPhase, SharedShape,
+        },
+        pipeline::{PhysicsHooks, PhysicsPipeline},
     },
 };
 
 use crate::{config::CobbleConfig, interface::controller::MovementState};
 
-use super::{defaults, index_to_absolut, BlockType, NineSurroundChunk, PlayerPosition};
+use super::{blocks, defaults, index_to_absolut, EventChunkCommand, GameChunk, NineSurroundChunk};
 
 pub const COLLIDER_PLAYER_UD: u128 = 1;
 pub const COLLIDER_ENV_FLOOR_UD: u128 = 2;
 pub const COLLIDER_ENV_OTHER_UD: u128 = 3;
 pub const COLLIDER_PLAYER_SENSOR_UD: u128 = 4;
-
-pub const COLLIDER_FLOOR_0_ID: u16 = 9 * 3;
-pub const COLLIDER_SENSOR_ID: u16 = 9 * 3 + 1;
-pub const COLLIDER_ALL_IDS: RangeInclusive<u16> = 0..=(3 * 9);
+pub const COLLIDER_ITEM_UD: u128 = 5;
 
 pub const GROUP_PLAYER: InteractionGroups = InteractionGroups::new(0b0000101, 0b000001);
 pub const GROUP_PLAYER_SENSOR: InteractionGroups = InteractionGroups::new(0b0000110, 0b000010);
-pub const GROUP_FLOOR: InteractionGroups = InteractionGroups::new(0b0000111, 0b000111);
-pub const GROUP_ENV: InteractionGroups = InteractionGroups::new(0b0000101, 0b000111);
+pub const GROUP_FLOOR: InteractionGroups = InteractionGroups::new(0b0000111, 0b001111);
+pub const GROUP_ENV: InteractionGroups = InteractionGroups::new(0b0000101, 0b001111);
+pub const GROUP_ITEM: InteractionGroups = InteractionGroups::new(0b0001000, 0b000001);
+
+/// Half height of the slabs on top of walkable blocks that carry the floor's friction
+const FLOOR_SLAB_HALF_HEIGHT: f32 = 0.05;
+
+/// Marks the sensor below the player used to detect whether the player is airborn
+pub struct PlayerSensor;
 
+/// Entities of the static colliders of every loaded chunk section
 #[derive(Default)]
-pub struct VoxelColliderState(IVec2, UVec3);
-pub struct ColliderBlock(u16);
+pub struct SectionColliders(pub(super) HashMap<(IVec2, usize), Vec<Entity>>);
 
-pub fn setup_collider(
+/// Run of `length` blocks along the z-axis starting at `start`, given as chunk index
+#[derive(Debug, PartialEq)]
+pub struct BlockRun {
+    pub start: UVec3,
+    pub length: u32,
+}
+
+fn extend_run(run: &mut Option<BlockRun>, runs: &mut Vec<BlockRun>, continues: bool, at: UVec3) {
+    match (run.as_mut(), continues) {
+        (Some(run), true) => run.length += 1,
+        (None, true) => {
+            *run = Some(BlockRun {
+                start: at,
+                length: 1,
+            })
+        }
+        (Some(_), false) => runs.extend(run.take()),
+        (None, false) => (),
+    }
+}
+
+/// Runs of the solid blocks of a section that are not enclosed by other solid blocks (walls) and
+/// runs of the solid blocks with a non-solid block on top (floors). Blocks on the border of the
+/// chunk are always considered exposed
+pub fn section_runs(chunk: &GameChunk, section: usize) -> (Vec<BlockRun>, Vec<BlockRun>) {
+    const NEIGHBOURS: [(i32, i32, i32); 5] =
+        [(1, 0, 0), (-1, 0, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)];
+
+    let mut walls = Vec::new();
+    let mut floors = Vec::new();
+    if chunk.section(section).is_none() {
+        return (walls, floors);
+    }
+    let solid = |x: i32, y: i32, z: i32| {
+        chunk
+            .safe_get(x, y, z)
+            .map_or(false, |block| blocks::properties(&block).solid)
+    };
+    let size = defaults::SECTION_SIZE as i32;
+    let bottom = section as i32 * size;
+    for x in 0..size {
+        for y in bottom..bottom + size {
+            let (mut wall, mut floor) = (None, None);
+            for z in 0..=size {
+                let (is_wall, is_floor) = if z < size && solid(x, y, z) {
+                    let top = !solid(x, y + 1, z);
+                    let exposed = top
+                        || NEIGHBOURS
+                            .iter()
+                            .any(|(dx, dy, dz)| !solid(x + dx, y + dy, z + dz));
+                    (exposed, top)
+                } else {
+                    (false, false)
+                };
+                let at = UVec3::new(x as u32, y as u32, z as u32);
+                extend_run(&mut wall, &mut walls, is_wall, at);
+                extend_run(&mut floor, &mut floors, is_floor, at);
+            }
+        }
+    }
+    (walls, floors)
+}
+
+/// Whether a body with the given radius and its bottom at `feet` stands on a block, i.e., any
+/// corner of its footprint lies above a block for which `solid` holds
+pub fn is_supported(feet: Vec3, radius: f32, solid: impl Fn(Vec3) -> bool) -> bool {
+    [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+        .iter()
+        .any(|(x, z)| solid(feet + Vec3::new(x * radius, -0.5, z * radius)))
+}
+
+/// Restrict a horizontal movement by `step` so that the body does not leave the blocks it stands
+/// on. Each axis is restricted on its own, which lets the body slide along edges
+pub fn clamp_to_edges(feet: Vec3, step: Vec3, radius: f32, solid: impl Fn(Vec3) -> bool) -> Vec3 {
+    // Never trap a body that already overhangs, e.g., after being pushed off an edge
+    if !is_supported(feet, radius, &solid) {
+        return step;
+    }
+    let mut allowed = Vec3::new(step.x, 0.0, step.z);
+    if !is_supported(feet + Vec3::new(allowed.x, 0.0, 0.0), radius, &solid) {
+        allowed.x = 0.0;
+    }
+    if !is_supported(feet + allowed, radius, &solid) {
+        allowed.z = 0.0;
+    }
+    Vec3::new(allowed.x, step.y, allowed.z)
+}
+
+/// Height to lift a body with the given radius and height and its bottom at `feet` so that it
+/// stands on the one block high ledge in front of it when moving in `direction`. `None` if there
+/// is no such ledge or not enough space above it
+pub fn step_up_height(
+    feet: Vec3,
+    direction: Vec3,
+    radius: f32,
+    height: f32,
+    solid: impl Fn(Vec3) -> bool,
+) -> Option<f32> {
+    /// Distance in front of the body within which ledges are stepped onto
+    const LOOKAHEAD: f32 = 0.2;
+
+    let direction = Vec3::new(direction.x, 0.0, direction.z).normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+    let ahead = feet + direction * (radius + LOOKAHEAD);
+    let top = (feet.y + 0.5).floor() + 1.0;
+    let free = |point: Vec3| {
+        (0..height.ceil() as i32)
+            .all(|dy| !solid(Vec3::new(point.x, top + 0.5 + dy as f32, point.z)))
+    };
+    if solid(Vec3::new(ahead.x, top - 0.5, ahead.z)) && free(ahead) && free(feet) {
+        Some(top - feet.y)
+    } else {
+        None
+    }
+}
+
+/// Compound of one cuboid per run, with the given half height and offset on the y-axis relative
+/// to the center of the blocks
+fn run_compound(runs: &[BlockRun], half_height: f32, y_offset: f32) -> ColliderBuilder {
+    ColliderBuilder::compound(
+        runs.iter()
+            .map(|run| {
+                let half_length = run.length as f32 / 2.0;
+                (
+                    Isometry3::translation(
+                        run.start.x as f32 + 0.5,
+                        run.start.y as f32 + 0.5 + y_offset,
+                        run.start.z as f32 + half_length,
+                    ),
+                    SharedShape::cuboid(0.5, half_height, half_length),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Build static colliders for the solid blocks of chunk sections when they are loaded or
+/// modified. Each section gets a frictionless compound of its exposed blocks and a compound of
+/// thin slabs on top of walkable blocks, which carries the friction and is what the player's
+/// sensor detects as floor. Any rigid body colliding with `GROUP_ENV` collides with them
+pub fn section_colliders(
     mut commands: Commands,
-    config: Res<CobbleConfig>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    chunk_store: Res<NineSurroundChunk>,
+    mut colliders: ResMut<SectionColliders>,
+    mut event_chunk: EventReader<EventChunkCommand>,
 ) {
-    for i in COLLIDER_ALL_IDS {
-        let is_floor_0 = i == COLLIDER_FLOOR_0_ID;
-
-        let (user_data, collision_group, friction) = if is_floor_0 {
-            (COLLIDER_ENV_FLOOR_UD, GROUP_FLOOR, 9.0)
-        } else {
-            (COLLIDER_ENV_OTHER_UD, GROUP_ENV, 0.0)
+    for event in event_chunk.iter() {
+        let (index, sections) = match event {
+            EventChunkCommand::Load(index) | EventChunkCommand::Update(index) => (index, None),
+            EventChunkCommand::UpdateSection(index, section) => (index, Some(*section)),
+            EventChunkCommand::Unload(index) => {
+                colliders.0.retain(|(chunk, _), entities| {
+                    if chunk == index {
+                        for entity in entities.drain(..) {
+                            commands.entity(entity).despawn();
+                        }
+                    }
+                    chunk != index
+                });
+                continue;
+            }
         };
-        let collider = ColliderBuilder::cuboid(0.5, 0.5, 0.5)
-            .friction(friction)
-            .collision_groups(collision_group)
-            .user_data(user_data);
-        let rigid_body = RigidBodyBuilder::new_static()
-            .user_data(user_data)
-            .ccd_enabled(true);
-
-        let mut collider_entity_cmds =
-            commands.spawn_bundle((collider, rigid_body, ColliderBlock(i)));
-        if config.debug.show_colliders {
-            collider_entity_cmds.insert_bundle(PbrBundle {
-                mesh: meshes.add(shape::Cube { size: 1.0 }.into()),
-                material: materials.add(
-                    (if is_floor_0 {
-                        Color::rgb(0.9, 0.0, 0.1)
-                    } else {
-                        Color::rgb(0.0, 0.9, 0.1)
-                    })
-                    .into(),
-                ),
-                ..Default::default()
-            });
+        let chunk = match chunk_store.data.get(index) {
+            Some(chunk) => chunk,
+            None => continue,
+        };
+        let sections = sections.map_or(0..chunk.sections.len(), |s| s..s + 1);
+        let origin = index_to_absolut::<{ defaults::CHUNK_WIDTH }>(*index, UVec3::ZERO).as_f32();
+        for section in sections {
+            for entity in colliders.0.remove(&(*index, section)).into_iter().flatten() {
+                commands.entity(entity).despawn();
+            }
+
+            let (walls, floors) = section_runs(chunk, section);
+            let mut entities = Vec::with_capacity(2);
+            let mut spawn = |collider: ColliderBuilder| {
+                let rigid_body =
+                    RigidBodyBuilder::new_static().translation(origin.x, origin.y, origin.z);
+                entities.push(commands.spawn_bundle((collider, rigid_body)).id());
+            };
+            if !walls.is_empty() {
+                spawn(
+                    run_compound(&walls, 0.5, 0.0)
+                        .friction(0.0)
+                        .collision_groups(GROUP_ENV)
+                        .user_data(COLLIDER_ENV_OTHER_UD),
+                );
+            }
+            if !floors.is_empty() {
+                spawn(
+                    run_compound(
+                        &floors,
+                        FLOOR_SLAB_HALF_HEIGHT,
+                        0.5 - FLOOR_SLAB_HALF_HEIGHT,
+                    )
+                    .friction(9.0)
+                    .collision_groups(GROUP_FLOOR)
+                    .user_data(COLLIDER_ENV_FLOOR_UD),
+                );
+            }
+            colliders.0.insert((*index, section), entities);
         }
     }
+}
 
+pub fn setup_sensor(
+    mut commands: Commands,
+    config: Res<CobbleConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
     let airborn_collider = ColliderBuilder::capsule_z(0.2, 0.1)
         .sensor(true)
         .collision_groups(GROUP_PLAYER_SENSOR)
@@ -83,11 +270,8 @@ pub fn setup_collider(
         .lock_rotations()
         .ccd_enabled(true)
         .user_data(COLLIDER_PLAYER_SENSOR_UD);
-    let mut sensor_entity_cmds = commands.spawn_bundle((
-        airborn_collider,
-        airborn_ridig_body,
-        ColliderBlock(COLLIDER_SENSOR_ID),
-    ));
+    let mut sensor_entity_cmds =
+        commands.spawn_bundle((airborn_collider, airborn_ridig_body, PlayerSensor));
     if config.debug.show_colliders {
         sensor_entity_cmds.insert_bundle(PbrBundle {
             mesh: meshes.add(
@@ -103,91 +287,130 @@ pub fn setup_collider(
     }
 }
 
-pub fn run_criteria_update_colliders(
-    chunk_store: Res<NineSurroundChunk>,
-    position: Res<PlayerPosition>,
-) -> ShouldRun {
-    if chunk_store.is_changed() || position.is_changed() {
-        ShouldRun::Yes
-    } else {
-        ShouldRun::No
+/// Keep the airborn sensor right below the feet of the player, where it overlaps the floor slab
+/// the player stands on
+pub fn place_sensor(bodies: &mut RigidBodySet, sensor: RigidBodyHandle, feet: Vec3) {
+    if let Some(body) = bodies.get_mut(sensor) {
+        body.set_position(
+            Isometry3::from_parts(
+                Translation3::new(feet.x, feet.y - FLOOR_SLAB_HALF_HEIGHT, feet.z),
+                UnitQuaternion::identity(),
+            ),
+            true,
+        );
     }
 }
-pub fn update_colliders(
-    chunk_store: Res<NineSurroundChunk>,
-    position: Res<PlayerPosition>,
-    query: Query<(&RigidBodyHandleComponent, &ColliderBlock)>,
-    mut bodies: ResMut<RigidBodySet>,
-) {
-    let far_away: Isometry3<f32> = Isometry3::from_parts(
-        Translation3::new(0.0, -100.0, 0.0),
-        UnitQuaternion::identity(),
-    );
-    query.for_each(|(body_handle, ColliderBlock(id))| {
-        let body = bodies.get_mut(body_handle.handle()).unwrap();
-
-        if id == &COLLIDER_SENSOR_ID {
-            body.set_position(
-                Isometry3::from_parts(
-                    Translation3::new(
-                        position.absolut.x,
-                        position.absolut.y - 1.5,
-                        position.absolut.z,
-                    ),
-                    UnitQuaternion::identity(),
-                ),
-                true,
-            );
-        } else {
-            let (x, y, z) =
-                index_to_absolut::<{ defaults::CHUNK_WIDTH }>(position.chunk, position.index)
-                    .into();
-            let collider_pos = if id != &COLLIDER_FLOOR_0_ID {
-                let vertical_offset = match id.div_euclid(9) {
-                    0 => 1,
-                    1 => 0,
-                    2 => -1,
-                    _ => panic!("Unexpected collider id"),
-                };
 
-                IVec3::from(match id.rem_euclid(9) {
-                    0 => [0, vertical_offset, 0],
-                    1 => [1, vertical_offset, 0],
-                    2 => [0, vertical_offset, 1],
-                    3 => [1, vertical_offset, 1],
-                    4 => [-1, vertical_offset, 0],
-                    5 => [0, vertical_offset, -1],
-                    6 => [-1, vertical_offset, -1],
-                    7 => [1, vertical_offset, -1],
-                    8 => [-1, vertical_offset, 1],
-                    _ => [0, vertical_offset, 0],
-                }) + IVec3::new(x, y, z)
-            } else {
-                IVec3::from([x, y - 2, z])
-            }
-            .as_f32();
-            body.set_position(
-                match chunk_store.get(&collider_pos) {
-                    None => far_away,
-                    Some(block) => {
-                        if block != BlockType::Air {
-                            Isometry3::from_parts(
-                                Translation3::new(
-                                    collider_pos.x + 0.5,
-                                    collider_pos.y + 0.5,
-                                    collider_pos.z + 0.5,
-                                ),
-                                UnitQuaternion::identity(),
-                            )
-                        } else {
-                            far_away
-                        }
-                    }
-                },
-                true,
+/// Clock of the fixed timestep physics simulation, which also advances the player's movement.
+/// The rendered frames are interpolated between the last two steps
+#[derive(Default)]
+pub struct PhysicsClock {
+    /// Whether the simulation runs, it is paused until all assets are loaded
+    pub active: bool,
+    /// Time in seconds that has passed but is yet to be simulated
+    accumulator: f32,
+}
+
+impl PhysicsClock {
+    /// Most steps simulated per frame, the simulation slows down rather than stalling the game
+    /// if the steps take longer than the time they simulate
+    const MAX_STEPS_PER_FRAME: u32 = 8;
+
+    /// Advance the clock by a frame of `delta` seconds, returns the number of steps of `dt`
+    /// seconds to simulate
+    pub fn advance(&mut self, delta: f32, dt: f32) -> u32 {
+        if !self.active {
+            return 0;
+        }
+        self.accumulator += delta;
+        let mut steps = 0;
+        while self.accumulator >= dt {
+            self.accumulator -= dt;
+            steps += 1;
+        }
+        if steps > Self::MAX_STEPS_PER_FRAME {
+            debug!(
+                "Skipping {} physics steps",
+                steps - Self::MAX_STEPS_PER_FRAME
             );
+            steps = Self::MAX_STEPS_PER_FRAME;
         }
-    });
+        steps
+    }
+
+    /// Fraction of a step that lies between the last simulated step and the rendered frame
+    pub fn alpha(&self, dt: f32) -> f32 {
+        (self.accumulator / dt).clamp(0.0, 1.0)
+    }
+}
+
+/// Rapier's world, stepped by the physics clock instead of by `RapierPhysicsPlugin`
+#[derive(SystemParam)]
+pub struct PhysicsWorld<'a> {
+    pipeline: ResMut<'a, PhysicsPipeline>,
+    configuration: Res<'a, RapierConfiguration>,
+    integration_parameters: Res<'a, IntegrationParameters>,
+    broad_phase: ResMut<'a, BroadPhase>,
+    narrow_phase: ResMut<'a, NarrowPhase>,
+    pub bodies: ResMut<'a, RigidBodySet>,
+    pub colliders: ResMut<'a, ColliderSet>,
+    joints: ResMut<'a, JointSet>,
+    ccd_solver: ResMut<'a, CCDSolver>,
+    filters: Res<'a, InteractionPairFilters>,
+    pub events: Res<'a, EventQueue>,
+}
+
+impl<'a> PhysicsWorld<'a> {
+    /// Duration of a single step in seconds
+    pub fn dt(&self) -> f32 {
+        self.integration_parameters.dt
+    }
+
+    pub fn gravity(&self) -> f32 {
+        -self.configuration.gravity.y
+    }
+
+    pub fn step(&mut self) {
+        let hooks: &dyn PhysicsHooks = match &self.filters.hook {
+            Some(hook) => hook.as_ref(),
+            None => &(),
+        };
+        self.pipeline.step(
+            &self.configuration.gravity,
+            &self.integration_parameters,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joints,
+            &mut self.ccd_solver,
+            hooks,
+            &*self.events,
+        );
+    }
+}
+
+/// Move rendered bodies from their position at the previous step towards the one at the last
+/// step. Rotations are not interpolated, so that looking around is not delayed
+pub fn interpolate_bodies(
+    clock: Res<PhysicsClock>,
+    integration_parameters: Res<IntegrationParameters>,
+    bodies: Res<RigidBodySet>,
+    mut query: Query<(
+        &RigidBodyHandleComponent,
+        &PhysicsInterpolationComponent,
+        &mut Transform,
+    )>,
+) {
+    let alpha = clock.alpha(integration_parameters.dt);
+    for (body_handle, previous, mut transform) in query.iter_mut() {
+        if let (Some(body), Some(previous)) = (bodies.get(body_handle.handle()), previous.0) {
+            let current = body.position().translation.vector;
+            let previous = previous.translation.vector;
+            transform.translation = Vec3::new(previous.x, previous.y, previous.z)
+                .lerp(Vec3::new(current.x, current.y, current.z), alpha);
+        }
+    }
 }
 
 pub fn compute_is_airborn(
@@ -221,12 +444,20 @@ pub fn compute_is_airborn(
         };
     }
 
+    // Section colliders are replaced whenever a block changes, which does not necessarily
+    // report the end of the intersection
+    state.intersections.retain(|(index, generation)| {
+        collider_set
+            .get(ColliderHandle::from_raw_parts(*index, *generation))
+            .is_some()
+    });
     state.airborn = state.intersections.is_empty();
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::world::BlockType;
 
     #[test]
     fn collision_groups() {
@@ -241,5 +472,125 @@ mod test {
         assert!(!GROUP_PLAYER.test(GROUP_PLAYER_SENSOR));
         assert!(!GROUP_PLAYER_SENSOR.test(GROUP_ENV));
         assert!(!GROUP_ENV.test(GROUP_PLAYER_SENSOR));
+        // Dropped items only collide with the world
+        assert!(GROUP_ITEM.test(GROUP_ENV));
+        assert!(GROUP_ITEM.test(GROUP_FLOOR));
+        assert!(!GROUP_ITEM.test(GROUP_PLAYER));
+        assert!(!GROUP_ITEM.test(GROUP_PLAYER_SENSOR));
+        assert!(!GROUP_ITEM.test(GROUP_ITEM));
+    }
+
+    #[test]
+    fn section_runs_cover_exposed_blocks() {
+        let mut chunk = GameChunk::empty(IVec2::ZERO, 2 * defaults::SECTION_SIZE);
+        // A solid 3x3x3 cube
+        for x in 1..4 {
+            for y in 1..4 {
+                for z in 1..4 {
+                    chunk.set(UVec3::new(x, y, z), BlockType::Dirt);
+                }
+            }
+        }
+        let blocks = |runs: &[BlockRun]| runs.iter().map(|run| run.length).sum::<u32>();
+
+        let (walls, floors) = section_runs(&chunk, 0);
+        // Only the center block is enclosed and splits the run through it
+        assert_eq!(blocks(&walls), 26);
+        assert!(walls.contains(&BlockRun {
+            start: UVec3::new(1, 1, 1),
+            length: 3
+        }));
+        assert!(walls.contains(&BlockRun {
+            start: UVec3::new(2, 2, 1),
+            length: 1
+        }));
+        assert_eq!(blocks(&floors), 9);
+
+        // Digging a hole into the top exposes the center block, which can now be stood on
+        chunk.set(UVec3::new(2, 3, 2), BlockType::Air);
+        let (walls, floors) = section_runs(&chunk, 0);
+        assert_eq!(blocks(&walls), 26);
+        assert_eq!(blocks(&floors), 9);
+        assert!(floors.contains(&BlockRun {
+            start: UVec3::new(2, 2, 2),
+            length: 1
+        }));
+        assert_eq!(section_runs(&chunk, 1), (vec![], vec![]));
+    }
+
+    #[test]
+    fn edges_and_steps() {
+        // Floor for x < 2 with a one block step at x = 3 and a wall at x = 6
+        let solid = |point: Vec3| {
+            let (x, y) = (point.x.floor() as i32, point.y.floor() as i32);
+            (y == 0 && x < 2) || (y <= 1 && x == 3) || (y <= 3 && x == 6)
+        };
+        let (radius, height) = (0.1, 1.6);
+
+        let feet = Vec3::new(1.5, 1.0, 0.5);
+        assert!(is_supported(feet, radius, solid));
+        assert!(is_supported(Vec3::new(2.05, 1.0, 0.5), radius, solid));
+        assert!(!is_supported(Vec3::new(2.2, 1.0, 0.5), radius, solid));
+        // Walking off the edge is blocked, sliding along it is not
+        let step = Vec3::new(0.7, -0.1, 0.3);
+        assert_eq!(
+            clamp_to_edges(feet, step, radius, solid),
+            Vec3::new(0.0, -0.1, 0.3)
+        );
+        assert_eq!(
+            clamp_to_edges(feet, Vec3::new(-0.3, 0.0, 0.0), radius, solid),
+            Vec3::new(-0.3, 0.0, 0.0)
+        );
+        let overhanging = Vec3::new(2.5, 1.0, 0.5);
+        assert_eq!(clamp_to_edges(overhanging, step, radius, solid), step);
+
+        // Step onto the ledge at x = 3, but neither onto the wall nor when walking away
+        let feet = Vec3::new(2.75, 1.0, 0.5);
+        let lift = step_up_height(feet, Vec3::X, radius, height, solid);
+        assert!((lift.unwrap() - 1.0).abs() < 1e-5);
+        assert_eq!(step_up_height(feet, -Vec3::X, radius, height, solid), None);
+        assert_eq!(
+            step_up_height(feet, Vec3::ZERO, radius, height, solid),
+            None
+        );
+        let feet = Vec3::new(5.75, 1.0, 0.5);
+        assert_eq!(step_up_height(feet, Vec3::X, radius, height, solid), None);
+        // No head room
+        let low_ceiling = |point: Vec3| solid(point) || point.y.floor() as i32 == 3;
+        let feet = Vec3::new(2.75, 1.0, 0.5);
+        assert_eq!(
+            step_up_height(feet, Vec3::X, radius, height, low_ceiling),
+            None
+        );
+    }
+
+    #[test]
+    fn physics_clock_is_frame_rate_independent() {
+        let dt = 1.0 / 60.0;
+        let simulate = |fps: u32| {
+            let mut clock = PhysicsClock {
+                active: true,
+                ..Default::default()
+            };
+            (0..fps)
+                .map(|_| clock.advance(1.0 / fps as f32, dt))
+                .sum::<u32>()
+        };
+        // One second simulates the same steps at any frame rate, up to the rounding of the
+        // accumulated frame times
+        for fps in [30, 60, 144, 240].iter() {
+            let steps = simulate(*fps) as i32;
+            assert!((steps - 60).abs() <= 1, "{} steps at {} FPS", steps, fps);
+        }
+
+        let mut clock = PhysicsClock::default();
+        assert_eq!(clock.advance(1.0, dt), 0);
+        clock.active = true;
+        assert_eq!(clock.advance(dt * 1.5, dt), 1);
+        assert!((clock.alpha(dt) - 0.5).abs() < 1e-3);
+        // Long frames are not caught up with
+        assert_eq!(clock.advance(1.0, dt), PhysicsClock::MAX_STEPS_PER_FRAME);
+        // and the skipped time is dropped rather than simulated by the following frames
+        assert_eq!(clock.advance(dt, dt), 1);
     }
 }
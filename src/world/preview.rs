@@ -0,0 +1,391 @@
+//! Headless, top-down preview of a world generator rendered to PNG images
+use std::path::{Path, PathBuf};
+
+use bevy::math::IVec2;
+use image::{Rgb, RgbImage};
+
+use super::{defaults, BlockType, GameChunk, WorldGenerator, BLOCK_COLORS};
+
+/// Layers that can be rendered in addition to the surface blocks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewLayer {
+    /// Surface block colors shaded by height
+    Surface,
+    /// Grayscale height only
+    Height,
+    /// Surface blocks classified into biomes
+    Biome,
+}
+
+impl PreviewLayer {
+    fn suffix(&self) -> &'static str {
+        match self {
+            PreviewLayer::Surface => "",
+            PreviewLayer::Height => "_height",
+            PreviewLayer::Biome => "_biome",
+        }
+    }
+}
+
+pub struct PreviewArgs {
+    pub seed: u32,
+    /// First chunk of the rectangle
+    pub from: IVec2,
+    /// Last chunk (inclusive) of the rectangle
+    pub to: IVec2,
+    pub layers: Vec<PreviewLayer>,
+    pub output: PathBuf,
+}
+
+pub const USAGE: &str = "Usage: cobble preview [--seed N] [--from X,Z] [--to X,Z] \
+[--layers surface,height,biome] [--output FILE]
+
+Renders the chunks from `--from` to `--to` (inclusive), default -8,-8 to 7,7, of the configured \
+world generator to a PNG. Additional layers are written next to the output with the layer name \
+as suffix, e.g., `preview_height.png`.";
+
+impl PreviewArgs {
+    /// Parse the arguments following the program name. Returns `None` if no preview was requested
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Option<Result<Self, String>> {
+        if args.next().as_deref() != Some("preview") {
+            return None;
+        }
+        let mut preview = Self {
+            seed: 0,
+            from: IVec2::new(-8, -8),
+            to: IVec2::new(7, 7),
+            layers: vec![PreviewLayer::Surface],
+            output: PathBuf::from("preview.png"),
+        };
+
+        fn chunk(value: Option<String>) -> Result<IVec2, String> {
+            let value = value.ok_or("Missing chunk index")?;
+            let mut components = value.split(',').map(|c| c.trim().parse::<i32>());
+            match (components.next(), components.next(), components.next()) {
+                (Some(Ok(x)), Some(Ok(z)), None) => Ok(IVec2::new(x, z)),
+                _ => Err(format!("Invalid chunk index `{}`, expected X,Z", value)),
+            }
+        }
+
+        while let Some(arg) = args.next() {
+            let result = match arg.as_str() {
+                "--seed" => args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .map(|seed| preview.seed = seed)
+                    .ok_or_else(|| "Invalid or missing seed".to_owned()),
+                "--from" => chunk(args.next()).map(|c| preview.from = c),
+                "--to" => chunk(args.next()).map(|c| preview.to = c),
+                "--output" => args
+                    .next()
+                    .map(|p| preview.output = PathBuf::from(p))
+                    .ok_or_else(|| "Missing output path".to_owned()),
+                "--layers" => args
+                    .next()
+                    .ok_or_else(|| "Missing layers".to_owned())
+                    .and_then(|layers| {
+                        layers
+                            .split(',')
+                            .map(|layer| match layer.trim() {
+                                "surface" => Ok(PreviewLayer::Surface),
+                                "height" => Ok(PreviewLayer::Height),
+                                "biome" => Ok(PreviewLayer::Biome),
+                                other => Err(format!("Unknown layer `{}`", other)),
+                            })
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .map(|layers| preview.layers = layers),
+                other => Err(format!("Unknown argument `{}`", other)),
+            };
+            if let Err(e) = result {
+                return Some(Err(e));
+            }
+        }
+        if preview.from.x > preview.to.x || preview.from.y > preview.to.y {
+            return Some(Err("`--from` must not be greater than `--to`".to_owned()));
+        }
+        Some(Ok(preview))
+    }
+}
+
+/// Topmost non-air block of a column and its height
+fn surface(chunk: &GameChunk, x: usize, z: usize) -> Option<(BlockType, usize)> {
+    (0..chunk.height())
+        .rev()
+        .map(|y| (chunk[(x, y, z)], y))
+        .find(|(block, _)| *block != BlockType::Air)
+}
+
+pub fn block_color(block: BlockType) -> [u8; 3] {
+    BLOCK_COLORS
+        .iter()
+        .find(|(_, b)| *b == Some(block))
+        .map_or([255, 0, 255], |(color, _)| *color)
+}
+
+/// Snow covers the upper quarter of a world `world_height` blocks high
+fn biome_color(block: BlockType, height: usize, world_height: usize) -> [u8; 3] {
+    match block {
+        BlockType::Sand => [230, 200, 90],
+        BlockType::Leaves | BlockType::Wood => [30, 100, 40],
+        BlockType::Water => [63, 118, 228],
+        _ if height >= world_height * 3 / 4 => [240, 240, 240],
+        BlockType::Gravel | BlockType::Cobble => [120, 110, 110],
+        _ => [110, 180, 70],
+    }
+}
+
+/// Height map and surface blocks of a rectangle of chunks
+pub struct SurfaceMap {
+    width: usize,
+    world_height: usize,
+    columns: Vec<Option<(BlockType, usize)>>,
+}
+
+impl SurfaceMap {
+    pub fn generate(
+        generator: &dyn WorldGenerator,
+        seed: u32,
+        world_height: usize,
+        from: IVec2,
+        to: IVec2,
+    ) -> Self {
+        let chunks_x = (to.x - from.x + 1) as usize;
+        let chunks_z = (to.y - from.y + 1) as usize;
+        let width = chunks_x * defaults::CHUNK_WIDTH;
+        let mut columns = vec![None; width * chunks_z * defaults::CHUNK_WIDTH];
+        for cz in 0..chunks_z {
+            for cx in 0..chunks_x {
+                let at = from + IVec2::new(cx as i32, cz as i32);
+                let chunk = generator.chunk(at, seed, world_height);
+                for z in 0..defaults::CHUNK_WIDTH {
+                    for x in 0..defaults::CHUNK_WIDTH {
+                        let px = cx * defaults::CHUNK_WIDTH + x;
+                        let pz = cz * defaults::CHUNK_WIDTH + z;
+                        columns[pz * width + px] = surface(&chunk, x, z);
+                    }
+                }
+            }
+        }
+        Self {
+            width,
+            world_height,
+            columns,
+        }
+    }
+
+    fn height(&self) -> usize {
+        self.columns.len() / self.width
+    }
+
+    fn column_height(&self, x: usize, z: usize) -> f32 {
+        self.columns[z * self.width + x].map_or(0.0, |(_, h)| h as f32)
+    }
+
+    pub fn render(&self, layer: PreviewLayer) -> RgbImage {
+        RgbImage::from_fn(self.width as u32, self.height() as u32, |x, z| {
+            let (x, z) = (x as usize, z as usize);
+            match (layer, self.columns[z * self.width + x]) {
+                (_, None) => Rgb([0, 0, 0]),
+                (PreviewLayer::Height, Some((_, height))) => {
+                    let v = (height * 255 / (self.world_height - 1)) as u8;
+                    Rgb([v, v, v])
+                }
+                (PreviewLayer::Biome, Some((block, height))) => {
+                    Rgb(biome_color(block, height, self.world_height))
+                }
+                (PreviewLayer::Surface, Some((block, height))) => {
+                    // Brighten higher terrain and add relief by comparing with the north-western
+                    // neighbour
+                    let relief = if x > 0 && z > 0 {
+                        height as f32 - self.column_height(x - 1, z - 1)
+                    } else {
+                        0.0
+                    };
+                    let shade = (0.6
+                        + 0.4 * height as f32 / (self.world_height - 1) as f32
+                        + 0.08 * relief)
+                        .clamp(0.2, 1.4);
+                    let color = block_color(block);
+                    Rgb([
+                        (color[0] as f32 * shade).min(255.0) as u8,
+                        (color[1] as f32 * shade).min(255.0) as u8,
+                        (color[2] as f32 * shade).min(255.0) as u8,
+                    ])
+                }
+            }
+        })
+    }
+}
+
+fn layer_path(output: &Path, layer: PreviewLayer) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map_or_else(|| "preview".into(), |s| s.to_string_lossy());
+    output.with_file_name(format!("{}{}.png", stem, layer.suffix()))
+}
+
+/// Generate the requested chunks and write an image for every layer
+pub fn render_preview(
+    generator: &dyn WorldGenerator,
+    world_height: usize,
+    args: &PreviewArgs,
+) -> Result<Vec<PathBuf>, image::ImageError> {
+    let map = SurfaceMap::generate(generator, args.seed, world_height, args.from, args.to);
+    let mut written = Vec::with_capacity(args.layers.len());
+    for layer in args.layers.iter() {
+        let path = layer_path(&args.output, *layer);
+        map.render(*layer).save(&path)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::VoxelChunk;
+
+    /// Columns as high as their x coordinate in the chunk, of sand in the first row and of dirt
+    /// everywhere else
+    struct Stairs;
+
+    impl WorldGenerator for Stairs {
+        fn chunk(&self, at: IVec2, _seed: u32, height: usize) -> GameChunk {
+            let mut voxels =
+                VoxelChunk::air([defaults::CHUNK_WIDTH, height, defaults::CHUNK_WIDTH]);
+            for x in 0..defaults::CHUNK_WIDTH {
+                for z in 0..defaults::CHUNK_WIDTH {
+                    for y in 0..x {
+                        voxels[(x, y, z)] = if z == 0 {
+                            BlockType::Sand
+                        } else {
+                            BlockType::Dirt
+                        };
+                    }
+                }
+            }
+            GameChunk::from_voxels(at, &voxels)
+        }
+    }
+
+    fn parse(args: &str) -> Option<Result<PreviewArgs, String>> {
+        PreviewArgs::parse(args.split_whitespace().map(str::to_owned))
+    }
+
+    fn error(args: &str) -> String {
+        parse(args).unwrap().err().unwrap()
+    }
+
+    #[test]
+    fn parse_arguments() {
+        assert!(parse("").is_none());
+        assert!(parse("--seed 3").is_none());
+
+        let defaults = parse("preview").unwrap().unwrap();
+        assert_eq!(defaults.seed, 0);
+        assert_eq!(defaults.from, IVec2::new(-8, -8));
+        assert_eq!(defaults.to, IVec2::new(7, 7));
+        assert_eq!(defaults.layers, vec![PreviewLayer::Surface]);
+        assert_eq!(defaults.output, PathBuf::from("preview.png"));
+
+        let args = parse(
+            "preview --seed 42 --from -2,3 --to 4,3 --layers height,biome --output maps/a.png",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(args.seed, 42);
+        assert_eq!(args.from, IVec2::new(-2, 3));
+        assert_eq!(args.to, IVec2::new(4, 3));
+        assert_eq!(args.layers, vec![PreviewLayer::Height, PreviewLayer::Biome]);
+        assert_eq!(args.output, PathBuf::from("maps/a.png"));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert_eq!(error("preview --seed"), "Invalid or missing seed");
+        assert_eq!(error("preview --seed -1"), "Invalid or missing seed");
+        assert_eq!(error("preview --from"), "Missing chunk index");
+        assert_eq!(
+            error("preview --to 1,2,3"),
+            "Invalid chunk index `1,2,3`, expected X,Z"
+        );
+        assert_eq!(
+            error("preview --from a,0"),
+            "Invalid chunk index `a,0`, expected X,Z"
+        );
+        assert_eq!(
+            error("preview --layers height,caves"),
+            "Unknown layer `caves`"
+        );
+        assert_eq!(error("preview --output"), "Missing output path");
+        assert_eq!(error("preview --zoom 2"), "Unknown argument `--zoom`");
+        // The range is inclusive, a single chunk is allowed but not an empty range
+        assert!(parse("preview --from 2,2 --to 2,2").unwrap().is_ok());
+        assert_eq!(
+            error("preview --from 3,0 --to 2,5"),
+            "`--from` must not be greater than `--to`"
+        );
+        assert_eq!(
+            error("preview --from 0,3 --to 5,2"),
+            "`--from` must not be greater than `--to`"
+        );
+    }
+
+    #[test]
+    fn render_layers() {
+        let world_height = defaults::SECTION_SIZE;
+        let map = SurfaceMap::generate(&Stairs, 0, world_height, IVec2::ZERO, IVec2::new(1, 0));
+        let pixel = |layer, x, z| map.render(layer).get_pixel(x, z).0;
+        let image = map.render(PreviewLayer::Surface);
+        let width = defaults::CHUNK_WIDTH as u32;
+        assert_eq!(image.dimensions(), (2 * width, width));
+
+        // Empty columns are black in every layer
+        for layer in [
+            PreviewLayer::Surface,
+            PreviewLayer::Height,
+            PreviewLayer::Biome,
+        ]
+        .iter()
+        {
+            assert_eq!(pixel(*layer, 0, 3), [0, 0, 0]);
+            assert_eq!(pixel(*layer, 16, 3), [0, 0, 0]);
+        }
+
+        // The topmost block of the column at x is at a height of x - 1
+        assert_eq!(pixel(PreviewLayer::Height, 1, 3), [0, 0, 0]);
+        assert_eq!(pixel(PreviewLayer::Height, 6, 3), [85, 85, 85]);
+        assert_eq!(pixel(PreviewLayer::Height, 31, 3), [238, 238, 238]);
+
+        // Higher terrain is brighter
+        let dirt = |x| pixel(PreviewLayer::Surface, x, 3);
+        assert!(dirt(4)[0] < dirt(8)[0] && dirt(8)[0] < dirt(12)[0]);
+        assert_eq!(
+            pixel(PreviewLayer::Surface, 20, 0),
+            pixel(PreviewLayer::Surface, 4, 0)
+        );
+
+        // Snow starts at three quarters of the world's height, but not on the beaches
+        assert_eq!(pixel(PreviewLayer::Biome, 12, 3), [110, 180, 70]);
+        assert_eq!(pixel(PreviewLayer::Biome, 13, 3), [240, 240, 240]);
+        assert_eq!(pixel(PreviewLayer::Biome, 15, 0), [230, 200, 90]);
+    }
+
+    #[test]
+    fn layer_paths() {
+        let output = Path::new("maps/world.png");
+        assert_eq!(
+            layer_path(output, PreviewLayer::Surface),
+            PathBuf::from("maps/world.png")
+        );
+        assert_eq!(
+            layer_path(output, PreviewLayer::Height),
+            PathBuf::from("maps/world_height.png")
+        );
+        assert_eq!(
+            layer_path(Path::new("world"), PreviewLayer::Biome),
+            PathBuf::from("world_biome.png")
+        );
+    }
+}
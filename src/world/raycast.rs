@@ -1,14 +1,26 @@
-use std::convert::TryInto;
+use std::collections::HashMap;
 
 use bevy::math::UVec3;
 use bevy::{
-    math::{swizzles::Vec3Swizzles, IVec2, IVec3, Vec3},
+    math::{IVec2, IVec3, Vec3},
     prelude::*,
 };
+use bevy_rapier3d::{
+    na::{Point3, Vector3},
+    physics::ColliderHandleComponent,
+    rapier::{
+        geometry::{Collider, ColliderHandle, ColliderSet, InteractionGroups, Ray},
+        pipeline::QueryPipeline,
+    },
+};
 
 use crate::{
     interface::controller::CameraTag,
-    world::{absolut_to_index, defaults, BlockType, NineSurroundChunk},
+    world::{
+        absolut_to_index_i32, defaults, properties, BlockType, GameChunk, NineSurroundChunk,
+        COLLIDER_ENV_FLOOR_UD, COLLIDER_ENV_OTHER_UD, COLLIDER_PLAYER_SENSOR_UD,
+        COLLIDER_PLAYER_UD,
+    },
 };
 
 const MAX_REACH: f32 = 6.0;
@@ -23,178 +35,188 @@ pub struct VoxelRaycastPlugin;
 
 impl Plugin for VoxelRaycastPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<RaycastSelection>().add_system_to_stage(
-            CoreStage::PostUpdate,
-            raycast_from_camera.system().label(RaycastLabels::Raycast), //),
-        );
+        app.init_resource::<RaycastSelection>()
+            .init_resource::<ColliderEntities>()
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                track_collider_entities
+                    .system()
+                    .before(RaycastLabels::Raycast),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                raycast_from_camera.system().label(RaycastLabels::Raycast),
+            );
     }
 }
 
-/// Compute the intersection distance and normal of a ray with a cuboidal voxel if any.
-///
-/// Adapted from Majercik, A., Crassin, C., Shirley, P. and McGuire, M., 2018. _A ray-box intersection algorithm and efficient dynamic voxel rendering_. Journal of Computer Graphics Techniques Vol, 7(3).
-/// Available online http://jcgt.org/published/0007/03/04/
-///
-/// # Arguments
-/// * `position` - Absolute coordinates of the cube center. Note that this differs from the absolute index of a voxel by 0.5 on each axsis
-/// * `ray_origin` - Origin of the ray, e.g., the camera position
-/// * `ray_dir` - Direction of the the ray, e.g., based on rotation of the camera
-///
-/// # Alternative rapier/parry implementation
-/// Seems to be a bit slower, however
-/// ```
-/// bevy_rapier3d::rapier::parry::query::RayCast::cast_ray_and_get_normal(
-///     &bevy_rapier3d::rapier::geometry::Cuboid::new(nalgebra::Matrix3x1::from_element(BOX_RADIUS)),
-///     &nalgebra::Isometry3::from_parts(
-///         nalgebra::Translation3::new(box_center.x, box_center.y, box_center.z),
-///         nalgebra::UnitQuaternion::identity(),
-///     ),
-///     &bevy_rapier3d::rapier::geometry::Ray::new(ray_origin.into(), ray_dir.into()),
-///     6.0,
-///     true,
-/// )
-/// .map(|v| (v.toi, v.normal.into()))
-/// ```
-pub fn intersect_box(box_center: Vec3, ray_origin: Vec3, ray_dir: Vec3) -> Option<(f32, Vec3)> {
-    const ORIENTED: bool = false;
-    const CAN_START_IN_BOX: bool = false;
-
-    const BOX_RADIUS: f32 = 0.5;
-    const INV_BOX_RADIUS: f32 = 1.0 / BOX_RADIUS;
-    const BOX_ROT: Vec3 = Vec3::ZERO;
-    let inv_ray_dir = 1.0 / ray_dir;
-
-    let mut ray_origin = ray_origin - box_center;
-    let mut ray_dir = ray_dir;
-    if ORIENTED {
-        ray_dir *= BOX_ROT;
-        ray_origin *= BOX_ROT;
-    }
-
-    let winding: f32 =
-        if CAN_START_IN_BOX && (ray_origin.abs() * INV_BOX_RADIUS).max_element() < 1.0 {
-            -1.0
-        } else {
-            1.0
-        };
-    let mut sgn: Vec3 = -ray_dir.signum();
-    // Distance to plane
-    let mut d: Vec3 = BOX_RADIUS * winding * sgn - ray_origin;
-    if ORIENTED {
-        d /= ray_dir;
-    } else {
-        d *= inv_ray_dir;
-    };
-
-    fn test_component(u: f32, vm: fn(Vec3) -> Vec2, ray_origin: &Vec3, ray_dir: &Vec3) -> bool {
-        u >= 0.0
-            && (vm(*ray_origin) + vm(*ray_dir) * u)
-                .abs()
-                .cmplt(Vec2::from([BOX_RADIUS, BOX_RADIUS]))
-                .all()
-    }
-    struct Bvec3 {
-        x: bool,
-        y: bool,
-        z: bool,
-    }
-    let test = Bvec3 {
-        x: test_component(d.x, Vec3Swizzles::yz, &ray_origin, &ray_dir),
-        y: test_component(d.y, Vec3Swizzles::zx, &ray_origin, &ray_dir),
-        z: test_component(d.z, Vec3Swizzles::xy, &ray_origin, &ray_dir),
-    };
-    sgn = if test.x {
-        Vec3::new(sgn.x, 0.0, 0.0)
-    } else if test.y {
-        Vec3::new(0.0, sgn.y, 0.0)
-    } else {
-        Vec3::new(0.0, 0.0, if test.z { sgn.z } else { 0.0 })
-    };
+/// Block hit by a voxel raycast
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelHit {
+    pub chunk: IVec2,
+    pub index: UVec3,
+    pub block: BlockType,
+    /// Normal of the face the ray entered the block through, zero if the ray started inside it
+    pub normal: IVec3,
+    /// Distance from the ray origin to the entry point
+    pub distance: f32,
+}
 
-    let distance: f32 = if sgn.x != 0.0 {
-        d.x
-    } else if sgn.y != 0.0 {
-        d.y
-    } else {
-        d.z
-    };
-    let normal = if ORIENTED { BOX_ROT * sgn } else { sgn };
-    if (sgn.x != 0.0) || (sgn.y != 0.0) || (sgn.z != 0.0) {
-        Some((distance, normal))
-    } else {
-        None
+/// Block at absolute voxel coordinates if its chunk is loaded and it lies within the world
+fn block_at(chunk_store: &NineSurroundChunk, voxel: IVec3) -> Option<(IVec2, UVec3, BlockType)> {
+    if voxel.y < 0 {
+        return None;
     }
+    let (chunk, index) = absolut_to_index_i32::<{ defaults::CHUNK_WIDTH }>(&voxel);
+    chunk_store
+        .data
+        .get(&chunk)
+        .and_then(|data| data.get(index))
+        .map(|block| (chunk, index, block))
 }
 
-/// Find the closest non-air voxel to the provided ray origin.
-/// Returning the voxel chunk, index and normal vector if any assuming the relevant chunks are
-/// in memory.
+/// Find the first block along a ray for which `filter` returns true, up to `max_distance` from
+/// the origin. The grid is traversed voxel by voxel, across chunk borders, following
+/// Amanatides, J. and Woo, A., 1987. _A fast voxel traversal algorithm for ray tracing_.
+/// Voxels in unloaded chunks or outside of the world are skipped, so `max_distance` has to be
+/// finite.
 pub fn raycast_voxel(
-    ray_origin: Vec3,
-    ray_direction: Vec3,
     chunk_store: &NineSurroundChunk,
-) -> Option<(IVec2, UVec3, IVec3)> {
-    let mut min_distance = f32::INFINITY;
-    let mut arg_min = None;
-
-    // TODO Make more efficient by pruning
-    // Find closest intersecting voxel using arg_min on the minimal intersection distance
-    for x in -5..5 {
-        for y in -5..5 {
-            for z in -5..5 {
-                // Calculate the center of the next voxel to check
-                let v = ray_origin.as_i32().as_f32()
-                    + IVec3::new(x, y, z).as_f32()
-                    + Vec3::from([0.5; 3]);
-
-                let (v_chunk, v_index) = absolut_to_index::<{ defaults::CHUNK_WIDTH }>(&v);
-                if v_index.y >= defaults::CHUNK_HEIGHT.try_into().unwrap()
-                    || v_index.x >= defaults::CHUNK_WIDTH.try_into().unwrap()
-                    || v_index.z >= defaults::CHUNK_WIDTH.try_into().unwrap()
-                {
-                    continue;
-                }
-                if let Some((distance, normal)) = intersect_box(v, ray_origin, ray_direction) {
-                    match chunk_store.data.get(&v_chunk) {
-                        Some(chunk)
-                            if distance < min_distance
-                                && distance >= 0.0
-                                && distance <= MAX_REACH =>
-                        {
-                            match chunk.voxel[v_index] {
-                                BlockType::Air => continue,
-                                _ => {
-                                    min_distance = distance;
-                                    arg_min = Some((v_chunk, v_index, normal.as_i32()));
-                                }
-                            }
-                        }
-                        None => {
-                            debug!(
-                                "Attempted to raycast with unloaded chunk. {} is missing",
-                                v_chunk
-                            );
-                        }
-                        _ => {}
-                    }
-                }
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    filter: impl Fn(BlockType) -> bool,
+) -> Option<VoxelHit> {
+    debug_assert!(max_distance.is_finite());
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+    let origin: [f32; 3] = origin.into();
+    let direction: [f32; 3] = direction.into();
+
+    let mut voxel = [0i32; 3];
+    let mut step = [0i32; 3];
+    // Distance along the ray to the next voxel border and between two borders for every axis
+    let mut t_max = [f32::INFINITY; 3];
+    let mut t_delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        voxel[axis] = origin[axis].floor() as i32;
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_delta[axis] = 1.0 / direction[axis];
+            t_max[axis] = (voxel[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_delta[axis] = -1.0 / direction[axis];
+            t_max[axis] = (origin[axis] - voxel[axis] as f32) * t_delta[axis];
+        }
+    }
+
+    let mut distance = 0.0;
+    let mut normal = IVec3::ZERO;
+    loop {
+        if let Some((chunk, index, block)) = block_at(chunk_store, IVec3::from(voxel)) {
+            if filter(block) {
+                return Some(VoxelHit {
+                    chunk,
+                    index,
+                    block,
+                    normal,
+                    distance,
+                });
             }
         }
+
+        let axis = if t_max[0] < t_max[1] && t_max[0] < t_max[2] {
+            0
+        } else if t_max[1] < t_max[2] {
+            1
+        } else {
+            2
+        };
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+        voxel[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        let mut entered = [0; 3];
+        entered[axis] = -step[axis];
+        normal = IVec3::from(entered);
     }
-    arg_min
 }
 
-/// Block the player is looking at and normal (unit) vector indicating the looked at face
+/// Entity of every collider, to resolve the collider hit by a ray without going through all of
+/// them
+#[derive(Default)]
+pub struct ColliderEntities(HashMap<ColliderHandle, Entity>);
+
+fn track_collider_entities(
+    mut entities: ResMut<ColliderEntities>,
+    added: Query<(Entity, &ColliderHandleComponent), Added<ColliderHandleComponent>>,
+    removed: RemovedComponents<ColliderHandleComponent>,
+) {
+    for (entity, component) in added.iter() {
+        entities.0.insert(component.handle(), entity);
+    }
+    let removed: Vec<Entity> = removed.iter().collect();
+    if !removed.is_empty() {
+        entities.0.retain(|_, entity| !removed.contains(entity));
+    }
+}
+
+/// Find the closest entity with a collider along a ray, up to `max_distance` from the origin,
+/// ignoring the player and the colliders of the world itself. Returns the entity and the distance
+/// to it
+pub fn raycast_entity(
+    query_pipeline: &QueryPipeline,
+    collider_set: &ColliderSet,
+    entities: &ColliderEntities,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<(Entity, f32)> {
+    const IGNORED: [u128; 4] = [
+        COLLIDER_PLAYER_UD,
+        COLLIDER_PLAYER_SENSOR_UD,
+        COLLIDER_ENV_FLOOR_UD,
+        COLLIDER_ENV_OTHER_UD,
+    ];
+    let direction = direction.normalize_or_zero();
+    let ray = Ray::new(
+        Point3::new(origin.x, origin.y, origin.z),
+        Vector3::new(direction.x, direction.y, direction.z),
+    );
+    let (handle, distance) = query_pipeline.cast_ray(
+        collider_set,
+        &ray,
+        max_distance,
+        true,
+        InteractionGroups::all(),
+        Some(&|_: ColliderHandle, collider: &Collider| !IGNORED.contains(&collider.user_data)),
+    )?;
+    entities.0.get(&handle).map(|entity| (*entity, distance))
+}
+
+/// Block the player is looking at and normal (unit) vector indicating the looked at face, or the
+/// entity the player is looking at, whichever is closer
 #[derive(Default)]
 pub struct RaycastSelection {
     pub looking_at: Option<(IVec2, UVec3)>,
     pub normal: Option<IVec3>,
+    pub entity: Option<Entity>,
+    /// Distance to the selected block or entity
+    pub distance: Option<f32>,
 }
 
 fn raycast_from_camera(
     mut selection: ResMut<RaycastSelection>,
     chunks: Res<NineSurroundChunk>,
     query: Query<&GlobalTransform, With<CameraTag>>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_set: Res<ColliderSet>,
+    entities: Res<ColliderEntities>,
 ) {
     if let Ok(global_transform) = query.single() {
         // Taken from bevy_mod_raycast, see README for license information
@@ -206,21 +228,189 @@ fn raycast_from_camera(
         let ray_direction = pick_position - source_origin;
 
         // Compute the looked at voxel and the respective normal vector
-        if let Some((sel_chunk, sel_index, sel_normal)) =
-            raycast_voxel(source_origin, ray_direction, &chunks)
-        {
-            selection.looking_at = Some((sel_chunk, sel_index));
-            selection.normal = if (sel_index.y == 0 && sel_normal.y < 0)
-                || (sel_index.y >= defaults::CHUNK_HEIGHT as u32 - 1 && sel_normal.y > 0)
+        // Fluids can neither be selected nor block the selection
+        let block = raycast_voxel(&chunks, source_origin, ray_direction, MAX_REACH, |block| {
+            block != BlockType::Air && !properties(&block).fluid
+        });
+        let entity = raycast_entity(
+            &query_pipeline,
+            &collider_set,
+            &entities,
+            source_origin,
+            ray_direction,
+            MAX_REACH,
+        );
+        *selection = select(&chunks, block, entity);
+    }
+}
+
+/// Selection of the block or entity that was hit, whichever is closer
+fn select(
+    chunks: &NineSurroundChunk,
+    block: Option<VoxelHit>,
+    entity: Option<(Entity, f32)>,
+) -> RaycastSelection {
+    match (block, entity) {
+        (_, Some((entity, distance))) if block.map_or(true, |block| distance < block.distance) => {
+            RaycastSelection {
+                looking_at: None,
+                normal: None,
+                entity: Some(entity),
+                distance: Some(distance),
+            }
+        }
+        (Some(hit), _) => {
+            let height = chunks.data.get(&hit.chunk).map_or(0, GameChunk::height);
+            let normal = if (hit.index.y == 0 && hit.normal.y < 0)
+                || (hit.index.y as usize + 1 >= height && hit.normal.y > 0)
+                || hit.normal == IVec3::ZERO
             {
-                // Ignore normals that are above or below the chunk boundaries
+                // Ignore normals that are above or below the chunk boundaries or from within
+                // a block
                 None
             } else {
-                Some(sel_normal)
+                Some(hit.normal)
             };
-        } else {
-            selection.looking_at = None;
-            selection.normal = None;
+            RaycastSelection {
+                looking_at: Some((hit.chunk, hit.index)),
+                normal,
+                entity: None,
+                distance: Some(hit.distance),
+            }
+        }
+        (None, _) => RaycastSelection::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_rapier3d::rapier::{
+        dynamics::{RigidBodyBuilder, RigidBodySet},
+        geometry::ColliderBuilder,
+    };
+
+    use super::*;
+    use crate::world::COLLIDER_ITEM_UD;
+
+    fn store() -> NineSurroundChunk {
+        let mut data = HashMap::new();
+        for index in [IVec2::new(0, 0), IVec2::new(-1, 0)].iter() {
+            let mut chunk = GameChunk::empty(*index, defaults::WORLD_HEIGHT);
+            for x in 0..defaults::CHUNK_WIDTH as u32 {
+                for z in 0..defaults::CHUNK_WIDTH as u32 {
+                    chunk.set(UVec3::new(x, 2, z), BlockType::Cobble);
+                }
+            }
+            data.insert(*index, chunk);
         }
+        // Pillar in the neighbouring chunk at x = -3
+        let neighbour = data.get_mut(&IVec2::new(-1, 0)).unwrap();
+        neighbour.set(UVec3::new(13, 3, 1), BlockType::Dirt);
+        neighbour.set(UVec3::new(12, 3, 1), BlockType::Sand);
+        NineSurroundChunk::from_data(data)
+    }
+
+    #[test]
+    fn raycast_hits() {
+        let store = store();
+        let solid = |block| block != BlockType::Air;
+
+        let hit = raycast_voxel(&store, Vec3::new(0.5, 5.5, 0.5), -Vec3::Y, 10.0, solid).unwrap();
+        assert_eq!(hit.chunk, IVec2::new(0, 0));
+        assert_eq!(hit.index, UVec3::new(0, 2, 0));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert!((hit.distance - 2.5).abs() < 1e-5);
+
+        // Across the chunk border
+        let origin = Vec3::new(1.5, 3.5, 1.5);
+        let hit = raycast_voxel(&store, origin, -Vec3::X, 10.0, solid).unwrap();
+        assert_eq!(hit.chunk, IVec2::new(-1, 0));
+        assert_eq!(hit.index, UVec3::new(13, 3, 1));
+        assert_eq!(hit.block, BlockType::Dirt);
+        assert_eq!(hit.normal, IVec3::X);
+        assert!((hit.distance - 3.5).abs() < 1e-5);
+
+        // Maximum distance and filter
+        assert_eq!(raycast_voxel(&store, origin, -Vec3::X, 3.0, solid), None);
+        let hit = raycast_voxel(&store, origin, -Vec3::X, 10.0, |block| {
+            block == BlockType::Sand
+        })
+        .unwrap();
+        assert_eq!(hit.index, UVec3::new(12, 3, 1));
+
+        // Diagonal ray starting inside a block
+        let hit = raycast_voxel(&store, Vec3::new(0.2, 2.5, 0.3), Vec3::ONE, 10.0, solid).unwrap();
+        assert_eq!(hit.normal, IVec3::ZERO);
+        assert_eq!(hit.distance, 0.0);
+        assert_eq!(
+            raycast_voxel(&store, Vec3::new(0.5, 3.5, 0.5), Vec3::Y, 100.0, solid),
+            None
+        );
+    }
+
+    #[test]
+    fn closer_hit_is_selected() {
+        let store = store();
+        let block = raycast_voxel(&store, Vec3::new(0.5, 5.5, 0.5), -Vec3::Y, 10.0, |block| {
+            block != BlockType::Air
+        });
+        let block_distance = block.map(|hit| hit.distance);
+        let entity = Entity::new(7);
+
+        let selection = select(&store, block, Some((entity, 1.0)));
+        assert_eq!(selection.entity, Some(entity));
+        assert_eq!(selection.looking_at, None);
+        assert_eq!(selection.normal, None);
+        assert_eq!(selection.distance, Some(1.0));
+
+        // An entity behind the block is hidden by it
+        let selection = select(&store, block, Some((entity, 4.0)));
+        assert_eq!(selection.entity, None);
+        assert_eq!(
+            selection.looking_at,
+            Some((IVec2::ZERO, UVec3::new(0, 2, 0)))
+        );
+        assert_eq!(selection.normal, Some(IVec3::Y));
+        assert_eq!(selection.distance, block_distance);
+
+        let selection = select(&store, None, Some((entity, 5.0)));
+        assert_eq!(selection.entity, Some(entity));
+        let selection = select(&store, None, None);
+        assert_eq!(selection.entity, None);
+        assert_eq!(selection.looking_at, None);
+        assert_eq!(selection.distance, None);
+
+        // A dropped item lying above the floor is hit by rapier's ray cast before the floor
+        let mut bodies = RigidBodySet::new();
+        let mut collider_set = ColliderSet::new();
+        let body = bodies.insert(
+            RigidBodyBuilder::new_static()
+                .translation(0.5, 4.0, 0.5)
+                .build(),
+        );
+        let collider = ColliderBuilder::cuboid(0.1, 0.1, 0.1)
+            .user_data(COLLIDER_ITEM_UD)
+            .build();
+        let handle = collider_set.insert(collider, body, &mut bodies);
+        let mut query_pipeline = QueryPipeline::new();
+        query_pipeline.update(&bodies, &collider_set);
+        let item = Entity::new(3);
+        let mut entities = ColliderEntities::default();
+        entities.0.insert(handle, item);
+
+        let origin = Vec3::new(0.5, 5.5, 0.5);
+        let hit = raycast_entity(
+            &query_pipeline,
+            &collider_set,
+            &entities,
+            origin,
+            -Vec3::Y,
+            MAX_REACH,
+        );
+        assert_eq!(hit.map(|(entity, _)| entity), Some(item));
+        assert!((hit.unwrap().1 - 1.4).abs() < 1e-5);
+        let selection = select(&store, block, hit);
+        assert_eq!(selection.entity, Some(item));
+        assert_eq!(selection.looking_at, None);
     }
 }
@@ -1,6 +1,6 @@
 use super::{blocks, defaults, BlockType};
 use bevy::{prelude::*, render::pipeline::PrimitiveTopology};
-use blocks::MeshGroup;
+use blocks::{Facing, MeshGroup};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
@@ -18,7 +18,6 @@ impl<T: Sized + Clone> VoxelChunk<T> {
         Self(ndarray::Array3::from_elem(size, fill), size)
     }
 
-    #[allow(dead_code)]
     pub fn shape(&self) -> [usize; 3] {
         self.1
     }
@@ -44,21 +43,10 @@ impl VoxelChunk<Block> {
     pub fn air(size: [usize; 3]) -> Self {
         Self(ndarray::Array3::from_elem(size, BlockType::Air), size)
     }
-}
 
-impl<T: Sized + Clone> VoxelChunk<T> {
-    fn safe_get(&self, x: i32, y: i32, z: i32) -> Option<&T> {
-        if 0 > x
-            || x >= self.width() as i32
-            || 0 > y
-            || y >= self.height() as i32
-            || 0 > z
-            || z >= self.depth() as i32
-        {
-            None
-        } else {
-            Some(&self.0[(x as usize, y as usize, z as usize)])
-        }
+    /// Whether the chunk only contains air
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|block| *block == BlockType::Air)
     }
 }
 
@@ -132,12 +120,156 @@ impl<const WIDTH: usize> InChunk<WIDTH> for IVec3 {
     }
 }
 
+/// Column of cubic sections, stacked from the bottom of the world to its top. Sections that only
+/// contain air are elided
 #[derive(Clone)]
 pub struct GameChunk {
-    pub voxel: Box<VoxelChunk<Block>>,
+    pub sections: Vec<Option<Box<VoxelChunk<Block>>>>,
     pub index: IVec2,
 }
 
+/// Returned when indexing elided sections
+static AIR: Block = BlockType::Air;
+
+impl GameChunk {
+    /// Chunk of only air with the given height, which is rounded up to whole sections
+    pub fn empty(index: IVec2, height: usize) -> Self {
+        Self {
+            sections: vec![None; (height + defaults::SECTION_SIZE - 1) / defaults::SECTION_SIZE],
+            index,
+        }
+    }
+
+    /// Split a column of voxels into sections, eliding the empty ones. The height of the column
+    /// has to be a multiple of the section size
+    pub fn from_voxels(index: IVec2, voxels: &VoxelChunk<Block>) -> Self {
+        assert_eq!(voxels.width(), defaults::SECTION_SIZE);
+        assert_eq!(voxels.depth(), defaults::SECTION_SIZE);
+        assert_eq!(voxels.height() % defaults::SECTION_SIZE, 0);
+        let sections = (0..voxels.height() / defaults::SECTION_SIZE)
+            .map(|s| {
+                let range = s * defaults::SECTION_SIZE..(s + 1) * defaults::SECTION_SIZE;
+                let section = VoxelChunk(
+                    voxels.0.slice(ndarray::s![.., range, ..]).to_owned(),
+                    defaults::SECTION_SHAPE,
+                );
+                if section.is_empty() {
+                    None
+                } else {
+                    Some(Box::new(section))
+                }
+            })
+            .collect();
+        Self { sections, index }
+    }
+
+    pub fn height(&self) -> usize {
+        self.sections.len() * defaults::SECTION_SIZE
+    }
+
+    /// Section `s`, counted from the bottom, or `None` if it is empty or above the world
+    pub fn section(&self, s: usize) -> Option<&VoxelChunk<Block>> {
+        self.sections.get(s).and_then(|section| section.as_deref())
+    }
+
+    /// Block at an index, or `None` if the index lies outside of the chunk
+    pub fn get(&self, index: UVec3) -> Option<Block> {
+        let (x, y, z) = (index.x as usize, index.y as usize, index.z as usize);
+        if x >= defaults::SECTION_SIZE || z >= defaults::SECTION_SIZE || y >= self.height() {
+            return None;
+        }
+        Some(self[(x, y, z)])
+    }
+
+    /// Like [`GameChunk::get`] but with signed coordinates, used to look beyond section borders
+    pub fn safe_get(&self, x: i32, y: i32, z: i32) -> Option<Block> {
+        if x < 0 || y < 0 || z < 0 {
+            None
+        } else {
+            self.get(UVec3::new(x as u32, y as u32, z as u32))
+        }
+    }
+
+    /// Set the block at an index, allocating its section or eliding it once it only contains air.
+    /// Returns `false` if the index lies outside of the chunk
+    pub fn set(&mut self, index: UVec3, block: Block) -> bool {
+        let (x, y, z) = (index.x as usize, index.y as usize, index.z as usize);
+        if x >= defaults::SECTION_SIZE || z >= defaults::SECTION_SIZE || y >= self.height() {
+            return false;
+        }
+        let (s, y) = (y / defaults::SECTION_SIZE, y % defaults::SECTION_SIZE);
+        let entry = &mut self.sections[s];
+        match entry {
+            None if block == BlockType::Air => (),
+            None => {
+                let mut section = VoxelChunk::air(defaults::SECTION_SHAPE);
+                section[(x, y, z)] = block;
+                *entry = Some(Box::new(section));
+            }
+            Some(section) => {
+                section[(x, y, z)] = block;
+                if block == BlockType::Air && section.is_empty() {
+                    *entry = None;
+                }
+            }
+        }
+        true
+    }
+
+    /// Hash of the chunk's shape and voxels that is stable across platforms, runs and Rust
+    /// versions (64-bit FNV-1a). Elided sections hash like sections full of air
+    pub fn content_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+        let shape = [
+            defaults::SECTION_SIZE,
+            self.height(),
+            defaults::SECTION_SIZE,
+        ];
+        for dim in shape.iter() {
+            write(&(*dim as u32).to_le_bytes());
+        }
+        for x in 0..shape[0] {
+            for y in 0..shape[1] {
+                for z in 0..shape[2] {
+                    write(&[self[(x, y, z)] as u8]);
+                }
+            }
+        }
+        hash
+    }
+}
+
+impl Index<(usize, usize, usize)> for GameChunk {
+    type Output = Block;
+
+    fn index(&self, index: (usize, usize, usize)) -> &Self::Output {
+        if index.1 >= self.height() {
+            panic!("Out of index access");
+        }
+        match self.section(index.1 / defaults::SECTION_SIZE) {
+            Some(section) => &section[(index.0, index.1 % defaults::SECTION_SIZE, index.2)],
+            None => &AIR,
+        }
+    }
+}
+
+impl Index<UVec3> for GameChunk {
+    type Output = Block;
+
+    fn index(&self, index: UVec3) -> &Self::Output {
+        &self[(index.x as usize, index.y as usize, index.z as usize)]
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum Face {
     Top = 0,
@@ -170,6 +302,102 @@ fn quad_to_points(index: (i32, i32, i32), face: Face) -> [[f32; 3]; 4] {
     ]
 }
 
+/// Distance between a panel and the wall it is attached to
+const PANEL_INSET: f32 = 1.0 / 16.0;
+
+/// Vertex data of the faces of a mesh group
+#[derive(Default)]
+struct BlockMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+    layer: Vec<u32>,
+    index_counter: u32,
+}
+
+impl BlockMesh {
+    fn normal(face: Face) -> [i32; 3] {
+        match face {
+            Face::Top => [0, 1, 0],
+            Face::Bottom => [0, -1, 0],
+            Face::Front => [-1, 0, 0],
+            Face::Back => [1, 0, 0],
+            Face::Left => [0, 0, -1],
+            Face::Right => [0, 0, 1],
+        }
+    }
+
+    fn add_face(&mut self, index: (i32, i32, i32), face: Face, tex_id: u32) {
+        self.add_face_offset(index, face, tex_id, Vec3::ZERO);
+    }
+
+    /// Add a face whose vertices are shifted by `offset`
+    fn add_face_offset(&mut self, index: (i32, i32, i32), face: Face, tex_id: u32, offset: Vec3) {
+        for point in quad_to_points(index, face).iter() {
+            self.positions.push((Vec3::from(*point) + offset).into());
+        }
+
+        let normal = Self::normal(face);
+        let normal = [normal[0] as f32, normal[1] as f32, normal[2] as f32];
+
+        self.normals.extend_from_slice(&[normal; 4]);
+        self.layer.extend_from_slice(&[tex_id; 4]);
+
+        let uv = if [Face::Top, Face::Front, Face::Right].contains(&face) {
+            &[[0.0, 0.0], [1.0, -1.0], [0.0, -1.0], [1.0, 0.0]]
+        } else {
+            /*if [Face::Bottom, Face::Back, Face::Left].contains(face)*/
+            &[[0.0, 1.0], [-1.0, 0.0], [-1.0, 1.0], [0.0, 0.0]]
+        };
+
+        self.uvs.extend_from_slice(uv);
+
+        let c: u32 = self.index_counter;
+        // First triangle
+        self.indices.push(c);
+        self.indices.push(c + 1);
+        self.indices.push(c + 2);
+        // Second triangle
+        self.indices.push(c + 1);
+        self.indices.push(c);
+        self.indices.push(c + 3);
+
+        self.index_counter += 4;
+    }
+
+    /// Add a panel, i.e., a double-sided quad `PANEL_INSET` in front of the wall it is attached to
+    fn add_panel(&mut self, index: (i32, i32, i32), facing: Facing, tex_id: u32) {
+        // Faces pointing away from and towards the wall
+        let (outer, inner) = match facing {
+            Facing::North => (Face::Left, Face::Right),
+            Facing::East => (Face::Back, Face::Front),
+            Facing::South => (Face::Right, Face::Left),
+            Facing::West => (Face::Front, Face::Back),
+        };
+        let normal = facing.normal().as_f32();
+        self.add_face_offset(index, outer, tex_id, -normal * (1.0 - PANEL_INSET));
+        self.add_face_offset(index, inner, tex_id, normal * PANEL_INSET);
+    }
+
+    fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.set_attribute(bevy::prelude::Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.set_attribute("Vertex_Layer", self.layer);
+        mesh.set_indices(Some(bevy::render::mesh::Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+fn tex_ids(block: &BlockType) -> &'static [u32; 6] {
+    blocks::BLOCK_TEX_ID.get(block).unwrap_or_else(|| {
+        warn!("Block `{:?}` has no texture id", block);
+        &[0; 6]
+    })
+}
+
 pub trait Meshable {
     const FACES: [Face; 6] = [
         Face::Top,
@@ -180,85 +408,74 @@ pub trait Meshable {
         Face::Right,
     ];
 
-    fn build(&self) -> HashMap<MeshGroup, Option<Mesh>>;
+    /// Build the meshes of a single section, positioned relative to the section's origin
+    fn build(&self, section: usize) -> HashMap<MeshGroup, Option<Mesh>>;
 }
 
-impl Meshable for GameChunk {
-    fn build(&self) -> HashMap<MeshGroup, Option<Mesh>> {
-        #[derive(Default)]
-        struct BlockMesh {
-            positions: Vec<[f32; 3]>,
-            normals: Vec<[f32; 3]>,
-            uvs: Vec<[f32; 2]>,
-            indices: Vec<u32>,
-            layer: Vec<u32>,
-            index_counter: u32,
+/// Mesh of a single block spanning from the origin to (1, 1, 1), rendered with the chunk pipeline
+pub fn block_mesh(block: BlockType) -> Mesh {
+    let mut mesh = BlockMesh::default();
+    let tex_ids = tex_ids(&block);
+    match block.facing() {
+        Some(facing) if blocks::properties(&block).mesh_group == MeshGroup::Panel => {
+            mesh.add_panel((0, 0, 0), facing, tex_ids[0]);
         }
+        _ => {
+            for face in GameChunk::FACES.iter() {
+                mesh.add_face((0, 0, 0), *face, tex_ids[*face as usize]);
+            }
+        }
+    }
+    mesh.into_mesh()
+}
 
+impl Meshable for GameChunk {
+    fn build(&self, section: usize) -> HashMap<MeshGroup, Option<Mesh>> {
         let mut block_meshes: HashMap<MeshGroup, BlockMesh> = HashMap::new();
         // Tracks blocktypes that have no mesh
         let mut non_existent: HashSet<MeshGroup> = blocks::EXCEPT_NONE_MESH_GROUP_SET.clone();
 
-        for (idx, block) in self.voxel.indexed_iter() {
+        let voxels = match self.section(section) {
+            Some(voxels) => voxels,
+            None => {
+                return blocks::EXCEPT_NONE_MESH_GROUP_SET
+                    .iter()
+                    .map(|mesh_group| (*mesh_group, None))
+                    .collect()
+            }
+        };
+        let section_y = (section * defaults::SECTION_SIZE) as i32;
+
+        for (idx, block) in voxels.indexed_iter() {
             let mesh_group = blocks::properties(block).mesh_group;
 
             if mesh_group != MeshGroup::None {
                 non_existent.remove(&mesh_group);
 
-                let e: &mut BlockMesh =
-                    block_meshes.entry(mesh_group).or_insert_with(|| BlockMesh {
-                        ..Default::default()
-                    });
+                let e: &mut BlockMesh = block_meshes.entry(mesh_group).or_default();
 
                 let iidx = (idx.0 as i32, idx.1 as i32, idx.2 as i32);
 
-                let tex_ids = blocks::BLOCK_TEX_ID.get(block).unwrap_or_else(|| {
-                    warn!("Block `{:?}` has no texture id", block);
-                    &[0; 6]
-                });
+                let tex_ids = tex_ids(block);
+                if let (MeshGroup::Panel, Some(facing)) = (mesh_group, block.facing()) {
+                    // Panels never fill their cell, hence they are always visible
+                    e.add_panel(iidx, facing, tex_ids[0]);
+                    continue;
+                }
                 for face in Self::FACES.iter() {
-                    let normal: [i32; 3] = match face {
-                        Face::Top => [0, 1, 0],
-                        Face::Bottom => [0, -1, 0],
-                        Face::Front => [-1, 0, 0],
-                        Face::Back => [1, 0, 0],
-                        Face::Left => [0, 0, -1],
-                        Face::Right => [0, 0, 1],
-                    };
-                    // Only add visible faces to the mesh
-                    if blocks::MeshGroup::None
-                        == self
-                            .voxel
-                            .safe_get(iidx.0 + normal[0], iidx.1 + normal[1], iidx.2 + normal[2])
-                            .map_or(MeshGroup::None, |x| blocks::properties(x).mesh_group)
-                    {
-                        e.positions.extend(quad_to_points(iidx, *face).iter());
-
-                        let normal = [normal[0] as f32, normal[1] as f32, normal[2] as f32];
-
-                        e.normals.extend_from_slice(&[normal; 4]);
-                        e.layer.extend_from_slice(&[tex_ids[*face as usize]; 4]);
-
-                        let uv = if [Face::Top, Face::Front, Face::Right].contains(face) {
-                            &[[0.0, 0.0], [1.0, -1.0], [0.0, -1.0], [1.0, 0.0]]
-                        } else {
-                            /*if [Face::Bottom, Face::Back, Face::Left].contains(face)*/
-                            &[[0.0, 1.0], [-1.0, 0.0], [-1.0, 1.0], [0.0, 0.0]]
-                        };
-
-                        e.uvs.extend_from_slice(uv);
-
-                        let c: u32 = e.index_counter;
-                        // First triangle
-                        e.indices.push(c);
-                        e.indices.push(c + 1);
-                        e.indices.push(c + 2);
-                        // Second triangle
-                        e.indices.push(c + 1);
-                        e.indices.push(c);
-                        e.indices.push(c + 3);
-
-                        e.index_counter += 4;
+                    let normal = BlockMesh::normal(*face);
+                    // Only add visible faces to the mesh, i.e., faces not covered by a cube or a
+                    // block of the same group. Neighbours above and below may lie in the adjacent
+                    // sections
+                    let neighbour = self
+                        .safe_get(
+                            iidx.0 + normal[0],
+                            section_y + iidx.1 + normal[1],
+                            iidx.2 + normal[2],
+                        )
+                        .map_or(MeshGroup::None, |x| blocks::properties(&x).mesh_group);
+                    if neighbour != mesh_group && neighbour != MeshGroup::Cube {
+                        e.add_face(iidx, *face, tex_ids[*face as usize]);
                     }
                 }
             }
@@ -266,21 +483,7 @@ impl Meshable for GameChunk {
 
         let mut m: HashMap<MeshGroup, Option<Mesh>> = block_meshes
             .into_iter()
-            .map(|(block_type, mesh_components)| {
-                let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-                mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, mesh_components.positions);
-                mesh.set_attribute(
-                    bevy::prelude::Mesh::ATTRIBUTE_NORMAL,
-                    mesh_components.normals,
-                );
-                mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, mesh_components.uvs);
-                mesh.set_attribute("Vertex_Layer", mesh_components.layer);
-                mesh.set_indices(Some(bevy::render::mesh::Indices::U32(
-                    mesh_components.indices,
-                )));
-
-                (block_type, Some(mesh))
-            })
+            .map(|(block_type, mesh_components)| (block_type, Some(mesh_components.into_mesh())))
             .collect();
 
         non_existent.iter().for_each(|non_existent_block| {
@@ -363,4 +566,31 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn sections() {
+        let mut voxels = VoxelChunk::air([defaults::SECTION_SIZE, 4 * defaults::SECTION_SIZE, 16]);
+        voxels[(3, 20, 5)] = BlockType::Dirt;
+        let mut chunk = GameChunk::from_voxels(IVec2::ZERO, &voxels);
+        assert_eq!(chunk.height(), 4 * defaults::SECTION_SIZE);
+        assert!(chunk.section(0).is_none());
+        assert!(chunk.section(1).is_some());
+        assert_eq!(chunk[(3, 20, 5)], BlockType::Dirt);
+        assert_eq!(chunk.get(UVec3::new(0, 64, 0)), None);
+        let hash = chunk.content_hash();
+
+        // Allocate a section and elide it again
+        assert!(chunk.set(UVec3::new(0, 50, 0), BlockType::Sand));
+        assert!(chunk.section(3).is_some());
+        assert_ne!(chunk.content_hash(), hash);
+        assert!(chunk.set(UVec3::new(0, 50, 0), BlockType::Air));
+        assert!(chunk.section(3).is_none());
+        assert_eq!(chunk.content_hash(), hash);
+        assert!(!chunk.set(UVec3::new(0, 64, 0), BlockType::Sand));
+
+        assert_eq!(
+            GameChunk::empty(IVec2::ZERO, 64).content_hash(),
+            GameChunk::from_voxels(IVec2::ZERO, &VoxelChunk::air([16, 64, 16])).content_hash()
+        );
+    }
 }